@@ -29,11 +29,12 @@
         return withBase(baseUrl, "/files/browser");
     }
 
-    function buildUploadUrl(baseUrl, path) {
-        if (path) {
-            return withBase(baseUrl, `/files/upload?path=${encodeURIComponent(path)}`);
-        }
-        return withBase(baseUrl, "/files/upload");
+    function buildUploadUrl(baseUrl, path, overwrite) {
+        const params = new URLSearchParams();
+        if (path) params.set("path", path);
+        if (overwrite) params.set("overwrite", "true");
+        const query = params.toString();
+        return withBase(baseUrl, query ? `/files/upload?${query}` : "/files/upload");
     }
 
     function buildCreateFolderUrl(baseUrl, path) {
@@ -79,6 +80,11 @@
         let currentPath = initialPath || "";
         const shouldManageHistory = baseUrl === "";
 
+        function csrfToken() {
+            const root = host.querySelector("[data-file-browser-root]");
+            return (root && root.dataset.csrfToken) || "";
+        }
+
         async function loadBrowser(path, options = {}) {
             currentPath = path || "";
             const { updateHistory = false, replaceHistory = false } = options;
@@ -124,15 +130,26 @@
                     `,
                     );
 
-                    fetch(buildUploadUrl(baseUrl, currentPath), {
-                        method: "POST",
-                        body: formData,
-                        credentials: "include",
-                    })
-                        .then((res) => {
+                    const upload = (overwrite) =>
+                        fetch(buildUploadUrl(baseUrl, currentPath, overwrite), {
+                            method: "POST",
+                            body: formData,
+                            headers: {
+                                "X-CSRF-Token": csrfToken(),
+                            },
+                            credentials: "include",
+                        }).then((res) => {
+                            if (res.status === 409 && !overwrite) {
+                                if (window.confirm(`Файл ${file.name} уже существует. Перезаписать?`)) {
+                                    return upload(true);
+                                }
+                                throw new Error("файл уже существует.");
+                            }
                             if (!res.ok) throw new Error(`Не удалось загрузить ${file.name}.`);
                             return res.text();
-                        })
+                        });
+
+                    upload(false)
                         .then(() => {
                             const wrapper = document.getElementById(`${id}_wrapper`);
                             if (wrapper) {
@@ -194,6 +211,7 @@
                     body: formData,
                     headers: {
                         "Content-Type": "application/x-www-form-urlencoded",
+                        "X-CSRF-Token": csrfToken(),
                     },
                     credentials: "include",
                 })
@@ -0,0 +1,133 @@
+//! Client IP extraction that only trusts forwarding headers from known proxies.
+use std::net::IpAddr;
+
+use actix_web::http::header::{self, HeaderMap};
+
+/// Resolve the originating client address.
+///
+/// When the socket peer is one of `trusted_proxies`, the `Forwarded` (or
+/// `X-Forwarded-For`) chain is walked from the nearest hop outwards and the
+/// first address that is not itself a trusted proxy is returned. Headers sent
+/// by any other peer are ignored so clients cannot spoof their address.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+
+    let mut client = peer;
+    for hop in forwarded_hops(headers).into_iter().rev() {
+        client = hop;
+        if !trusted_proxies.contains(&hop) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+/// Addresses listed in the forwarding headers, ordered from the original
+/// client to the nearest proxy. Unparseable entries are dropped.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<IpAddr> {
+    let forwarded: Vec<IpAddr> = headers
+        .get_all(header::FORWARDED)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                if key.eq_ignore_ascii_case("for") {
+                    parse_node(value)
+                } else {
+                    None
+                }
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all(header::X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(parse_node)
+        .collect()
+}
+
+/// Parse a node such as `192.0.2.1`, `192.0.2.1:8080` or `"[2001:db8::1]:443"`.
+fn parse_node(raw: &str) -> Option<IpAddr> {
+    let node = raw.trim().trim_matches('"');
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.rsplit_once(':')?.0.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        map.insert(name, HeaderValue::from_str(value).unwrap());
+        map
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn trusted_proxy_forwards_client_address() {
+        let trusted = [ip("10.0.0.1")];
+        let map = headers(header::X_FORWARDED_FOR, "203.0.113.7, 10.0.0.1");
+
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &map, &trusted),
+            Some(ip("203.0.113.7"))
+        );
+    }
+
+    #[test]
+    fn forwarded_header_is_parsed() {
+        let trusted = [ip("10.0.0.1")];
+        let map = headers(
+            header::FORWARDED,
+            r#"for="[2001:db8::1]:4711";proto=https, for=10.0.0.1"#,
+        );
+
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &map, &trusted),
+            Some(ip("2001:db8::1"))
+        );
+    }
+
+    #[test]
+    fn direct_connection_uses_socket_address() {
+        assert_eq!(
+            client_ip(Some(ip("198.51.100.2")), &HeaderMap::new(), &[]),
+            Some(ip("198.51.100.2"))
+        );
+    }
+
+    #[test]
+    fn spoofed_header_from_untrusted_peer_is_ignored() {
+        let trusted = [ip("10.0.0.1")];
+        let map = headers(header::X_FORWARDED_FOR, "1.2.3.4");
+
+        assert_eq!(
+            client_ip(Some(ip("198.51.100.2")), &map, &trusted),
+            Some(ip("198.51.100.2"))
+        );
+    }
+}
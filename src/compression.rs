@@ -0,0 +1,294 @@
+//! Response compression at the configured `compression_level`.
+//!
+//! Actix's own `Compress` middleware always uses its default encoder settings,
+//! so responses are encoded here with gzip or deflate at the level held in the
+//! app data as [`CompressionLevel`].
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use actix_web::Error;
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::web::{self, Bytes};
+use flate2::Compression;
+use flate2::write::{DeflateEncoder, GzEncoder};
+
+/// Highest accepted `compression_level`.
+pub const MAX_COMPRESSION_LEVEL: u32 = 9;
+
+/// Level [`compress_response`] encodes with, from 1 (fastest) to
+/// [`MAX_COMPRESSION_LEVEL`] (smallest).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CompressionLevel(pub u32);
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    fn header_value(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        })
+    }
+}
+
+/// Pick gzip or deflate from an `Accept-Encoding` value by quality, gzip on
+/// ties. Encodings listed with `q=0` are refused.
+fn preferred_encoding(accept: &str) -> Option<Encoding> {
+    accept
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let encoding = match parts.next()?.trim().to_ascii_lowercase().as_str() {
+                "gzip" | "x-gzip" => Encoding::Gzip,
+                "deflate" => Encoding::Deflate,
+                _ => return None,
+            };
+            let quality = match parts.find_map(|param| param.trim().strip_prefix("q=")) {
+                Some(quality) => quality.trim().parse::<f32>().ok()?,
+                None => 1.0,
+            };
+            (quality > 0.0).then_some((encoding, quality))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map(|(encoding, _)| encoding)
+}
+
+/// Whether encoding `res` is worthwhile and safe: not empty, not a partial
+/// range, not encoded already and not a format that is compressed itself.
+fn should_compress(res: &ServiceResponse<impl MessageBody>) -> bool {
+    let status = res.status();
+    if status.is_informational()
+        || matches!(
+            status,
+            StatusCode::NO_CONTENT | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+        )
+        || res.headers().contains_key(header::CONTENT_ENCODING)
+        || res.response().body().size() == BodySize::Sized(0)
+    {
+        return false;
+    }
+    let content_type = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if content_type.starts_with("image/svg") {
+        return true;
+    }
+    ![
+        "image/",
+        "video/",
+        "audio/",
+        "application/zip",
+        "application/gzip",
+    ]
+    .iter()
+    .any(|prefix| content_type.starts_with(prefix))
+}
+
+/// Encode the response body with the encoding the client prefers, at the
+/// configured [`CompressionLevel`]. Responses for clients accepting neither
+/// gzip nor deflate pass through unchanged.
+pub async fn compress_response(
+    level: web::Data<CompressionLevel>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let encoding = req
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .and_then(preferred_encoding);
+    let res = next.call(req).await?;
+    let Some(encoding) = encoding.filter(|_| should_compress(&res)) else {
+        return Ok(res.map_into_boxed_body());
+    };
+
+    let level = Compression::new(level.0.min(MAX_COMPRESSION_LEVEL));
+    Ok(res.map_body(|head, body| {
+        let headers = head.headers_mut();
+        headers.insert(header::CONTENT_ENCODING, encoding.header_value());
+        headers.remove(header::CONTENT_LENGTH);
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        BoxBody::new(Encoded {
+            body: body.boxed(),
+            encoder: Some(Encoder::new(encoding, level)),
+        })
+    }))
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(DeflateEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding, level: Compression) -> Self {
+        match encoding {
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), level)),
+            Encoding::Deflate => Encoder::Deflate(DeflateEncoder::new(Vec::new(), level)),
+        }
+    }
+
+    /// Feed `chunk` in and take whatever encoded output is ready.
+    fn encode(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        let output = match self {
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.get_mut()
+            }
+            Encoder::Deflate(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.get_mut()
+            }
+        };
+        Ok(std::mem::take(output))
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Deflate(encoder) => encoder.finish(),
+        }
+    }
+}
+
+/// Response body encoded on the fly as the inner body is streamed.
+struct Encoded {
+    body: BoxBody,
+    encoder: Option<Encoder>,
+}
+
+impl MessageBody for Encoded {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = &mut *self;
+        loop {
+            let Some(encoder) = this.encoder.as_mut() else {
+                return Poll::Ready(None);
+            };
+            match ready!(Pin::new(&mut this.body).poll_next(cx)) {
+                Some(Ok(chunk)) => {
+                    let output = encoder.encode(&chunk)?;
+                    if !output.is_empty() {
+                        return Poll::Ready(Some(Ok(Bytes::from(output))));
+                    }
+                }
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => {
+                    let output = this.encoder.take().map(Encoder::finish).transpose()?;
+                    return Poll::Ready(Some(Ok(Bytes::from(output.unwrap_or_default()))));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use actix_web::{App, HttpResponse, middleware, test};
+    use flate2::read::{DeflateDecoder, GzDecoder};
+
+    use super::*;
+
+    #[test]
+    fn preferred_encoding_follows_quality() {
+        assert_eq!(
+            preferred_encoding("gzip, deflate, br"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            preferred_encoding("gzip;q=0.5, deflate"),
+            Some(Encoding::Deflate)
+        );
+        assert_eq!(preferred_encoding("gzip;q=0, br"), None);
+        assert_eq!(preferred_encoding("identity"), None);
+    }
+
+    #[actix_web::test]
+    async fn responses_decode_at_the_configured_level() {
+        for level in [1, MAX_COMPRESSION_LEVEL] {
+            let app = test::init_service(
+                App::new()
+                    .wrap(middleware::from_fn(compress_response))
+                    .app_data(web::Data::new(CompressionLevel(level)))
+                    .route("/", web::get().to(|| async { "hello ".repeat(100) })),
+            )
+            .await;
+
+            let request = |encoding: &str| {
+                test::TestRequest::get()
+                    .uri("/")
+                    .insert_header((header::ACCEPT_ENCODING, encoding))
+                    .to_request()
+            };
+            let res = test::call_service(&app, request("gzip")).await;
+            assert_eq!(res.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+            let body = test::read_body(res).await;
+            let mut decoded = String::new();
+            GzDecoder::new(&body[..])
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, "hello ".repeat(100));
+
+            let res = test::call_service(&app, request("deflate")).await;
+            assert_eq!(
+                res.headers().get(header::CONTENT_ENCODING).unwrap(),
+                "deflate"
+            );
+            let body = test::read_body(res).await;
+            let mut decoded = String::new();
+            DeflateDecoder::new(&body[..])
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, "hello ".repeat(100));
+
+            let res = test::call_service(&app, request("br")).await;
+            assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+            assert_eq!(test::read_body(res).await, "hello ".repeat(100));
+        }
+    }
+
+    #[actix_web::test]
+    async fn compressed_formats_pass_through() {
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware::from_fn(compress_response))
+                .app_data(web::Data::new(CompressionLevel(6)))
+                .route(
+                    "/",
+                    web::get()
+                        .to(|| async { HttpResponse::Ok().content_type("image/png").body("png") }),
+                ),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(test::read_body(res).await, "png");
+    }
+}
@@ -0,0 +1,166 @@
+//! CSRF protection for the session-authenticated routes.
+//!
+//! Every session carries a random token that pages embed for their forms and
+//! scripts. State-changing requests must echo it back either in the
+//! [`CSRF_HEADER`] header or the `csrf_token` query parameter.
+use actix_session::{Session, SessionExt};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode, header};
+use actix_web::middleware::Next;
+use actix_web::{Error, ResponseError, web};
+use serde::Deserialize;
+
+use crate::routes::error::ApiError;
+
+/// Header scripts send the token in.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Session key the token is stored under.
+const SESSION_KEY: &str = "csrf_token";
+
+#[derive(Deserialize)]
+struct CsrfQuery {
+    csrf_token: Option<String>,
+}
+
+/// Token of the current session, generated on first use.
+pub fn csrf_token(session: &Session) -> String {
+    if let Ok(Some(token)) = session.get::<String>(SESSION_KEY) {
+        return token;
+    }
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    if let Err(e) = session.insert(SESSION_KEY, &token) {
+        log::warn!("Failed to store CSRF token in session: {e}");
+    }
+    token
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Compare without short-circuiting on the first differing byte.
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn submitted_token(req: &ServiceRequest) -> Option<String> {
+    if let Some(value) = req.headers().get(CSRF_HEADER) {
+        return value.to_str().ok().map(str::to_string);
+    }
+    web::Query::<CsrfQuery>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.into_inner().csrf_token)
+}
+
+/// Paths exempt from the check: the shared logout form is also rendered by
+/// pages outside this crate that have no token to embed.
+const EXEMPT_PATHS: &[&str] = &["/logout"];
+
+/// Refuse state-changing requests whose token does not match the session.
+/// Requests carrying an `Authorization` header are not cookie-authenticated
+/// and pass through.
+pub async fn verify_csrf(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if is_safe(req.method())
+        || req.headers().contains_key(header::AUTHORIZATION)
+        || EXEMPT_PATHS.contains(&req.path())
+    {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    }
+
+    let expected = req.get_session().get::<String>(SESSION_KEY).ok().flatten();
+    let valid = match (expected, submitted_token(&req)) {
+        (Some(expected), Some(given)) => tokens_match(&expected, &given),
+        _ => false,
+    };
+    if !valid {
+        let error = ApiError::new(
+            StatusCode::FORBIDDEN,
+            "csrf",
+            "Недействительный CSRF-токен. Обновите страницу и повторите попытку.",
+        );
+        return Ok(req.into_response(error.error_response()));
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_boxed_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_session::SessionMiddleware;
+    use actix_session::storage::CookieSessionStore;
+    use actix_web::cookie::Key;
+    use actix_web::{App, HttpResponse, middleware, test};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn mutating_requests_need_the_session_token() {
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware::from_fn(verify_csrf))
+                .wrap(
+                    SessionMiddleware::builder(CookieSessionStore::default(), Key::generate())
+                        .cookie_secure(false)
+                        .build(),
+                )
+                .route(
+                    "/token",
+                    web::get().to(|session: Session| async move {
+                        HttpResponse::Ok().body(csrf_token(&session))
+                    }),
+                )
+                .route("/change", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res =
+            test::call_service(&app, test::TestRequest::get().uri("/token").to_request()).await;
+        let cookie = res.response().cookies().next().unwrap().into_owned();
+        let token = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+
+        let missing = test::TestRequest::post()
+            .uri("/change")
+            .cookie(cookie.clone())
+            .to_request();
+        let res = test::call_service(&app, missing).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let wrong = test::TestRequest::post()
+            .uri("/change")
+            .cookie(cookie.clone())
+            .insert_header((CSRF_HEADER, "nope"))
+            .to_request();
+        let res = test::call_service(&app, wrong).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let header = test::TestRequest::post()
+            .uri("/change")
+            .cookie(cookie.clone())
+            .insert_header((CSRF_HEADER, token.as_str()))
+            .to_request();
+        let res = test::call_service(&app, header).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let query = test::TestRequest::post()
+            .uri(&format!("/change?csrf_token={token}"))
+            .cookie(cookie)
+            .to_request();
+        let res = test::call_service(&app, query).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
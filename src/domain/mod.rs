@@ -1,26 +1,45 @@
 //! Strongly-typed domain structures for file handling.
+use std::ffi::OsStr;
 use std::fmt;
+use std::fs;
 use std::path::{Path, PathBuf};
 
+use serde::Deserialize;
 use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
 
 /// Identifier of a hub owning a storage root.
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct HubId(i32);
 
 impl HubId {
+    /// Wrap `id` without checking it. Use [`HubId::try_new`] for ids coming
+    /// from tokens, requests or the file system.
     pub fn new(id: i32) -> Self {
         Self(id)
     }
 
+    /// Accept only positive ids, so hubs never map to directories such as
+    /// `upload/0` or `upload/-1`.
+    pub fn try_new(id: i32) -> Result<Self, TypeConstraintError> {
+        if id > 0 {
+            Ok(Self(id))
+        } else {
+            Err(TypeConstraintError::InvalidHubId)
+        }
+    }
+
     pub fn value(&self) -> i32 {
         self.0
     }
-}
 
-impl From<i32> for HubId {
-    fn from(value: i32) -> Self {
-        Self::new(value)
+    /// Parse a directory name under the upload root as a hub id. Only the
+    /// canonical decimal form is accepted so `007` or `+7` are not hubs.
+    pub fn try_from_dir_name(name: &str) -> Result<Self, TypeConstraintError> {
+        match name.parse::<i32>() {
+            Ok(id) if id.to_string() == name => Self::try_new(id),
+            _ => Err(TypeConstraintError::InvalidHubId),
+        }
     }
 }
 
@@ -61,15 +80,21 @@ impl From<UploadRoot> for PathBuf {
 pub struct RelativePath(PathBuf);
 
 impl RelativePath {
+    /// Build a clean relative path: `.` and empty segments are dropped so
+    /// `a//b/./c` and `a/b/c` are the same value, while `..` is rejected.
     pub fn try_new(path: PathBuf) -> Result<Self, TypeConstraintError> {
-        if path
-            .components()
-            .any(|c| matches!(c, std::path::Component::ParentDir))
-        {
-            return Err(TypeConstraintError::InvalidPath);
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                std::path::Component::Normal(segment) => normalized.push(segment),
+                std::path::Component::ParentDir => return Err(TypeConstraintError::InvalidPath),
+                std::path::Component::CurDir
+                | std::path::Component::RootDir
+                | std::path::Component::Prefix(_) => {}
+            }
         }
 
-        Ok(Self(path))
+        Ok(Self(normalized))
     }
 
     pub fn try_from_str(input: &str) -> Result<Self, TypeConstraintError> {
@@ -91,19 +116,85 @@ impl RelativePath {
         combined.push(child.as_path());
         RelativePath(combined)
     }
+
+    /// Whether the path lies inside one of the [`RESERVED_DIRS`] used by the
+    /// service itself.
+    pub fn is_reserved(&self) -> bool {
+        match self.0.components().next() {
+            Some(std::path::Component::Normal(first)) => first
+                .to_str()
+                .is_some_and(|name| RESERVED_DIRS.contains(&name)),
+            _ => false,
+        }
+    }
 }
 
+/// Backend-agnostic location of an entry: the hub id followed by the
+/// relative path segments, joined with `/` (e.g. `42/docs/report.pdf`).
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct StorageKey(String);
+
+impl StorageKey {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Segments of the key, starting with the hub id.
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split('/')
+    }
+
+    /// Key of a direct child of this key.
+    pub fn child(&self, name: &FileName) -> StorageKey {
+        StorageKey(format!("{}/{}", self.0, name.as_str()))
+    }
+}
+
+impl std::fmt::Display for StorageKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Top-level hub directories reserved for internal bookkeeping. Normal writes
+/// may never target them.
+pub const RESERVED_DIRS: &[&str] = &[
+    TRASH_DIR,
+    THUMBNAIL_DIR,
+    ".versions",
+    ".blobs",
+    CHUNKED_UPLOAD_DIR,
+];
+
+/// Top-level hub directory holding image thumbnails. It mirrors the hub's
+/// layout: the thumbnail of `docs/a.png` is `.thumbnails/docs/a.png`.
+pub const THUMBNAIL_DIR: &str = ".thumbnails";
+
+/// Top-level hub directory receiving soft-deleted files and folders.
+pub const TRASH_DIR: &str = ".trash";
+
+/// Top-level hub directory holding the parts of unfinished chunked uploads.
+pub const CHUNKED_UPLOAD_DIR: &str = ".uploads";
+
+/// Extensions treated as images unless `image_extensions` is configured.
+pub const DEFAULT_IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp", "svg"];
+
+/// Longest file name, in bytes, most file systems accept.
+pub const MAX_FILE_NAME_BYTES: usize = 255;
+
 /// Sanitized file name (single path component).
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct FileName(String);
 
 impl FileName {
+    /// Accept a single path component, normalized to Unicode NFC so names
+    /// sent in NFD (as macOS does) match the same name typed elsewhere.
     pub fn try_new(value: String) -> Result<Self, TypeConstraintError> {
         let path = Path::new(&value);
         let mut components = path.components();
         match (components.next(), components.next()) {
             (Some(std::path::Component::Normal(component)), None) => {
-                Ok(Self(component.to_string_lossy().to_string()))
+                Ok(Self(component.to_string_lossy().nfc().collect()))
             }
             _ => Err(TypeConstraintError::InvalidFileName),
         }
@@ -113,6 +204,49 @@ impl FileName {
         Self::try_new(value.to_string())
     }
 
+    /// Wrap the name of an existing directory entry as stored, without
+    /// normalizing it, so entries written in NFD keep resolving to themselves.
+    pub fn from_disk(raw: &OsStr) -> Result<Self, TypeConstraintError> {
+        let mut components = Path::new(raw).components();
+        match (components.next(), components.next()) {
+            (Some(std::path::Component::Normal(component)), None) => {
+                Ok(Self(component.to_string_lossy().into_owned()))
+            }
+            _ => Err(TypeConstraintError::InvalidFileName),
+        }
+    }
+
+    /// Rewrite an arbitrary name into one that is safe to serve: control
+    /// characters, separators and characters reserved on Windows become `_`,
+    /// surrounding whitespace and trailing dots are trimmed and the result is
+    /// cut to [`MAX_FILE_NAME_BYTES`].
+    pub fn sanitize(raw: &str) -> Self {
+        let replaced: String = raw
+            .nfc()
+            .map(|c| {
+                if c.is_control()
+                    || matches!(c, '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*')
+                {
+                    '_'
+                } else {
+                    c
+                }
+            })
+            .collect();
+        let mut name = replaced.trim().trim_end_matches('.').to_string();
+        if name.len() > MAX_FILE_NAME_BYTES {
+            let mut end = MAX_FILE_NAME_BYTES;
+            while !name.is_char_boundary(end) {
+                end -= 1;
+            }
+            name.truncate(end);
+        }
+        if name.is_empty() || name == "." || name == ".." {
+            name = "unnamed".to_string();
+        }
+        Self(name)
+    }
+
     pub fn as_str(&self) -> &str {
         &self.0
     }
@@ -125,26 +259,82 @@ impl FileName {
         PathBuf::from(&self.0)
     }
 
-    pub fn is_image(&self) -> bool {
+    /// Lowercased extension, if the name has one.
+    pub fn extension(&self) -> Option<String> {
         Path::new(&self.0)
             .extension()
             .and_then(|ext| ext.to_str())
-            .map(|ext| {
-                matches!(
-                    ext.to_ascii_lowercase().as_str(),
-                    "png" | "jpg" | "jpeg" | "gif" | "webp" | "bmp" | "svg"
-                )
-            })
-            .unwrap_or(false)
+            .map(|ext| ext.to_ascii_lowercase())
+    }
+
+    /// Whether the extension is one of [`DEFAULT_IMAGE_EXTENSIONS`]. The
+    /// service checks its configured set instead.
+    pub fn is_image(&self) -> bool {
+        self.category() == FileCategory::Image
+    }
+
+    /// Dotfiles such as `.DS_Store` are hidden from listings by default.
+    pub fn is_hidden(&self) -> bool {
+        self.0.starts_with('.')
+    }
+
+    /// Broad category derived from the extension, with images taken from
+    /// [`DEFAULT_IMAGE_EXTENSIONS`].
+    pub fn category(&self) -> FileCategory {
+        match self.extension().as_deref() {
+            Some(ext) if DEFAULT_IMAGE_EXTENSIONS.contains(&ext) => FileCategory::Image,
+            Some(
+                "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "odt" | "ods" | "odp"
+                | "txt" | "rtf" | "csv" | "md",
+            ) => FileCategory::Document,
+            Some("mp4" | "mov" | "avi" | "mkv" | "webm" | "wmv" | "flv" | "m4v") => {
+                FileCategory::Video
+            }
+            Some("zip" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "7z" | "rar") => {
+                FileCategory::Archive
+            }
+            _ => FileCategory::Other,
+        }
     }
 }
 
+/// Broad file category used for storage reporting.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum FileCategory {
+    Image,
+    Document,
+    Video,
+    Archive,
+    Other,
+}
+
 impl fmt::Display for FileName {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Display::fmt(&self.0, f)
     }
 }
 
+/// Path of `name` inside `dir`. Incoming names are NFC, but entries written
+/// before names were normalized (or copied in from macOS) may be stored in
+/// NFD; when no entry matches exactly, `dir` is scanned for one whose NFC
+/// form does. ASCII names have a single form and are never scanned for.
+fn locate_entry(dir: PathBuf, name: &FileName) -> PathBuf {
+    let path = dir.join(name.as_str());
+    if name.as_str().is_ascii() || path.symlink_metadata().is_ok() {
+        return path;
+    }
+    let stored = fs::read_dir(&dir).ok().and_then(|entries| {
+        entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name())
+            .find(|raw| {
+                raw.to_str()
+                    .is_some_and(|raw| raw.nfc().eq(name.as_str().nfc()))
+            })
+    });
+    stored.map_or(path, |raw| dir.join(raw))
+}
+
 /// File system entry recorded for a hub's storage.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct StorageEntry {
@@ -181,21 +371,212 @@ pub enum EntryKind {
     File { is_image: bool },
 }
 
+/// Subset of directory entries a caller is interested in.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum EntryFilter {
+    #[default]
+    All,
+    Directories,
+    Images,
+    /// Files with the given extension (compared case-insensitively).
+    Extension(String),
+}
+
+impl EntryFilter {
+    /// Parse a query value such as `images`, `dirs` or `ext:pdf`. Unknown
+    /// values fall back to [`EntryFilter::All`].
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "dirs" | "directories" | "folders" => Self::Directories,
+            "images" => Self::Images,
+            other => match other
+                .strip_prefix("ext:")
+                .or_else(|| other.strip_prefix('.'))
+            {
+                Some(ext) if !ext.is_empty() => Self::Extension(ext.to_string()),
+                _ => Self::All,
+            },
+        }
+    }
+
+    pub fn matches(&self, entry: &StorageEntry) -> bool {
+        match self {
+            Self::All => true,
+            Self::Directories => entry.is_directory(),
+            Self::Images => entry.is_image(),
+            Self::Extension(ext) => {
+                !entry.is_directory()
+                    && entry.name().extension().as_deref()
+                        == Some(ext.to_ascii_lowercase().as_str())
+            }
+        }
+    }
+}
+
+/// Key a folder listing can be ordered by.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SortKey {
+    Name,
+    /// Name with embedded numbers compared by value, see [`natural_cmp`].
+    Natural,
+    Size,
+    Modified,
+}
+
+/// Compare names ignoring case, with runs of digits compared as numbers so
+/// `img2.png` comes before `img10.png`. Equal numbers with more leading
+/// zeros sort last.
+pub fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let (mut a_chars, mut b_chars) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let order = match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x_digits = take_digits(&mut a_chars);
+                let y_digits = take_digits(&mut b_chars);
+                let (x_value, y_value) = (
+                    x_digits.trim_start_matches('0'),
+                    y_digits.trim_start_matches('0'),
+                );
+                x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x_digits.len().cmp(&y_digits.len()))
+            }
+            (Some(x), Some(y)) => {
+                a_chars.next();
+                b_chars.next();
+                x.to_lowercase().cmp(y.to_lowercase())
+            }
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits
+}
+
+/// Order of a folder listing. Folders always come before files.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ListingSort {
+    /// Folders by name, then files newest first.
+    #[default]
+    Default,
+    By {
+        key: SortKey,
+        descending: bool,
+    },
+}
+
+impl ListingSort {
+    /// Parse the `sort` (`name`, `natural`, `size`, `modified`) and `dir`
+    /// (`asc`, `desc`) query values. Unknown keys fall back to
+    /// [`ListingSort::Default`] and unknown directions to ascending.
+    pub fn parse(sort: Option<&str>, dir: Option<&str>) -> Self {
+        let key = match sort.map(|s| s.trim().to_ascii_lowercase()).as_deref() {
+            Some("name") => SortKey::Name,
+            Some("natural") => SortKey::Natural,
+            Some("size") => SortKey::Size,
+            Some("modified") => SortKey::Modified,
+            _ => return Self::Default,
+        };
+        let descending = dir.is_some_and(|d| d.trim().eq_ignore_ascii_case("desc"));
+        Self::By { key, descending }
+    }
+}
+
+/// What to do when a write targets a name that already exists.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Keep both files by saving the upload as `name (1).ext`, counting up
+    /// until a free name is found. Other writes replace the existing file.
+    #[default]
+    Rename,
+    /// Replace the existing file.
+    Overwrite,
+    /// Refuse the write unless the caller explicitly confirms an overwrite.
+    Reject,
+}
+
+/// Characters accepted in new folder names.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FolderNameCharset {
+    /// Anything a valid path component may contain.
+    #[default]
+    Any,
+    /// ASCII letters and digits only.
+    Alphanumeric,
+    /// ASCII letters, digits, `-` and `_`.
+    AlphanumericDash,
+}
+
+impl FolderNameCharset {
+    /// Whether every character of a single folder name is allowed.
+    pub fn allows(&self, name: &str) -> bool {
+        match self {
+            FolderNameCharset::Any => true,
+            FolderNameCharset::Alphanumeric => name.chars().all(|c| c.is_ascii_alphanumeric()),
+            FolderNameCharset::AlphanumericDash => name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+        }
+    }
+}
+
 /// Hub-scoped access to storage paths.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct HubStorage {
-    root: UploadRoot,
+    hub_root: PathBuf,
     hub_id: HubId,
+    allow_symlinked_root: bool,
 }
 
 impl HubStorage {
+    /// Storage of a hub living in its own directory below the shared upload
+    /// root (root + hub id).
     pub fn new(root: UploadRoot, hub_id: HubId) -> Self {
-        Self { root, hub_id }
+        let hub_root = root.as_path().join(hub_id.to_string());
+        Self::with_hub_root(hub_root, hub_id)
+    }
+
+    /// Storage of a hub whose files live directly in `hub_root`, e.g. a hub
+    /// moved to another mount.
+    pub fn with_hub_root(hub_root: PathBuf, hub_id: HubId) -> Self {
+        Self {
+            hub_root,
+            hub_id,
+            allow_symlinked_root: false,
+        }
+    }
+
+    /// Treat a symlinked hub root's target as the storage boundary instead of
+    /// rejecting every path below it.
+    pub fn with_symlinked_root(mut self, allowed: bool) -> Self {
+        self.allow_symlinked_root = allowed;
+        self
     }
 
-    /// Absolute path to the hub root (root + hub id).
+    pub fn hub_id(&self) -> &HubId {
+        &self.hub_id
+    }
+
+    /// Absolute path to the hub root.
     pub fn hub_root(&self) -> PathBuf {
-        self.root.as_path().join(self.hub_id.to_string())
+        self.hub_root.clone()
     }
 
     /// Resolve a relative path within the hub root.
@@ -203,11 +584,112 @@ impl HubStorage {
         self.hub_root().join(relative.as_path())
     }
 
-    /// Resolve a file name inside the given relative path.
+    /// Storage key of a folder of the hub.
+    pub fn dir_key(&self, relative: &RelativePath) -> StorageKey {
+        let mut key = self.hub_id.to_string();
+        for segment in relative.as_path().components() {
+            key.push('/');
+            key.push_str(&segment.as_os_str().to_string_lossy());
+        }
+        StorageKey(key)
+    }
+
+    /// Storage key of a file inside the given relative path.
+    pub fn file_key(&self, relative: &RelativePath, name: &FileName) -> StorageKey {
+        self.dir_key(relative).child(name)
+    }
+
+    /// Resolve a file name inside the given relative path, also finding an
+    /// entry stored in another Unicode normalization form.
+    pub fn resolve_file(&self, relative: &RelativePath, name: &FileName) -> PathBuf {
+        locate_entry(self.resolve_dir(relative), name)
+    }
+
+    /// Check whether an absolute path stays inside the hub root once symlinks
+    /// are resolved. Paths that do not exist yet are checked through their
+    /// nearest existing ancestor. A symlinked hub root is only accepted when
+    /// allowed via [`HubStorage::with_symlinked_root`].
+    pub fn contains(&self, absolute: &Path) -> bool {
+        let hub_root = self.hub_root();
+        let is_symlink = hub_root
+            .symlink_metadata()
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+        if is_symlink && !self.allow_symlinked_root {
+            return false;
+        }
+
+        let Ok(root) = hub_root.canonicalize() else {
+            return false;
+        };
+
+        canonicalize_existing_prefix(absolute)
+            .map(|candidate| candidate.starts_with(&root))
+            .unwrap_or(false)
+    }
+}
+
+/// Read-only library shared by every hub, rooted outside the per-hub storage.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SharedStorage {
+    root: PathBuf,
+}
+
+impl SharedStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve a relative path within the shared root.
+    pub fn resolve_dir(&self, relative: &RelativePath) -> PathBuf {
+        self.root.join(relative.as_path())
+    }
+
+    /// Resolve a file name inside the given relative path, also finding an
+    /// entry stored in another Unicode normalization form.
     pub fn resolve_file(&self, relative: &RelativePath, name: &FileName) -> PathBuf {
-        let mut path = self.resolve_dir(relative);
-        path.push(name.as_str());
-        path
+        locate_entry(self.resolve_dir(relative), name)
+    }
+
+    /// Check whether an absolute path stays inside the shared root once
+    /// symlinks are resolved.
+    pub fn contains(&self, absolute: &Path) -> bool {
+        let Ok(root) = self.root.canonicalize() else {
+            return false;
+        };
+        canonicalize_existing_prefix(absolute)
+            .map(|candidate| candidate.starts_with(&root))
+            .unwrap_or(false)
+    }
+}
+
+/// Canonicalize the longest existing prefix of `path` and append the remaining
+/// components verbatim. Returns `None` for dangling symlinks or when the
+/// missing tail cannot be expressed as plain file names.
+fn canonicalize_existing_prefix(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut tail = Vec::new();
+
+    loop {
+        match existing.canonicalize() {
+            Ok(mut resolved) => {
+                for component in tail.iter().rev() {
+                    resolved.push(component);
+                }
+                return Some(resolved);
+            }
+            Err(_) => {
+                if existing.symlink_metadata().is_ok() {
+                    return None;
+                }
+                tail.push(existing.file_name()?.to_os_string());
+                existing = existing.parent()?;
+            }
+        }
     }
 }
 
@@ -215,9 +697,29 @@ impl HubStorage {
 mod tests {
     use super::*;
 
+    #[test]
+    fn hub_id_parses_numeric_dir_names() {
+        assert_eq!(HubId::try_from_dir_name("42").unwrap(), HubId::new(42));
+        assert!(HubId::try_from_dir_name("lost+found").is_err());
+        assert!(HubId::try_from_dir_name("007").is_err());
+        assert!(HubId::try_from_dir_name("").is_err());
+        assert!(HubId::try_from_dir_name("0").is_err());
+        assert!(HubId::try_from_dir_name("-1").is_err());
+    }
+
+    #[test]
+    fn hub_id_must_be_positive() {
+        assert_eq!(HubId::try_new(1).unwrap().value(), 1);
+        assert!(matches!(
+            HubId::try_new(0),
+            Err(TypeConstraintError::InvalidHubId)
+        ));
+        assert!(HubId::try_new(-5).is_err());
+    }
+
     #[test]
     fn hub_storage_resolves_paths() {
-        let storage = HubStorage::new(UploadRoot::from(PathBuf::from("upload")), HubId::from(7));
+        let storage = HubStorage::new(UploadRoot::from(PathBuf::from("upload")), HubId::new(7));
 
         let relative = RelativePath::try_new(PathBuf::from("nested/path")).unwrap();
         let file_name = FileName::try_new("file.txt".to_string()).unwrap();
@@ -226,6 +728,11 @@ mod tests {
 
         assert_eq!(dir, PathBuf::from("upload/7/nested/path"));
         assert_eq!(file, PathBuf::from("upload/7/nested/path/file.txt"));
+        assert_eq!(storage.dir_key(&RelativePath::root()).as_str(), "7");
+        assert_eq!(
+            storage.file_key(&relative, &file_name).as_str(),
+            "7/nested/path/file.txt"
+        );
     }
 
     #[test]
@@ -246,6 +753,150 @@ mod tests {
         assert!(!txt.is_image());
     }
 
+    #[test]
+    fn file_name_categorizes_by_extension() {
+        let category = |name: &str| FileName::try_from_str(name).unwrap().category();
+
+        assert_eq!(category("photo.JPG"), FileCategory::Image);
+        assert_eq!(category("report.pdf"), FileCategory::Document);
+        assert_eq!(category("clip.mp4"), FileCategory::Video);
+        assert_eq!(category("backup.tar.gz"), FileCategory::Archive);
+        assert_eq!(category("README"), FileCategory::Other);
+    }
+
+    #[test]
+    fn sanitize_rewrites_unsafe_names() {
+        assert_eq!(FileName::sanitize("report.pdf").as_str(), "report.pdf");
+        assert_eq!(
+            FileName::sanitize(" bad\tname?.txt. ").as_str(),
+            "bad_name_.txt"
+        );
+        assert_eq!(FileName::sanitize("a\\b").as_str(), "a_b");
+        assert_eq!(FileName::sanitize("...").as_str(), "unnamed");
+        let long = "я".repeat(200);
+        assert!(FileName::sanitize(&long).as_str().len() <= MAX_FILE_NAME_BYTES);
+    }
+
+    #[test]
+    fn reserved_dirs_detected_by_first_component() {
+        assert!(RelativePath::try_from_str(".trash").unwrap().is_reserved());
+        assert!(
+            RelativePath::try_from_str("/.versions/a")
+                .unwrap()
+                .is_reserved()
+        );
+        assert!(
+            !RelativePath::try_from_str("docs/.trash")
+                .unwrap()
+                .is_reserved()
+        );
+        assert!(!RelativePath::try_from_str(".config").unwrap().is_reserved());
+        assert!(!RelativePath::root().is_reserved());
+    }
+
+    #[test]
+    fn natural_cmp_orders_embedded_numbers_by_value() {
+        let mut names = vec![
+            "img10.png",
+            "IMG2.png",
+            "img1.png",
+            "img02.png",
+            "img.png",
+            "img1b.png",
+            "img1a.png",
+            "scan 100 final.pdf",
+            "scan 20 final.pdf",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec![
+                "img.png",
+                "img1.png",
+                "img1a.png",
+                "img1b.png",
+                "IMG2.png",
+                "img02.png",
+                "img10.png",
+                "scan 20 final.pdf",
+                "scan 100 final.pdf",
+            ]
+        );
+        assert_eq!(
+            ListingSort::parse(Some("natural"), None),
+            ListingSort::By {
+                key: SortKey::Natural,
+                descending: false
+            }
+        );
+    }
+
+    #[test]
+    fn listing_sort_parses_with_fallbacks() {
+        assert_eq!(ListingSort::parse(None, None), ListingSort::Default);
+        assert_eq!(
+            ListingSort::parse(Some("bogus"), Some("desc")),
+            ListingSort::Default
+        );
+        assert_eq!(
+            ListingSort::parse(Some("Size"), Some("DESC")),
+            ListingSort::By {
+                key: SortKey::Size,
+                descending: true
+            }
+        );
+        assert_eq!(
+            ListingSort::parse(Some("modified"), Some("sideways")),
+            ListingSort::By {
+                key: SortKey::Modified,
+                descending: false
+            }
+        );
+    }
+
+    #[test]
+    fn entry_filter_parses_and_matches() {
+        let dir = StorageEntry::new(
+            FileName::try_from_str("docs").unwrap(),
+            EntryKind::Directory,
+        );
+        let image = StorageEntry::new(
+            FileName::try_from_str("cat.PNG").unwrap(),
+            EntryKind::File { is_image: true },
+        );
+        let pdf = StorageEntry::new(
+            FileName::try_from_str("report.pdf").unwrap(),
+            EntryKind::File { is_image: false },
+        );
+
+        assert_eq!(EntryFilter::parse("bogus"), EntryFilter::All);
+        assert!(EntryFilter::parse("dirs").matches(&dir));
+        assert!(!EntryFilter::parse("dirs").matches(&pdf));
+        assert!(EntryFilter::parse("images").matches(&image));
+        assert!(EntryFilter::parse("ext:PDF").matches(&pdf));
+        assert!(!EntryFilter::parse("ext:pdf").matches(&image));
+    }
+
+    #[test]
+    fn file_name_detects_hidden() {
+        let hidden = |name: &str| FileName::try_from_str(name).unwrap().is_hidden();
+
+        assert!(hidden(".DS_Store"));
+        assert!(hidden(".trash"));
+        assert!(!hidden("report.pdf"));
+        assert!(!hidden("notes.d"));
+    }
+
+    #[test]
+    fn file_name_normalizes_to_nfc() {
+        let composed = FileName::try_from_str("caf\u{e9}.png").unwrap();
+        let decomposed = FileName::try_from_str("cafe\u{301}.png").unwrap();
+
+        assert_eq!(composed, decomposed);
+        assert_eq!(decomposed.as_str().as_bytes(), "caf\u{e9}.png".as_bytes());
+        assert_eq!(FileName::sanitize("cafe\u{301}.png"), composed);
+    }
+
     #[test]
     fn file_name_rejects_nested() {
         assert!(FileName::try_new("foo/bar.txt".to_string()).is_err());
@@ -255,6 +906,104 @@ mod tests {
     #[test]
     fn relative_path_rejects_parent() {
         assert!(RelativePath::try_new(PathBuf::from("../foo")).is_err());
+        assert!(RelativePath::try_from_str("a/./../b").is_err());
+    }
+
+    #[test]
+    fn relative_path_collapses_noise_segments() {
+        let noisy = RelativePath::try_from_str("a//b/./c").unwrap();
+        assert_eq!(noisy.as_path().to_str(), Some("a/b/c"));
+        assert_eq!(
+            noisy,
+            RelativePath::try_new(PathBuf::from("a/b/c")).unwrap()
+        );
+        assert_eq!(
+            RelativePath::try_from_str("./").unwrap(),
+            RelativePath::root()
+        );
+    }
+
+    #[test]
+    fn hub_storage_contains_paths_inside_hub() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = HubStorage::new(UploadRoot::from(dir.path().to_path_buf()), HubId::new(7));
+        std::fs::create_dir_all(storage.hub_root().join("nested")).unwrap();
+
+        assert!(storage.contains(&storage.hub_root().join("nested")));
+        assert!(storage.contains(&storage.hub_root().join("nested/missing.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hub_storage_rejects_symlink_outside_hub() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let storage = HubStorage::new(UploadRoot::from(dir.path().to_path_buf()), HubId::new(7));
+        std::fs::create_dir_all(storage.hub_root()).unwrap();
+        let link = storage.hub_root().join("escape");
+        std::os::unix::fs::symlink(outside.path(), &link).unwrap();
+
+        assert!(!storage.contains(&link));
+        assert!(!storage.contains(&link.join("file.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hub_storage_symlinked_root_requires_opt_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let volume = tempfile::tempdir().unwrap();
+        let target = volume.path().join("hub7");
+        std::fs::create_dir_all(target.join("docs")).unwrap();
+        std::fs::create_dir_all(volume.path().join("other")).unwrap();
+        std::os::unix::fs::symlink(&target, dir.path().join("7")).unwrap();
+
+        let strict = HubStorage::new(UploadRoot::from(dir.path().to_path_buf()), HubId::new(7));
+        assert!(!strict.contains(&strict.hub_root().join("docs")));
+
+        let relaxed = strict.clone().with_symlinked_root(true);
+        assert!(relaxed.contains(&relaxed.hub_root().join("docs")));
+        assert!(relaxed.contains(&relaxed.hub_root().join("docs/new.txt")));
+
+        // A link inside the target pointing above it must still be rejected.
+        std::os::unix::fs::symlink(volume.path().join("other"), target.join("up")).unwrap();
+        assert!(!relaxed.contains(&relaxed.hub_root().join("up")));
+    }
+
+    #[test]
+    fn hub_storage_rejects_sibling_hub() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = HubStorage::new(UploadRoot::from(dir.path().to_path_buf()), HubId::new(7));
+        std::fs::create_dir_all(storage.hub_root()).unwrap();
+        std::fs::create_dir_all(dir.path().join("8")).unwrap();
+        std::fs::create_dir_all(dir.path().join("70")).unwrap();
+
+        assert!(!storage.contains(&dir.path().join("8")));
+        assert!(!storage.contains(&dir.path().join("70/file.txt")));
+    }
+
+    #[test]
+    fn hub_storage_with_hub_root_resolves_below_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let mount = dir.path().join("mount");
+        let storage = HubStorage::with_hub_root(mount.clone(), HubId::new(7));
+        std::fs::create_dir_all(mount.join("docs")).unwrap();
+        std::fs::create_dir_all(dir.path().join("7")).unwrap();
+
+        let relative = RelativePath::try_from_str("docs").unwrap();
+        assert_eq!(storage.resolve_dir(&relative), mount.join("docs"));
+        assert_eq!(storage.dir_key(&relative).as_str(), "7/docs");
+        assert!(storage.contains(&mount.join("docs/new.txt")));
+        assert!(!storage.contains(&dir.path().join("7")));
+    }
+
+    #[test]
+    fn shared_storage_contains_only_its_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = SharedStorage::new(dir.path().join("shared"));
+        std::fs::create_dir_all(shared.root().join("templates")).unwrap();
+
+        assert!(shared.contains(&shared.root().join("templates/a.docx")));
+        assert!(!shared.contains(&dir.path().join("7")));
     }
 }
 
@@ -264,4 +1013,6 @@ pub enum TypeConstraintError {
     InvalidPath,
     #[error("invalid file name")]
     InvalidFileName,
+    #[error("invalid hub id")]
+    InvalidHubId,
 }
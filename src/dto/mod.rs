@@ -1,11 +1,26 @@
 use serde::Serialize;
 
+use crate::domain::FileCategory;
+
 /// Serializable entry for template rendering.
 #[derive(Clone, Debug, Serialize)]
 pub struct FileEntryDto {
     pub name: String,
     pub is_directory: bool,
     pub is_image: bool,
+    /// URL of an image inside the folder to show as its icon, when folder
+    /// previews are enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder_preview_image: Option<String>,
+    /// Folder holding the entry relative to the hub root, set when entries
+    /// from several folders are listed together (search results).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// MIME type of files, guessed from the extension; unset for folders.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// How often the file was downloaded through `/files/download`.
+    pub downloads: u64,
 }
 
 impl From<crate::domain::StorageEntry> for FileEntryDto {
@@ -18,6 +33,314 @@ impl From<crate::domain::StorageEntry> for FileEntryDto {
             name,
             is_directory,
             is_image,
+            folder_preview_image: None,
+            path: None,
+            content_type: None,
+            downloads: 0,
+        }
+    }
+}
+
+/// Field names of [`FileEntryDto`] a JSON listing can be projected onto.
+pub const FILE_ENTRY_FIELDS: &[&str] = &[
+    "name",
+    "is_directory",
+    "is_image",
+    "folder_preview_image",
+    "path",
+    "content_type",
+    "downloads",
+];
+
+/// Subset of [`FILE_ENTRY_FIELDS`] requested via `fields=name,is_directory`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FieldProjection(Vec<String>);
+
+impl FieldProjection {
+    /// Parse a comma separated field list, returning the first unknown name
+    /// as the error.
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let mut fields = Vec::new();
+        for field in raw.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            if !FILE_ENTRY_FIELDS.contains(&field) {
+                return Err(field.to_string());
+            }
+            if !fields.iter().any(|known| known == field) {
+                fields.push(field.to_string());
+            }
         }
+        Ok(Self(fields))
     }
+
+    /// Serialize the entries keeping only the requested keys.
+    pub fn apply(&self, entries: &[FileEntryDto]) -> Vec<serde_json::Value> {
+        entries
+            .iter()
+            .map(|entry| {
+                let mut object = match serde_json::to_value(entry) {
+                    Ok(serde_json::Value::Object(object)) => object,
+                    _ => serde_json::Map::new(),
+                };
+                object.retain(|key, _| self.0.iter().any(|field| field == key));
+                serde_json::Value::Object(object)
+            })
+            .collect()
+    }
+}
+
+/// Total size and number of files in a category.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct CategoryUsageDto {
+    pub bytes: u64,
+    pub count: u64,
+}
+
+/// Hub storage usage grouped by file category.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct StorageBreakdownDto {
+    pub image: CategoryUsageDto,
+    pub document: CategoryUsageDto,
+    pub video: CategoryUsageDto,
+    pub archive: CategoryUsageDto,
+    pub other: CategoryUsageDto,
+}
+
+impl StorageBreakdownDto {
+    /// Account a file of `bytes` size under `category`.
+    pub fn record(&mut self, category: FileCategory, bytes: u64) {
+        let bucket = match category {
+            FileCategory::Image => &mut self.image,
+            FileCategory::Document => &mut self.document,
+            FileCategory::Video => &mut self.video,
+            FileCategory::Archive => &mut self.archive,
+            FileCategory::Other => &mut self.other,
+        };
+        bucket.bytes += bytes;
+        bucket.count += 1;
+    }
+}
+
+/// Maximum directory nesting below a folder.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct FolderDepthDto {
+    /// Deepest directory level found (0 when there are no subfolders).
+    pub depth: usize,
+    /// Whether the traversal stopped at the configured cap.
+    pub capped: bool,
+}
+
+/// Folder or file of the hub tree returned by `/api/tree`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct TreeNodeDto {
+    pub name: String,
+    pub is_directory: bool,
+    /// Entries of a folder, folders first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeNodeDto>,
+    /// Whether entries of this folder lie beyond the depth cap and were left
+    /// out.
+    pub truncated: bool,
+}
+
+/// Number of entries per kind in a folder.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct EntryCountsDto {
+    pub files: usize,
+    pub directories: usize,
+    pub images: usize,
+}
+
+/// Outcome of repairing a hub's thumbnail cache.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ThumbnailRepairDto {
+    /// Thumbnails removed because their source is gone.
+    pub deleted: usize,
+    /// Thumbnails rebuilt because their source changed.
+    pub regenerated: usize,
+}
+
+/// Outcome of renaming a single file during a bulk rename.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct RenameResultDto {
+    pub from: String,
+    pub to: String,
+    pub renamed: bool,
+    /// Why the file was left untouched, if it was.
+    pub error: Option<String>,
+}
+
+/// Outcome of moving a single file during a bulk move.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct MoveResultDto {
+    pub name: String,
+    /// Name in the destination folder; differs from `name` when the file was
+    /// renamed to avoid a conflict.
+    pub to: String,
+    pub moved: bool,
+    /// Why the file was left in place, if it was.
+    pub error: Option<String>,
+}
+
+/// Outcome of deleting a single file during a bulk delete.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct DeleteResultDto {
+    pub name: String,
+    pub deleted: bool,
+    /// Why the file was kept, if it was.
+    pub error: Option<String>,
+}
+
+/// Result of publishing a staging folder.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct PublishResultDto {
+    /// Name the previous live folder was moved to, if there was one.
+    pub backup: Option<String>,
+}
+
+/// A byte range of a file together with its SHA-256 digest.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ChunkDto {
+    pub index: usize,
+    pub offset: u64,
+    pub length: u64,
+    /// Lowercase hex digest of the chunk bytes.
+    pub sha256: String,
+}
+
+/// Chunk layout of a file for verified, resumable range downloads.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ChunkManifestDto {
+    pub size: u64,
+    pub chunk_size: u64,
+    pub chunks: Vec<ChunkDto>,
+}
+
+/// Everything known about a single file. Features that are not enabled for
+/// the deployment are left out of the serialized object.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct FileInfoDto {
+    pub name: String,
+    pub size: u64,
+    pub is_image: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extension: Option<String>,
+    pub content_type: String,
+    /// Last modification time in seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// Creation time in seconds since the Unix epoch, where supported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+}
+
+/// Listing entry extended with the details shown for a single selected
+/// file or folder.
+#[derive(Clone, Debug, Serialize)]
+pub struct EntryStatDto {
+    #[serde(flatten)]
+    pub entry: FileEntryDto,
+    /// Size in bytes; unset for folders.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Last modification time in seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// Pixel width of images whose header could be read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    /// Pixel height of images whose header could be read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+/// Line of a streamed batch upload, sent once per file.
+#[derive(Clone, Debug, Serialize)]
+pub struct UploadProgressDto {
+    /// Stored name on success, the name sent by the client otherwise.
+    pub name: String,
+    /// Files of the batch handled so far, this one included.
+    pub done: usize,
+    pub stored: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Stable identifier of the failure, as in JSON error bodies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
+}
+
+/// Container metadata of a video used by the UI player for seeking.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MediaInfoDto {
+    /// Major brand of the container, e.g. `isom` or `qt  `.
+    pub container: String,
+    pub duration_secs: f64,
+    pub tracks: Vec<MediaTrackDto>,
+}
+
+/// A single audio, video or subtitle track of a media file.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct MediaTrackDto {
+    pub track_type: Option<String>,
+    pub codec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u16>,
+}
+
+/// Upload limits in effect for the current hub.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct UploadLimitsDto {
+    pub max_upload_bytes: u64,
+}
+
+/// Folder listing returned by the JSON API.
+#[derive(Clone, Debug, Serialize)]
+pub struct FileListingDto {
+    pub path: String,
+    pub entries: Vec<FileEntryDto>,
+}
+
+/// One link of the breadcrumb trail above a listing.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct BreadcrumbDto {
+    pub name: String,
+    pub href: String,
+}
+
+/// Storage used by the current hub against its quota.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct HubUsageDto {
+    pub used_bytes: u64,
+    pub quota_bytes: u64,
+}
+
+/// A single entry of an inspected archive.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ArchiveEntryDto {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub is_directory: bool,
+    /// Why extracting this entry would be refused, if it would be.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub problem: Option<String>,
+}
+
+/// Safety report for an archive, built from its headers only.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ArchiveReportDto {
+    pub entries: Vec<ArchiveEntryDto>,
+    pub total_size: u64,
+    pub total_compressed_size: u64,
+    /// Uncompressed to compressed size; very high values suggest a zip bomb.
+    pub compression_ratio: f64,
+    pub possible_zip_bomb: bool,
+}
+
+/// Files written while extracting an uploaded archive.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ExtractResultDto {
+    /// Stored files relative to the target folder, under their final names.
+    pub files: Vec<String>,
 }
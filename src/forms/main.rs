@@ -1,13 +1,31 @@
-use actix_multipart::form::{MultipartForm, tempfile::TempFile};
+use actix_multipart::form::{MultipartForm, tempfile::TempFile, text::Text};
 use serde::Deserialize;
 use validator::Validate;
 
 /// Form representing a single file upload.
 #[derive(MultipartForm)]
 pub struct UploadFileForm {
-    /// Uploaded file with a 10MB limit.
-    #[multipart(limit = "10MB")]
+    /// Uploaded file. Its size is bounded by the multipart config and the
+    /// hub's upload limit in `FileService`.
     pub file: TempFile,
+    /// Unpack a `.zip` upload into the target folder instead of storing it.
+    pub extract: Option<Text<bool>>,
+}
+
+/// Form data finishing a chunked upload.
+#[derive(Deserialize)]
+pub struct CompleteUploadForm {
+    /// UUID the parts were uploaded under.
+    pub upload_id: String,
+    /// Name of the stored file.
+    pub name: String,
+}
+
+/// Form data for creating an empty file.
+#[derive(Deserialize)]
+pub struct CreateFileForm {
+    /// Name of the new file inside the current folder.
+    pub name: String,
 }
 
 /// Form data for creating a new folder.
@@ -16,4 +34,108 @@ pub struct CreateFolderForm {
     /// Name of the folder to create. Must be at least one character long.
     #[validate(length(min = 1))]
     pub name: String,
+    /// Treat an already existing folder as success instead of a conflict.
+    #[serde(default = "default_exist_ok")]
+    pub exist_ok: bool,
+}
+
+fn default_exist_ok() -> bool {
+    true
+}
+
+/// JSON payload for publishing a staging folder over a live one.
+#[derive(Deserialize)]
+pub struct PublishForm {
+    /// Folder containing both `staging` and `target`.
+    pub path: Option<String>,
+    /// Folder with the prepared contents, e.g. `staging`.
+    pub staging: String,
+    /// Live folder replaced by the staging contents, e.g. `public`.
+    pub target: String,
+}
+
+/// Form data for renaming a file or folder in place.
+#[derive(Deserialize)]
+pub struct RenameForm {
+    /// Folder containing the entry.
+    pub path: Option<String>,
+    pub from: String,
+    pub to: String,
+}
+
+/// Form data for moving or copying a file or folder into another folder.
+#[derive(Deserialize)]
+pub struct MoveEntryForm {
+    pub from_path: Option<String>,
+    pub to_path: Option<String>,
+    pub name: String,
+}
+
+/// Form data naming a file to delete from the current folder.
+#[derive(Deserialize)]
+pub struct DeleteFileForm {
+    pub name: String,
+}
+
+/// Form data naming a folder to delete from the current folder.
+#[derive(Deserialize)]
+pub struct DeleteFolderForm {
+    pub name: String,
+    /// Delete the folder together with its contents.
+    #[serde(default, deserialize_with = "deserialize_checkbox")]
+    pub recursive: bool,
+}
+
+/// Accept HTML checkbox values (`on`) as well as `true`/`1`.
+fn deserialize_checkbox<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    Ok(matches!(value.as_str(), "on" | "true" | "1"))
+}
+
+/// JSON payload for saving text content as a file.
+#[derive(Deserialize)]
+pub struct SaveTextForm {
+    /// UTF-8 content written to the target file.
+    pub content: String,
+}
+
+/// JSON payload for moving every file of a folder that matches a filter.
+#[derive(Deserialize)]
+pub struct MoveMatchingForm {
+    /// Folder whose files are considered (non-recursive).
+    pub from_path: Option<String>,
+    /// Destination folder, created when missing.
+    pub to_path: Option<String>,
+    /// Glob matched against each file name, e.g. `*.log`.
+    pub glob: Option<String>,
+    /// Only move files last modified before this Unix timestamp (seconds).
+    pub older_than: Option<u64>,
+}
+
+/// JSON payload naming two files to exchange.
+#[derive(Deserialize)]
+pub struct SwapEntriesForm {
+    pub name_a: String,
+    pub name_b: String,
+}
+
+/// JSON payload restoring an entry of the trash.
+#[derive(Deserialize)]
+pub struct RestoreForm {
+    /// Path inside `.trash`, starting with the timestamp folder.
+    pub path: String,
+}
+
+/// JSON payload for renaming files in a folder with a regex.
+#[derive(Deserialize)]
+pub struct RenamePatternForm {
+    /// Folder whose files are renamed (non-recursive).
+    pub path: Option<String>,
+    /// Regular expression matched against each file name.
+    pub find: String,
+    /// Replacement template, may reference capture groups (`$1`).
+    pub replace: String,
 }
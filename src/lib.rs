@@ -1,29 +1,95 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
 use actix_cors::Cors;
 use actix_files::Files;
 use actix_identity::IdentityMiddleware;
+use actix_multipart::MultipartError;
+use actix_multipart::form::MultipartFormConfig;
 use actix_session::{SessionMiddleware, storage::CookieSessionStore};
 use actix_web::cookie::Key;
-use actix_web::{App, HttpServer, middleware, web};
+use actix_web::error::{InternalError, PayloadError};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, middleware, web};
 use actix_web_flash_messages::{FlashMessagesFramework, storage::CookieMessageStore};
 use pushkind_common::middleware::RedirectUnauthorized;
 use pushkind_common::models::config::CommonServerConfig;
 use pushkind_common::routes::{logout, not_assigned};
 use tera::Tera;
 
+use crate::client_ip::client_ip;
+use crate::compression::{CompressionLevel, compress_response};
+use crate::csrf::verify_csrf;
+use crate::domain::{HubId, UploadRoot};
 use crate::models::config::ServerConfig;
-use crate::routes::main::{create_folder, file_browser, index, upload_files};
+use crate::routes::health::{healthz, readyz};
+use crate::routes::main::{
+    chunk_manifest, complete_upload, copy_entry, count_entries, create_file, create_folder,
+    delete_file, delete_folder, delete_many, download_file, download_folder, empty_trash,
+    extension_counts, file_browser, file_head, file_info, folder_depth, hub_tree, index,
+    inspect_archive, list_files, media_info, move_entry, move_matching, prune_empty,
+    publish_folder, recent_files, reject_shared_write, rename_by_pattern, rename_entry,
+    repair_thumbnails, restore_trashed, sanitize_names, save_file, save_text_file, search,
+    shared_files, stat_entry, storage_breakdown, swap_entries, upload_chunk, upload_files,
+    upload_files_streaming, upload_limits, view_file, watch_entries,
+};
+use crate::routes::metrics::metrics_endpoint;
+use crate::services::audit::FileAuditLog;
+use crate::services::download_counts::DownloadCounts;
+use crate::services::files::FileService;
+use crate::services::quota::DownloadQuota;
+use crate::services::rate_limit::UploadRateLimiter;
 
+pub mod client_ip;
+pub mod compression;
+pub mod csrf;
 pub mod domain;
 pub mod dto;
 pub mod forms;
+pub mod metrics;
 pub mod models;
 pub mod routes;
 pub mod services;
 
 pub const SERVICE_ACCESS_ROLE: &str = "files";
 
+/// Role granting cross-hub maintenance operations under `/api/admin`.
+pub const SERVICE_ADMIN_ROLE: &str = "files_admin";
+
+/// CORS policy of the app: only `allowed_origins` may make credentialed
+/// cross-origin requests, so with none listed browsers stay same-origin.
+/// `cors_permissive` restores the allow-everything policy for local
+/// development.
+fn build_cors(allowed_origins: &[String], permissive: bool) -> Cors {
+    if permissive {
+        return Cors::permissive();
+    }
+    allowed_origins.iter().fold(
+        Cors::default()
+            .allow_any_method()
+            .allow_any_header()
+            .supports_credentials()
+            .max_age(3600),
+        |cors, origin| cors.allowed_origin(origin),
+    )
+}
+
+/// Answer multipart bodies over the largest upload limit with `413` instead of
+/// the generic multipart error, which never reaches `upload_files`.
+fn upload_too_large(err: MultipartError, _req: &HttpRequest) -> actix_web::Error {
+    let response = match &err {
+        MultipartError::Payload(PayloadError::Overflow) => {
+            HttpResponse::PayloadTooLarge().body("Файл больше допустимого размера загрузки.")
+        }
+        _ => HttpResponse::BadRequest().body(err.to_string()),
+    };
+    InternalError::from_response(err, response).into()
+}
+
 /// Builds and runs the Actix-Web HTTP server using the provided configuration.
 pub async fn run(server_config: ServerConfig) -> std::io::Result<()> {
+    server_config.validate()?;
+
     let common_config = CommonServerConfig {
         auth_service_url: server_config.auth_service_url.to_string(),
         secret: server_config.secret.clone(),
@@ -38,34 +104,196 @@ pub async fn run(server_config: ServerConfig) -> std::io::Result<()> {
     let tera = Tera::new(&server_config.templates_dir)
         .map_err(|e| std::io::Error::other(format!("Template parsing error(s): {e}")))?;
 
+    let upload_root = UploadRoot::from(PathBuf::from(&server_config.upload_path));
+    if let Some(hub_id) = server_config
+        .hub_roots
+        .keys()
+        .find(|hub_id| HubId::try_new(**hub_id).is_err())
+    {
+        return Err(std::io::Error::other(format!(
+            "hub_roots has an invalid hub id: {hub_id}"
+        )));
+    }
+    let hub_roots = server_config
+        .hub_roots
+        .iter()
+        .map(|(hub_id, path)| (*hub_id, PathBuf::from(path)))
+        .collect();
+    let mut file_service = FileService::new(upload_root)
+        .with_hub_roots(hub_roots)
+        .with_symlinked_roots(server_config.allow_symlinked_roots)
+        .with_max_tree_depth(server_config.max_tree_depth)
+        .with_conflict_policy(server_config.conflict_policy())
+        .with_max_head_bytes(server_config.max_head_bytes)
+        .with_folder_name_charset(server_config.folder_name_charset)
+        .with_max_watchers_per_hub(server_config.max_watchers_per_hub)
+        .with_max_upload_bytes(server_config.max_upload_bytes)
+        .with_hub_upload_limits(server_config.hub_upload_limits.clone())
+        .with_quota_bytes(server_config.quota_bytes)
+        .with_chunked_upload_ttl(Duration::from_secs(server_config.chunked_upload_ttl_secs))
+        .with_folder_previews(server_config.folder_previews)
+        .with_prune_empty_on_delete(server_config.prune_empty_on_delete)
+        .with_soft_delete(server_config.soft_delete)
+        .with_strict_mime(server_config.strict_mime)
+        .with_double_extension_blocklist(&server_config.reject_double_extension_executables)
+        .with_allowed_extensions(server_config.allowed_extensions.as_deref())
+        .with_image_extensions(server_config.image_extensions.as_deref())
+        .with_reserved_sidecars(&server_config.reserved_sidecar_names)
+        .with_ignore_patterns(&server_config.ignore_patterns)
+        .map_err(|e| std::io::Error::other(format!("Invalid ignore pattern: {e}")))?;
+    let default_mime = server_config
+        .default_mime_extensionless
+        .parse::<mime::Mime>()
+        .map_err(|e| std::io::Error::other(format!("Invalid default_mime_extensionless: {e}")))?;
+    file_service = file_service.with_default_mime_extensionless(default_mime);
+    if let Some(ratio) = server_config.max_aspect_ratio {
+        if ratio.is_nan() || ratio < 1.0 {
+            return Err(std::io::Error::other(format!(
+                "max_aspect_ratio must be at least 1.0, got {ratio}"
+            )));
+        }
+        file_service = file_service.with_max_aspect_ratio(ratio);
+    }
+    if let Some(shared_path) = &server_config.shared_path {
+        file_service = file_service.with_shared_root(PathBuf::from(shared_path));
+    }
+    if let Some(mirror_path) = &server_config.mirror_path {
+        file_service = file_service.with_mirror(UploadRoot::from(PathBuf::from(mirror_path)));
+    }
+    if let Some(audit_log_path) = &server_config.audit_log_path {
+        let audit_log = FileAuditLog::open(audit_log_path).map_err(|e| {
+            std::io::Error::other(format!(
+                "audit_log_path {audit_log_path} is not writable: {e}"
+            ))
+        })?;
+        file_service = file_service.with_audit_log(Arc::new(audit_log));
+    }
+    file_service = file_service.with_download_counts(
+        DownloadCounts::load(
+            PathBuf::from(&server_config.upload_path).join(".download_counts.json"),
+        )
+        .with_shared_storage(server_config.shared_storage_mode),
+    );
+
+    let multipart_limit =
+        usize::try_from(file_service.largest_upload_limit()).unwrap_or(usize::MAX);
+
+    let download_quota = server_config.download_quota_bytes.map(|limit| {
+        DownloadQuota::load(
+            limit,
+            Duration::from_secs(server_config.download_quota_period_secs),
+            PathBuf::from(&server_config.upload_path).join(".download_usage.json"),
+        )
+        .with_shared_storage(server_config.shared_storage_mode)
+    });
+
+    let upload_rate_limiter = server_config
+        .upload_rate_limit_per_minute
+        .map(UploadRateLimiter::new);
+
+    let compression_level = CompressionLevel(server_config.compression_level);
+
+    let trusted_proxies = server_config.trusted_proxies.clone();
+
     let bind_address = (server_config.address.clone(), server_config.port);
+    let allowed_origins = server_config.allowed_origins.clone();
+    let cors_permissive = server_config.cors_permissive;
 
     HttpServer::new(move || {
         App::new()
-            .wrap(Cors::permissive())
+            .wrap(build_cors(&allowed_origins, cors_permissive))
             .wrap(message_framework.clone())
             .wrap(IdentityMiddleware::default())
             .wrap(
                 SessionMiddleware::builder(CookieSessionStore::default(), secret_key.clone())
-                    .cookie_secure(false) // set to true in prod
+                    .cookie_secure(server_config.cookie_secure)
+                    .cookie_same_site(server_config.cookie_same_site.into())
                     .cookie_domain(Some(format!(".{}", server_config.domain)))
                     .build(),
             )
-            .wrap(middleware::Compress::default())
-            .wrap(middleware::Logger::default())
-            .service(Files::new("/upload", &server_config.upload_path).show_files_listing())
+            .wrap(middleware::Condition::new(
+                compression_level.0 > 0,
+                middleware::from_fn(compress_response),
+            ))
+            .wrap({
+                let trusted_proxies = trusted_proxies.clone();
+                middleware::Logger::new(
+                    r#"%{client_ip}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#,
+                )
+                .custom_request_replace("client_ip", move |req| {
+                    let peer = req.peer_addr().map(|addr| addr.ip());
+                    client_ip(peer, req.headers(), &trusted_proxies)
+                        .map(|ip| ip.to_string())
+                        .unwrap_or_else(|| "-".to_string())
+                })
+            })
             .service(Files::new("/assets", "./assets"))
+            .service(healthz)
+            .service(readyz)
+            .service(metrics_endpoint)
             .service(not_assigned)
             .service(
                 web::scope("")
+                    .wrap(middleware::from_fn(verify_csrf))
                     .wrap(RedirectUnauthorized)
                     .service(index)
                     .service(file_browser)
+                    .service(list_files)
+                    .service(search)
                     .service(logout)
                     .service(upload_files)
-                    .service(create_folder),
+                    .service(upload_files_streaming)
+                    .service(upload_chunk)
+                    .service(complete_upload)
+                    .service(create_folder)
+                    .service(create_file)
+                    .service(delete_file)
+                    .service(delete_many)
+                    .service(delete_folder)
+                    .service(restore_trashed)
+                    .service(empty_trash)
+                    .service(save_text_file)
+                    .service(save_file)
+                    .service(storage_breakdown)
+                    .service(folder_depth)
+                    .service(hub_tree)
+                    .service(recent_files)
+                    .service(count_entries)
+                    .service(extension_counts)
+                    .service(swap_entries)
+                    .service(rename_by_pattern)
+                    .service(rename_entry)
+                    .service(move_entry)
+                    .service(copy_entry)
+                    .service(move_matching)
+                    .service(publish_folder)
+                    .service(shared_files)
+                    .service(reject_shared_write)
+                    .service(repair_thumbnails)
+                    .service(chunk_manifest)
+                    .service(view_file)
+                    .service(download_file)
+                    .service(download_folder)
+                    .service(file_head)
+                    .service(file_info)
+                    .service(stat_entry)
+                    .service(media_info)
+                    .service(watch_entries)
+                    .service(upload_limits)
+                    .service(sanitize_names)
+                    .service(prune_empty)
+                    .service(inspect_archive),
             )
             .app_data(web::Data::new(tera.clone()))
+            .app_data(web::Data::new(file_service.clone()))
+            .app_data(web::Data::new(download_quota.clone()))
+            .app_data(web::Data::new(upload_rate_limiter.clone()))
+            .app_data(web::Data::new(compression_level))
+            .app_data(
+                MultipartFormConfig::default()
+                    .total_limit(multipart_limit)
+                    .error_handler(upload_too_large),
+            )
             .app_data(web::Data::new(server_config.clone()))
             .app_data(web::Data::new(common_config.clone()))
     })
@@ -75,4 +303,49 @@ pub async fn run(server_config: ServerConfig) -> std::io::Result<()> {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use actix_web::http::header;
+    use actix_web::test;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn cors_allows_credentials_for_listed_origins_only() {
+        let app = test::init_service(
+            App::new()
+                .wrap(build_cors(&["https://app.test.me".to_string()], false))
+                .route("/", web::get().to(|| async { "ok" })),
+        )
+        .await;
+
+        let request = |origin: &str| {
+            test::TestRequest::get()
+                .uri("/")
+                .insert_header((header::ORIGIN, origin))
+                .to_request()
+        };
+        let listed = test::call_service(&app, request("https://app.test.me")).await;
+        assert_eq!(
+            listed
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap(),
+            "https://app.test.me"
+        );
+        assert_eq!(
+            listed
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+
+        let other = test::call_service(&app, request("https://evil.example")).await;
+        assert!(
+            other
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .is_none()
+        );
+    }
+}
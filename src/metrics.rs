@@ -0,0 +1,73 @@
+//! Prometheus metrics collected by the service and served at `/metrics`.
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use prometheus::{
+    Encoder, Histogram, IntCounter, TextEncoder, register_histogram, register_int_counter,
+};
+
+/// Files stored through `persist_upload`.
+pub static FILE_UPLOADS_TOTAL: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("file_uploads_total", "Files stored through uploads")
+        .expect("file_uploads_total is registered once")
+});
+/// Bytes of files stored through `persist_upload`.
+pub static UPLOAD_BYTES_TOTAL: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("upload_bytes_total", "Bytes of uploaded files")
+        .expect("upload_bytes_total is registered once")
+});
+/// Folders created by `create_folder`.
+pub static FOLDER_CREATES_TOTAL: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("folder_creates_total", "Folders created")
+        .expect("folder_creates_total is registered once")
+});
+/// Time spent in `list_entries`.
+pub static LIST_ENTRIES_SECONDS: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "list_entries_duration_seconds",
+        "Time taken to list a folder"
+    )
+    .expect("list_entries_duration_seconds is registered once")
+});
+
+/// Count a stored upload of `bytes` bytes.
+pub fn record_upload(bytes: u64) {
+    FILE_UPLOADS_TOTAL.inc();
+    UPLOAD_BYTES_TOTAL.inc_by(bytes);
+}
+
+/// Count a newly created folder.
+pub fn record_folder_create() {
+    FOLDER_CREATES_TOTAL.inc();
+}
+
+/// Record how long a listing took.
+pub fn observe_listing(elapsed: Duration) {
+    LIST_ENTRIES_SECONDS.observe(elapsed.as_secs_f64());
+}
+
+/// Every registered metric in the Prometheus text exposition format.
+pub fn encode() -> Result<String, prometheus::Error> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_metrics_are_encoded() {
+        record_upload(42);
+        record_folder_create();
+        observe_listing(Duration::from_millis(5));
+
+        let text = encode().unwrap();
+        assert!(text.contains("file_uploads_total"));
+        assert!(text.contains("upload_bytes_total"));
+        assert!(text.contains("folder_creates_total"));
+        assert!(text.contains("list_entries_duration_seconds_bucket"));
+        assert!(UPLOAD_BYTES_TOTAL.get() >= 42);
+    }
+}
@@ -1,6 +1,22 @@
 //! Configuration model loaded from external sources.
 
-use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, TcpListener};
+
+use actix_web::cookie::SameSite;
+use serde::{Deserialize, Deserializer};
+
+use crate::compression::MAX_COMPRESSION_LEVEL;
+use crate::domain::{ConflictPolicy, FolderNameCharset};
+use crate::services::files::{
+    DEFAULT_CHUNKED_UPLOAD_TTL, DEFAULT_MAX_HEAD_BYTES, DEFAULT_MAX_SAVE_BYTES,
+    DEFAULT_MAX_TREE_DEPTH, DEFAULT_MAX_UPLOAD_BYTES, DEFAULT_MAX_VIEW_BYTES,
+    DEFAULT_RESERVED_SIDECARS,
+};
+use crate::services::quota::DEFAULT_DOWNLOAD_QUOTA_PERIOD_SECS;
+use crate::services::watch::DEFAULT_MAX_WATCHERS_PER_HUB;
 
 #[derive(Clone, Debug, Deserialize)]
 /// Basic configuration shared across handlers.
@@ -12,4 +28,501 @@ pub struct ServerConfig {
     pub templates_dir: String,
     pub secret: String,
     pub upload_path: String,
+    /// Glob patterns for entry names hidden from listings (e.g. `.DS_Store`).
+    #[serde(default)]
+    pub ignore_patterns: Vec<String>,
+    /// Extensions refused when they trail another one, as in `invoice.pdf.exe`.
+    #[serde(default)]
+    pub reject_double_extension_executables: Vec<String>,
+    /// Only extensions uploads may use; every extension is accepted when unset.
+    #[serde(default)]
+    pub allowed_extensions: Option<Vec<String>>,
+    /// Sidecar file names uploads and renames may not use.
+    #[serde(default = "default_reserved_sidecar_names")]
+    pub reserved_sidecar_names: Vec<String>,
+    /// Secondary root that receives a best-effort copy of every write.
+    #[serde(default)]
+    pub mirror_path: Option<String>,
+    /// Read-only library listed to every hub via `/api/files/shared`.
+    #[serde(default)]
+    pub shared_path: Option<String>,
+    /// Allow `upload_path/{hub_id}` to be a symlink to another volume.
+    #[serde(default)]
+    pub allow_symlinked_roots: bool,
+    /// Deepest level recursive folder operations descend to.
+    #[serde(default = "default_max_tree_depth")]
+    pub max_tree_depth: usize,
+    /// How uploads onto an existing file name are handled.
+    #[serde(default)]
+    pub upload_conflict_policy: ConflictPolicy,
+    /// Replace existing files on upload instead of numbering the new one;
+    /// takes precedence over `upload_conflict_policy`.
+    #[serde(default)]
+    pub overwrite_on_conflict: bool,
+    /// Characters allowed in new folder names (`any`, `alphanumeric`,
+    /// `alphanumeric_dash`).
+    #[serde(default)]
+    pub folder_name_charset: FolderNameCharset,
+    /// Show the first image inside each folder as its icon in listings.
+    #[serde(default)]
+    pub folder_previews: bool,
+    /// Send the file browser in chunks while the folder is read. Entries
+    /// then arrive in directory order instead of sorted.
+    #[serde(default)]
+    pub stream_listings: bool,
+    /// Remove folders left empty by a delete or move, up to the hub root.
+    #[serde(default)]
+    pub prune_empty_on_delete: bool,
+    /// Move deleted files and folders into the hub's `.trash` folder.
+    #[serde(default)]
+    pub soft_delete: bool,
+    /// MIME type served for files without an extension.
+    #[serde(default = "default_mime_extensionless")]
+    pub default_mime_extensionless: String,
+    /// Gzip/deflate level for responses, from 1 (fastest) to 9 (smallest);
+    /// `0` disables response compression.
+    #[serde(default = "default_compression_level")]
+    pub compression_level: u32,
+    /// Largest accepted width/height (or height/width) ratio of uploaded images.
+    #[serde(default)]
+    pub max_aspect_ratio: Option<f64>,
+    /// Reject uploads whose sniffed content does not match their extension.
+    #[serde(default)]
+    pub strict_mime: bool,
+    /// Most bytes returned by the `/api/files/head` preview endpoint.
+    #[serde(default = "default_max_head_bytes")]
+    pub max_head_bytes: u64,
+    /// Largest text file `/files/view` returns.
+    #[serde(default = "default_max_view_bytes")]
+    pub max_view_bytes: u64,
+    /// Largest text `/files/save` writes.
+    #[serde(default = "default_max_save_bytes")]
+    pub max_save_bytes: u64,
+    /// Concurrent `/api/files/watch` long-polls allowed per hub.
+    #[serde(default = "default_max_watchers_per_hub")]
+    pub max_watchers_per_hub: usize,
+    /// Largest accepted upload in bytes.
+    #[serde(default = "default_max_upload_bytes")]
+    pub max_upload_bytes: u64,
+    /// Upload size limits in bytes overriding the global one for specific
+    /// hubs, keyed by hub id.
+    #[serde(default, deserialize_with = "deserialize_hub_map")]
+    pub hub_upload_limits: HashMap<i32, u64>,
+    /// Directories holding specific hubs instead of `upload_path/<hub id>`,
+    /// keyed by hub id.
+    #[serde(default, deserialize_with = "deserialize_hub_map")]
+    pub hub_roots: HashMap<i32, String>,
+    /// Extensions listed as images, replacing the built-in set.
+    #[serde(default)]
+    pub image_extensions: Option<Vec<String>>,
+    /// Seconds an unfinished chunked upload is kept after its last part.
+    #[serde(default = "default_chunked_upload_ttl_secs")]
+    pub chunked_upload_ttl_secs: u64,
+    /// Uploads each user may start per minute.
+    #[serde(default)]
+    pub upload_rate_limit_per_minute: Option<u32>,
+    /// Bytes each hub may store in total.
+    #[serde(default)]
+    pub quota_bytes: Option<u64>,
+    /// Bytes each hub may download through `/files/download` and
+    /// `/folder/download` per quota period.
+    #[serde(default)]
+    pub download_quota_bytes: Option<u64>,
+    /// Length of a download quota period in seconds.
+    #[serde(default = "default_download_quota_period_secs")]
+    pub download_quota_period_secs: u64,
+    /// File receiving one JSON line per upload, new file or folder, editor
+    /// save, deletion, rename and trash change. No audit trail is kept when
+    /// unset.
+    #[serde(default)]
+    pub audit_log_path: Option<String>,
+    /// Reverse proxies whose forwarding headers are trusted for the client IP.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Several instances serve the same `upload_path`, e.g. during
+    /// blue-green deploys. Download counts and download quota usage are then
+    /// re-read from their sidecars on every use instead of trusted from
+    /// memory; listings always read the disk. Upload rate limits stay per
+    /// instance.
+    #[serde(default)]
+    pub shared_storage_mode: bool,
+    /// Origins allowed to make credentialed cross-origin requests, e.g.
+    /// `https://app.example.com`. Only same-origin requests when empty.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Allow every origin, method and header. Meant for local development.
+    #[serde(default)]
+    pub cors_permissive: bool,
+    /// Send the session cookie over HTTPS only.
+    #[serde(default = "default_cookie_secure")]
+    pub cookie_secure: bool,
+    /// `SameSite` attribute of the session cookie.
+    #[serde(default)]
+    pub cookie_same_site: CookieSameSite,
+}
+
+/// `SameSite` attribute of the session cookie.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CookieSameSite {
+    Strict,
+    #[default]
+    Lax,
+    /// Sent with cross-site requests too; requires `cookie_secure`.
+    None,
+}
+
+impl From<CookieSameSite> for SameSite {
+    fn from(value: CookieSameSite) -> Self {
+        match value {
+            CookieSameSite::Strict => SameSite::Strict,
+            CookieSameSite::Lax => SameSite::Lax,
+            CookieSameSite::None => SameSite::None,
+        }
+    }
+}
+
+/// Shortest `secret` accepted; `Key::from` needs 64 bytes of key material.
+pub const MIN_SECRET_BYTES: usize = 64;
+
+impl ServerConfig {
+    /// Conflict policy for uploads after applying `overwrite_on_conflict`.
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        if self.overwrite_on_conflict {
+            ConflictPolicy::Overwrite
+        } else {
+            self.upload_conflict_policy
+        }
+    }
+
+    /// Check the settings that otherwise only fail once requests arrive, so a
+    /// broken deployment refuses to start with a readable error.
+    pub fn validate(&self) -> io::Result<()> {
+        if self.secret.len() < MIN_SECRET_BYTES {
+            return Err(io::Error::other(format!(
+                "secret must be at least {MIN_SECRET_BYTES} bytes, got {}",
+                self.secret.len()
+            )));
+        }
+
+        if self.cookie_same_site == CookieSameSite::None && !self.cookie_secure {
+            return Err(io::Error::other(
+                "cookie_same_site none requires cookie_secure",
+            ));
+        }
+
+        if self.compression_level > MAX_COMPRESSION_LEVEL {
+            return Err(io::Error::other(format!(
+                "compression_level must be between 0 and {MAX_COMPRESSION_LEVEL}, got {}",
+                self.compression_level
+            )));
+        }
+
+        for origin in &self.allowed_origins {
+            let is_origin = origin
+                .parse::<actix_web::http::Uri>()
+                .is_ok_and(|uri| uri.scheme().is_some() && uri.host().is_some());
+            if !is_origin {
+                return Err(io::Error::other(format!(
+                    "allowed_origins entry {origin} is not an origin like https://example.com"
+                )));
+            }
+        }
+
+        self.check_upload_path()?;
+
+        let tera = tera::Tera::new(&self.templates_dir)
+            .map_err(|e| io::Error::other(format!("Template parsing error(s): {e}")))?;
+        if tera.get_template_names().next().is_none() {
+            return Err(io::Error::other(format!(
+                "templates_dir {} matches no templates",
+                self.templates_dir
+            )));
+        }
+
+        TcpListener::bind((self.address.as_str(), self.port)).map_err(|e| {
+            io::Error::other(format!("cannot bind {}:{}: {e}", self.address, self.port))
+        })?;
+
+        Ok(())
+    }
+
+    /// Create `upload_path` if needed and make sure a file can be written
+    /// inside it.
+    pub fn check_upload_path(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.upload_path)
+            .and_then(|_| tempfile::tempfile_in(&self.upload_path))
+            .map(|_| ())
+            .map_err(|e| {
+                io::Error::other(format!(
+                    "upload_path {} is not writable: {e}",
+                    self.upload_path
+                ))
+            })
+    }
+}
+
+fn default_reserved_sidecar_names() -> Vec<String> {
+    DEFAULT_RESERVED_SIDECARS
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn default_mime_extensionless() -> String {
+    mime::APPLICATION_OCTET_STREAM.to_string()
+}
+
+fn default_cookie_secure() -> bool {
+    true
+}
+
+fn default_compression_level() -> u32 {
+    6
+}
+
+fn default_max_tree_depth() -> usize {
+    DEFAULT_MAX_TREE_DEPTH
+}
+
+fn default_max_head_bytes() -> u64 {
+    DEFAULT_MAX_HEAD_BYTES
+}
+
+fn default_max_view_bytes() -> u64 {
+    DEFAULT_MAX_VIEW_BYTES
+}
+
+fn default_max_save_bytes() -> u64 {
+    DEFAULT_MAX_SAVE_BYTES
+}
+
+fn default_max_upload_bytes() -> u64 {
+    DEFAULT_MAX_UPLOAD_BYTES
+}
+
+fn default_max_watchers_per_hub() -> usize {
+    DEFAULT_MAX_WATCHERS_PER_HUB
+}
+
+fn default_chunked_upload_ttl_secs() -> u64 {
+    DEFAULT_CHUNKED_UPLOAD_TTL.as_secs()
+}
+
+fn default_download_quota_period_secs() -> u64 {
+    DEFAULT_DOWNLOAD_QUOTA_PERIOD_SECS
+}
+
+/// Config sources hand map keys over as strings; parse them into hub ids.
+fn deserialize_hub_map<'de, D, V>(deserializer: D) -> Result<HashMap<i32, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    HashMap::<String, V>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| {
+            key.parse::<i32>()
+                .map(|hub_id| (hub_id, value))
+                .map_err(|_| serde::de::Error::custom(format!("invalid hub id: {key}")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{Config, File, FileFormat};
+    use tempfile::tempdir;
+
+    use super::*;
+
+    const BASE: &str = r#"
+domain: test.me
+address: 127.0.0.1
+port: 8080
+auth_service_url: http://auth.test.me
+templates_dir: templates/**/*
+secret: secret
+upload_path: ./upload/
+"#;
+
+    fn parse(extra: &str) -> ServerConfig {
+        Config::builder()
+            .add_source(File::from_str(&format!("{BASE}{extra}"), FileFormat::Yaml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    /// A config that passes validation: long secret, temporary upload root,
+    /// the repository templates and a port that was free a moment ago.
+    fn valid(upload_path: &std::path::Path) -> ServerConfig {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut config = parse("");
+        config.secret = "s".repeat(MIN_SECRET_BYTES);
+        config.upload_path = upload_path.to_string_lossy().into_owned();
+        config.port = port;
+        config
+    }
+
+    #[test]
+    fn validate_accepts_sound_config() {
+        let dir = tempdir().unwrap();
+        valid(dir.path()).validate().unwrap();
+    }
+
+    #[test]
+    fn validate_rejects_short_secret() {
+        let dir = tempdir().unwrap();
+        let mut config = valid(dir.path());
+        config.secret = "secret".into();
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn validate_rejects_malformed_origins() {
+        let dir = tempdir().unwrap();
+        let mut config = valid(dir.path());
+        assert!(config.allowed_origins.is_empty());
+        assert!(!config.cors_permissive);
+
+        config.allowed_origins = vec!["https://app.test.me".into()];
+        config.validate().unwrap();
+        config.allowed_origins = vec!["*".into()];
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("allowed_origins"));
+    }
+
+    #[test]
+    fn validate_rejects_unwritable_upload_path() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("not-a-dir");
+        fs::write(&file, b"").unwrap();
+        let config = valid(&file);
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("upload_path"));
+    }
+
+    #[test]
+    fn validate_rejects_empty_template_glob() {
+        let dir = tempdir().unwrap();
+        let mut config = valid(dir.path());
+        config.templates_dir = format!("{}/templates/**/*", dir.path().display());
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("templates_dir"));
+    }
+
+    #[test]
+    fn validate_rejects_port_in_use() {
+        let dir = tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = valid(dir.path());
+        config.port = listener.local_addr().unwrap().port();
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("bind"));
+    }
+
+    #[test]
+    fn session_cookie_is_secure_and_lax_by_default() {
+        let config = parse("");
+        assert!(config.cookie_secure);
+        assert_eq!(config.cookie_same_site, CookieSameSite::Lax);
+
+        let config = parse("cookie_secure: false\ncookie_same_site: strict\n");
+        assert!(!config.cookie_secure);
+        assert_eq!(SameSite::from(config.cookie_same_site), SameSite::Strict);
+    }
+
+    #[test]
+    fn validate_rejects_insecure_same_site_none() {
+        let dir = tempdir().unwrap();
+        let mut config = valid(dir.path());
+        config.cookie_same_site = CookieSameSite::None;
+        config.validate().unwrap();
+        config.cookie_secure = false;
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("cookie_secure"));
+    }
+
+    #[test]
+    fn compression_level_defaults_and_parses() {
+        assert_eq!(parse("").compression_level, 6);
+        assert_eq!(parse("compression_level: 1\n").compression_level, 1);
+        assert_eq!(parse("compression_level: 0\n").compression_level, 0);
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_compression_level() {
+        let dir = tempdir().unwrap();
+        let mut config = valid(dir.path());
+        config.compression_level = MAX_COMPRESSION_LEVEL;
+        config.validate().unwrap();
+        config.compression_level = MAX_COMPRESSION_LEVEL + 1;
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("compression_level"));
+    }
+
+    #[test]
+    fn shared_storage_mode_is_off_by_default() {
+        assert!(!parse("").shared_storage_mode);
+        assert!(parse("shared_storage_mode: true\n").shared_storage_mode);
+    }
+
+    #[test]
+    fn overwrite_on_conflict_restores_overwriting_uploads() {
+        assert_eq!(parse("").conflict_policy(), ConflictPolicy::Rename);
+        assert_eq!(
+            parse("overwrite_on_conflict: true\n").conflict_policy(),
+            ConflictPolicy::Overwrite
+        );
+        assert_eq!(
+            parse("upload_conflict_policy: reject\n").conflict_policy(),
+            ConflictPolicy::Reject
+        );
+    }
+
+    #[test]
+    fn max_upload_bytes_defaults_to_ten_megabytes() {
+        assert_eq!(parse("").max_upload_bytes, 10 * 1024 * 1024);
+        assert_eq!(
+            parse("max_upload_bytes: 52428800\n").max_upload_bytes,
+            52_428_800
+        );
+    }
+
+    #[test]
+    fn hub_upload_limits_parse_hub_ids() {
+        assert!(parse("").hub_upload_limits.is_empty());
+        let config = parse("hub_upload_limits:\n  7: 52428800\n");
+        assert_eq!(config.hub_upload_limits.get(&7), Some(&52_428_800));
+    }
+
+    #[test]
+    fn hub_roots_parse_hub_ids() {
+        assert!(parse("").hub_roots.is_empty());
+        let config = parse("hub_roots:\n  7: /mnt/big/7\n");
+        assert_eq!(
+            config.hub_roots.get(&7).map(String::as_str),
+            Some("/mnt/big/7")
+        );
+    }
+
+    #[test]
+    fn quota_bytes_is_unset_by_default() {
+        assert_eq!(parse("").quota_bytes, None);
+        assert_eq!(
+            parse(
+                "quota_bytes: 1073741824
+"
+            )
+            .quota_bytes,
+            Some(1_073_741_824)
+        );
+    }
 }
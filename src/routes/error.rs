@@ -0,0 +1,194 @@
+//! JSON error bodies for API routes.
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+
+use crate::services::ServiceError;
+
+/// Error answered by JSON routes as `{ "error": "...", "code": "..." }`.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    /// Human readable message for the user.
+    error: String,
+    /// Stable machine readable identifier of the failure.
+    code: &'static str,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, error: impl Into<String>) -> Self {
+        Self {
+            status,
+            error: error.into(),
+            code,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.error)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(self)
+    }
+}
+
+impl From<ServiceError> for ApiError {
+    fn from(err: ServiceError) -> Self {
+        match err {
+            ServiceError::Unauthorized => Self::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "Недостаточно прав.",
+            ),
+            ServiceError::Validation(msg) => Self::new(StatusCode::BAD_REQUEST, "validation", msg),
+            ServiceError::InvalidPath => Self::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_path",
+                "Некорректный путь.",
+            ),
+            ServiceError::InvalidFileName => Self::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_file_name",
+                "Некорректное имя файла.",
+            ),
+            ServiceError::FileExists => Self::new(
+                StatusCode::CONFLICT,
+                "file_exists",
+                "Файл или папка с таким именем уже существует.",
+            ),
+            ServiceError::NotFound => Self::new(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "Файл или папка не найдены.",
+            ),
+            ServiceError::CopyConflict(paths) => Self::new(
+                StatusCode::CONFLICT,
+                "copy_conflict",
+                format!("В папке назначения уже есть: {}.", paths.join(", ")),
+            ),
+            ServiceError::FolderNotEmpty => {
+                Self::new(StatusCode::CONFLICT, "folder_not_empty", "Папка не пуста.")
+            }
+            ServiceError::FileTooLarge { limit } => Self::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "file_too_large",
+                format!("Файл больше допустимых {limit} байт."),
+            ),
+            ServiceError::QuotaExceeded { folder, limit } => Self::new(
+                StatusCode::INSUFFICIENT_STORAGE,
+                "quota_exceeded",
+                format!("Превышена квота папки {folder} ({limit} байт)."),
+            ),
+            ServiceError::DisallowedExtension => Self::new(
+                StatusCode::BAD_REQUEST,
+                "disallowed_extension",
+                "Недопустимое расширение файла.",
+            ),
+            ServiceError::ForbiddenExtension => Self::new(
+                StatusCode::BAD_REQUEST,
+                "forbidden_extension",
+                "Недопустимое расширение файла.",
+            ),
+            ServiceError::TooLargeToView { limit } => Self::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "too_large_to_view",
+                format!("Файл больше {limit} байт, скачайте его."),
+            ),
+            ServiceError::NotText => Self::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "not_text",
+                "Файл не текстовый, скачайте его.",
+            ),
+            ServiceError::UnsupportedMedia => Self::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media",
+                "Неподдерживаемый медиафайл.",
+            ),
+            ServiceError::AspectRatioExceeded => Self::new(
+                StatusCode::BAD_REQUEST,
+                "aspect_ratio_exceeded",
+                "Недопустимое соотношение сторон изображения.",
+            ),
+            ServiceError::ContentTypeMismatch => Self::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "content_type_mismatch",
+                "Содержимое файла не соответствует его расширению.",
+            ),
+            ServiceError::DownloadQuotaExceeded => Self::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "download_quota_exceeded",
+                "Превышена квота скачивания.",
+            ),
+            ServiceError::TooManyWatchers => Self::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "too_many_watchers",
+                "Слишком много наблюдателей за папками.",
+            ),
+            ServiceError::TooManyUploads => Self::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "too_many_uploads",
+                "Слишком много загрузок, попробуйте через минуту.",
+            ),
+            err @ (ServiceError::Watch(_)
+            | ServiceError::StorageSetup(_)
+            | ServiceError::ListEntries(_)
+            | ServiceError::CreateFolder(_)
+            | ServiceError::ReadFile(_)
+            | ServiceError::SaveFile(_)
+            | ServiceError::DeleteEntry(_)
+            | ServiceError::MoveEntry(_)
+            | ServiceError::CopyEntry(_)
+            | ServiceError::BuildArchive(_)) => {
+                log::error!("Service error: {err:?}");
+                Self::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal",
+                    "Внутренняя ошибка сервера.",
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn service_errors_become_json_bodies() {
+        let response = ApiError::from(ServiceError::FileTooLarge { limit: 10 }).error_response();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "error": "Файл больше допустимых 10 байт.",
+                "code": "file_too_large",
+            })
+        );
+
+        let internal = ApiError::from(ServiceError::ListEntries(std::io::Error::other("disk")));
+        assert_eq!(internal.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(internal.code(), "internal");
+        assert_eq!(internal.to_string(), "Внутренняя ошибка сервера.");
+    }
+}
@@ -0,0 +1,44 @@
+//! Liveness and readiness probes, served without authentication.
+use actix_web::{HttpResponse, Responder, get, web};
+
+use crate::models::config::ServerConfig;
+
+/// Liveness probe: answers as long as the server handles requests.
+#[get("/healthz")]
+pub async fn healthz() -> impl Responder {
+    HttpResponse::Ok().body("ok")
+}
+
+/// Readiness probe: the upload path must exist, or be creatable, and accept
+/// writes.
+#[get("/readyz")]
+pub async fn readyz(server_config: web::Data<ServerConfig>) -> impl Responder {
+    let server_config = server_config.into_inner();
+    match web::block(move || server_config.check_upload_path()).await {
+        Ok(Ok(())) => HttpResponse::Ok().body("ready"),
+        Ok(Err(e)) => {
+            log::warn!("Readiness check failed: {e}");
+            HttpResponse::ServiceUnavailable().body("upload path is not writable")
+        }
+        Err(e) => {
+            log::error!("Readiness check failed to run: {e:?}");
+            HttpResponse::ServiceUnavailable().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{App, test};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn healthz_always_answers_ok() {
+        let app = test::init_service(App::new().service(healthz)).await;
+        let req = test::TestRequest::get().uri("/healthz").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -1,33 +1,280 @@
-use std::path::Path;
-
+use actix_files::NamedFile;
 use actix_multipart::form::MultipartForm;
-use actix_web::{HttpResponse, Responder, get, post, web};
-use actix_web_flash_messages::IncomingFlashMessages;
+use actix_multipart::{Field, Multipart};
+use actix_session::Session;
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{HttpRequest, HttpResponse, Responder, ResponseError, get, post, route, web};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use futures_util::TryStreamExt;
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
 use pushkind_common::domain::auth::AuthenticatedUser;
 use pushkind_common::models::config::CommonServerConfig;
+use pushkind_common::routes::base_context;
 use pushkind_common::routes::redirect;
-use pushkind_common::routes::{base_context, render_template};
-use serde::Deserialize;
-use tera::Tera;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use tera::{Context, Tera};
+use tokio::io::AsyncWriteExt;
 
-use crate::domain::UploadRoot;
-use crate::dto::FileEntryDto;
-use crate::forms::main::{CreateFolderForm, UploadFileForm};
+use crate::csrf::csrf_token;
+use crate::domain::{EntryFilter, HubId, ListingSort, RelativePath};
+use crate::dto::{
+    BreadcrumbDto, FieldProjection, FileEntryDto, FileInfoDto, FileListingDto, HubUsageDto,
+    UploadProgressDto,
+};
+use crate::forms::main::{
+    CompleteUploadForm, CreateFileForm, CreateFolderForm, DeleteFileForm, DeleteFolderForm,
+    MoveEntryForm, MoveMatchingForm, PublishForm, RenameForm, RenamePatternForm, RestoreForm,
+    SaveTextForm, SwapEntriesForm, UploadFileForm,
+};
 use crate::models::config::ServerConfig;
-use crate::services::ServiceError;
-use crate::services::files::FileService;
+use crate::routes::error::ApiError;
+use crate::routes::render_template;
+use crate::services::files::{
+    DEFAULT_RECENT_LIMIT, FileService, MAX_RECENT_RESULTS, looks_textual,
+};
+use crate::services::quota::DownloadQuota;
+use crate::services::rate_limit::UploadRateLimiter;
+use crate::services::{ServiceError, ServiceResult};
 
 /// Query parameters for the [`index`] route.
 #[derive(Deserialize)]
 struct IndexQueryParams {
     /// Optional path relative to the user's upload directory.
     path: Option<String>,
+    /// Listing order: `name`, `natural` (numbers by value), `size` or
+    /// `modified`; the default order when omitted or unknown.
+    sort: Option<String>,
+    /// `asc` or `desc` for the chosen `sort`.
+    dir: Option<String>,
+    /// Entry filter (`dirs`, `images`, `ext:<extension>`); everything is
+    /// listed when omitted or unknown.
+    filter: Option<String>,
+    /// List entries whose name starts with `.` too.
+    #[serde(default)]
+    show_hidden: bool,
+}
+
+impl IndexQueryParams {
+    fn listing_sort(&self) -> ListingSort {
+        ListingSort::parse(self.sort.as_deref(), self.dir.as_deref())
+    }
+
+    fn entry_filter(&self) -> EntryFilter {
+        self.filter
+            .as_deref()
+            .map(EntryFilter::parse)
+            .unwrap_or_default()
+    }
+}
+
+/// Query parameters for the [`search`] route.
+#[derive(Deserialize)]
+struct SearchQueryParams {
+    /// Text every returned name contains, compared case-insensitively.
+    q: String,
+    /// Comma separated entry fields to return, all when omitted.
+    fields: Option<String>,
+}
+
+/// Query parameters for the [`recent_files`] route.
+#[derive(Deserialize)]
+struct RecentQueryParams {
+    /// Number of files to return, capped at [`MAX_RECENT_RESULTS`].
+    #[serde(default = "default_recent_limit")]
+    limit: usize,
+}
+
+fn default_recent_limit() -> usize {
+    DEFAULT_RECENT_LIMIT
+}
+
+/// Query parameters for the [`count_entries`] route.
+#[derive(Deserialize)]
+struct CountQueryParams {
+    /// Optional path relative to the user's upload directory.
+    path: Option<String>,
+    /// Optional entry filter (`dirs`, `images`, `ext:<extension>`).
+    filter: Option<String>,
+}
+
+/// Query parameters for recursive routes such as [`folder_depth`].
+#[derive(Deserialize)]
+struct DepthQueryParams {
+    /// Optional path relative to the user's upload directory.
+    path: Option<String>,
+    /// Requested traversal depth, clamped to `max_tree_depth`.
+    depth: Option<i64>,
+}
+
+/// Query parameters of [`hub_tree`].
+#[derive(Deserialize)]
+struct TreeQueryParams {
+    /// Requested tree depth, clamped to `max_tree_depth`.
+    max_depth: Option<i64>,
+}
+
+/// Query parameters addressing a single file inside the hub.
+#[derive(Deserialize)]
+struct FileQueryParams {
+    /// Optional path relative to the user's upload directory.
+    path: Option<String>,
+    /// Name of the file inside `path`.
+    name: String,
+    /// Confirms replacing an existing file when conflicts are rejected.
+    #[serde(default)]
+    overwrite: bool,
+    /// Show a downloaded file in the browser (image previews) instead of
+    /// saving it.
+    #[serde(default)]
+    inline: bool,
+}
+
+/// Query parameters for the [`shared_files`] route.
+#[derive(Deserialize)]
+struct SharedQueryParams {
+    /// Optional path relative to the shared root.
+    path: Option<String>,
+    /// File to download; the folder is listed when omitted.
+    name: Option<String>,
+    /// Comma separated entry fields to return, all when omitted.
+    fields: Option<String>,
+}
+
+/// Query parameters for admin routes addressing a whole hub.
+#[derive(Deserialize)]
+struct HubQueryParams {
+    hub_id: i32,
+}
+
+/// Query parameters for the [`chunk_manifest`] route.
+#[derive(Deserialize)]
+struct ChunksQueryParams {
+    /// Optional path relative to the user's upload directory.
+    path: Option<String>,
+    /// Name of the file inside `path`.
+    name: String,
+    /// Chunk size in bytes; a server default is used when omitted.
+    chunk_size: Option<u64>,
+}
+
+/// Query parameters for the [`file_head`] route.
+#[derive(Deserialize)]
+struct HeadQueryParams {
+    /// Optional path relative to the user's upload directory.
+    path: Option<String>,
+    /// Name of the file inside `path`.
+    name: String,
+    /// Number of leading bytes to return, capped by `max_head_bytes`.
+    bytes: Option<u64>,
+}
+
+/// Query parameters for the [`watch_entries`] route.
+#[derive(Deserialize)]
+struct WatchQueryParams {
+    /// Optional path relative to the user's upload directory.
+    path: Option<String>,
+    /// Seconds to wait for a change, capped at [`MAX_WATCH_TIMEOUT_SECS`].
+    timeout: Option<u64>,
+    /// Comma separated entry fields to return, all when omitted.
+    fields: Option<String>,
+}
+
+/// Longest a watch request may be held open.
+const MAX_WATCH_TIMEOUT_SECS: u64 = 60;
+
+/// Wait used when the client does not pass `timeout`.
+const DEFAULT_WATCH_TIMEOUT_SECS: u64 = 30;
+
+/// Query parameters for the [`sanitize_names`] admin route.
+#[derive(Deserialize)]
+struct SanitizeQueryParams {
+    /// Hub whose storage is cleaned up.
+    hub_id: i32,
+    /// Only report the planned renames.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Query parameters for write routes.
+#[derive(Deserialize)]
+struct UploadQueryParams {
+    /// Optional path relative to the user's upload directory.
+    path: Option<String>,
+    /// Confirms replacing an existing file when conflicts are rejected.
+    #[serde(default)]
+    overwrite: bool,
+}
+
+/// Query parameters of one part of a chunked upload.
+#[derive(Deserialize)]
+struct ChunkQueryParams {
+    /// Client chosen UUID shared by every part of the upload.
+    upload_id: String,
+    /// Zero-based position of this part.
+    index: u32,
+    /// Number of parts the file was split into.
+    total: u32,
+}
+
+/// Redirect back to the folder the user was browsing, preserving `path`.
+fn redirect_to_current(path: Option<&str>) -> HttpResponse {
+    match path.filter(|p| !p.is_empty()) {
+        Some(path) => redirect(&format!(
+            "/?path={}",
+            utf8_percent_encode(path, NON_ALPHANUMERIC)
+        )),
+        None => redirect("/"),
+    }
+}
+
+/// Breadcrumb trail for `relative`: a "Home" crumb for the hub root followed by
+/// one crumb per ancestor folder, each linking to its cumulative path.
+fn breadcrumbs(relative: &RelativePath) -> Vec<BreadcrumbDto> {
+    let mut crumbs = vec![BreadcrumbDto {
+        name: "Home".to_string(),
+        href: "/".to_string(),
+    }];
+    let mut cumulative = String::new();
+    for segment in relative.as_path().components() {
+        let name = segment.as_os_str().to_string_lossy();
+        if !cumulative.is_empty() {
+            cumulative.push('/');
+        }
+        cumulative.push_str(&name);
+        crumbs.push(BreadcrumbDto {
+            name: name.into_owned(),
+            href: format!(
+                "/?path={}",
+                utf8_percent_encode(&cumulative, NON_ALPHANUMERIC)
+            ),
+        });
+    }
+    crumbs
+}
+
+/// Serialize a JSON listing, projected onto `fields` when given. Unknown
+/// field names are rejected with 400.
+fn listing_json(entries: &[FileEntryDto], fields: Option<&str>) -> HttpResponse {
+    let Some(fields) = fields else {
+        return HttpResponse::Ok().json(entries);
+    };
+    match FieldProjection::parse(fields) {
+        Ok(projection) => HttpResponse::Ok().json(projection.apply(entries)),
+        Err(unknown) => HttpResponse::BadRequest().body(format!("Unknown field: {unknown}")),
+    }
 }
 
-fn file_service(server_config: &ServerConfig) -> FileService {
-    FileService::new(UploadRoot::from(
-        Path::new(&server_config.upload_path).to_path_buf(),
-    ))
+/// Whether the request is a plain browser form submission expecting a page
+/// (flash + redirect) rather than a status code or JSON as used by `filebrowser.js`.
+fn wants_html(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(|accept| accept.contains("text/html"))
+        .unwrap_or(false)
 }
 
 /// Display the contents of the current directory for the authenticated user.
@@ -35,9 +282,10 @@ fn file_service(server_config: &ServerConfig) -> FileService {
 pub async fn index(
     params: web::Query<IndexQueryParams>,
     user: AuthenticatedUser,
+    session: Session,
     flash_messages: IncomingFlashMessages,
     common_config: web::Data<CommonServerConfig>,
-    server_config: web::Data<ServerConfig>,
+    service: web::Data<FileService>,
     tera: web::Data<Tera>,
 ) -> impl Responder {
     let mut context = base_context(
@@ -46,13 +294,23 @@ pub async fn index(
         "index",
         &common_config.auth_service_url,
     );
+    context.insert("csrf_token", &csrf_token(&session));
 
-    let service = file_service(&server_config);
-
-    let entries: Vec<FileEntryDto> = match service.list_entries(&user, params.path.as_deref()) {
+    let entries: Vec<FileEntryDto> = match service
+        .list_entries(
+            &user,
+            params.path.as_deref(),
+            &params.entry_filter(),
+            params.listing_sort(),
+            params.show_hidden,
+        )
+        .await
+    {
         Ok(entries) => entries,
         Err(ServiceError::Unauthorized) => return redirect("/na"),
-        Err(ServiceError::InvalidPath) => return HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::InvalidPath) => {
+            return HttpResponse::BadRequest().body("Invalid path");
+        }
         Err(e) => {
             log::error!("Failed to list entries: {e:?}");
             return HttpResponse::InternalServerError().finish();
@@ -61,17 +319,61 @@ pub async fn index(
 
     context.insert("entries", &entries);
     context.insert("path", &params.path.clone().unwrap_or_default());
+    let relative = params
+        .path
+        .as_deref()
+        .and_then(|path| RelativePath::try_from_str(path).ok())
+        .unwrap_or_else(RelativePath::root);
+    context.insert("breadcrumbs", &breadcrumbs(&relative));
+    if let Some(quota_bytes) = service.quota_bytes() {
+        match service.hub_usage(&user) {
+            Ok(used_bytes) => context.insert(
+                "usage",
+                &HubUsageDto {
+                    used_bytes,
+                    quota_bytes,
+                },
+            ),
+            Err(e) => log::warn!("Failed to measure hub usage: {e:?}"),
+        }
+    }
 
     render_template(&tera, "main/index.html", &context)
 }
 
+/// JSON counterpart of [`index`] for API clients: the same listing with the
+/// requested `path`, and [`ApiError`] bodies instead of redirects.
+#[get("/api/files")]
+pub async fn list_files(
+    params: web::Query<IndexQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> Result<web::Json<FileListingDto>, ApiError> {
+    let entries = service
+        .list_entries(
+            &user,
+            params.path.as_deref(),
+            &params.entry_filter(),
+            params.listing_sort(),
+            params.show_hidden,
+        )
+        .await?;
+
+    Ok(web::Json(FileListingDto {
+        path: params.path.clone().unwrap_or_default(),
+        entries,
+    }))
+}
+
 /// Render the file browser fragment for embedding in other pages or services.
 #[get("/files/browser")]
 pub async fn file_browser(
     params: web::Query<IndexQueryParams>,
     user: AuthenticatedUser,
+    session: Session,
     flash_messages: IncomingFlashMessages,
     common_config: web::Data<CommonServerConfig>,
+    service: web::Data<FileService>,
     server_config: web::Data<ServerConfig>,
     tera: web::Data<Tera>,
 ) -> impl Responder {
@@ -81,75 +383,1941 @@ pub async fn file_browser(
         "file_browser",
         &common_config.auth_service_url,
     );
+    context.insert("csrf_token", &csrf_token(&session));
 
-    let service = file_service(&server_config);
+    context.insert("path", &params.path.clone().unwrap_or_default());
+    if server_config.stream_listings {
+        return match service.stream_entries(
+            &user,
+            params.path.as_deref(),
+            params.entry_filter(),
+            params.show_hidden,
+        ) {
+            Ok(entries) => stream_listing(tera.into_inner(), context, entries),
+            Err(ServiceError::Unauthorized) => redirect("/na"),
+            Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+            Err(e) => {
+                log::error!("Failed to list entries: {e:?}");
+                HttpResponse::InternalServerError().finish()
+            }
+        };
+    }
 
-    let entries: Vec<FileEntryDto> = match service.list_entries(&user, params.path.as_deref()) {
+    let entries: Vec<FileEntryDto> = match service
+        .list_entries(
+            &user,
+            params.path.as_deref(),
+            &params.entry_filter(),
+            params.listing_sort(),
+            params.show_hidden,
+        )
+        .await
+    {
         Ok(entries) => entries,
         Err(ServiceError::Unauthorized) => return redirect("/na"),
-        Err(ServiceError::InvalidPath) => return HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::InvalidPath) => {
+            return HttpResponse::BadRequest().body("Invalid path");
+        }
         Err(e) => {
             log::error!("Failed to list entries: {e:?}");
             return HttpResponse::InternalServerError().finish();
         }
     };
-
     context.insert("entries", &entries);
-    context.insert("path", &params.path.clone().unwrap_or_default());
 
     render_template(&tera, "components/file_browser.html", &context)
 }
 
+/// Search the whole hub by name. Browsers get the results as file browser
+/// cards linking into their folders, other clients a JSON listing.
+#[get("/search")]
+pub async fn search(
+    req: HttpRequest,
+    params: web::Query<SearchQueryParams>,
+    user: AuthenticatedUser,
+    session: Session,
+    flash_messages: IncomingFlashMessages,
+    common_config: web::Data<CommonServerConfig>,
+    service: web::Data<FileService>,
+    tera: web::Data<Tera>,
+) -> impl Responder {
+    let entries = match service.search(&user, &params.q) {
+        Ok(entries) => entries,
+        Err(ServiceError::Validation(msg)) => return HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::Unauthorized) => return redirect("/na"),
+        Err(e) => {
+            log::error!("Failed to search files: {e:?}");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    if !wants_html(&req) {
+        return listing_json(&entries, params.fields.as_deref());
+    }
+
+    let mut context = base_context(
+        &flash_messages,
+        &user,
+        "file_browser",
+        &common_config.auth_service_url,
+    );
+    context.insert("csrf_token", &csrf_token(&session));
+    context.insert("path", "");
+    context.insert("entries", &entries);
+    render_template(&tera, "components/file_browser.html", &context)
+}
+
+/// Placeholder the file browser template emits where streamed entries go.
+const STREAM_MARKER: &str = "<!--stream-entries-->";
+
+/// Send the file browser as a chunked body: the page shell up to the entry
+/// grid first, then one rendered card per entry as it is read, then the rest.
+fn stream_listing(
+    tera: std::sync::Arc<Tera>,
+    mut context: Context,
+    entries: Box<dyn Iterator<Item = FileEntryDto> + Send>,
+) -> HttpResponse {
+    context.insert("stream_marker", STREAM_MARKER);
+    let shell = match tera.render("components/file_browser.html", &context) {
+        Ok(shell) => shell,
+        Err(e) => {
+            log::error!("Failed to render file browser: {e:?}");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let Some((head, tail)) = shell.split_once(STREAM_MARKER) else {
+        log::error!("File browser template lacks the stream marker");
+        return HttpResponse::InternalServerError().finish();
+    };
+    let (head, tail) = (head.to_string(), tail.to_string());
+
+    let cards = entries.map(move |entry| {
+        let template = if entry.is_directory {
+            "components/folder.html"
+        } else {
+            "components/file.html"
+        };
+        let mut entry_context = context.clone();
+        entry_context.insert("entry", &entry);
+        tera.render(template, &entry_context)
+            .map(web::Bytes::from)
+            .map_err(|e| {
+                log::error!("Failed to render entry {}: {e:?}", entry.name);
+                std::io::Error::other("failed to render listing entry")
+            })
+    });
+    let chunks = std::iter::once(Ok(web::Bytes::from(head)))
+        .chain(cards)
+        .chain(std::iter::once(Ok(web::Bytes::from(tail))));
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .streaming(futures_util::stream::iter(chunks))
+}
+
 /// Handle a file upload and save it to the user's directory.
 #[post("/files/upload")]
 pub async fn upload_files(
-    params: web::Query<IndexQueryParams>,
+    req: HttpRequest,
+    params: web::Query<UploadQueryParams>,
     user: AuthenticatedUser,
     MultipartForm(form): MultipartForm<UploadFileForm>,
-    server_config: web::Data<ServerConfig>,
+    service: web::Data<FileService>,
+    rate_limiter: web::Data<Option<UploadRateLimiter>>,
 ) -> impl Responder {
+    if let Some(limiter) = rate_limiter.get_ref() {
+        if let Err(e) = limiter.check(&user.sub) {
+            return upload_response::<()>(&req, params.path.as_deref(), Err(e), "");
+        }
+    }
+
     let temp_file = form.file;
+    let extract = form.extract.is_some_and(|flag| *flag)
+        && temp_file
+            .file_name
+            .as_deref()
+            .is_some_and(FileService::is_zip_name);
 
-    let service = file_service(&server_config);
+    if extract {
+        let result = service
+            .extract_upload(&user, params.path.as_deref(), temp_file, params.overwrite)
+            .await;
+        return upload_response(&req, params.path.as_deref(), result, "Архив распакован.");
+    }
 
-    match service.persist_upload(
-        &user,
-        params.path.as_deref(),
-        temp_file.file_name.clone(),
-        temp_file,
-    ) {
-        Ok(_) => HttpResponse::Ok().finish(),
+    let result = service
+        .persist_upload(
+            &user,
+            params.path.as_deref(),
+            temp_file.file_name.clone(),
+            temp_file,
+            params.overwrite,
+        )
+        .await;
+
+    upload_response(&req, params.path.as_deref(), result, "Файл загружен.")
+}
+
+/// Store every `file` field of a multipart batch as soon as it arrives and
+/// answer with one JSON line per file, so clients can show progress while
+/// the rest of the batch is still being sent.
+#[post("/files/upload/stream")]
+pub async fn upload_files_streaming(
+    params: web::Query<UploadQueryParams>,
+    user: AuthenticatedUser,
+    payload: Multipart,
+    service: web::Data<FileService>,
+    rate_limiter: web::Data<Option<UploadRateLimiter>>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = service.upload_limits(&user)?.max_upload_bytes;
+    let UploadQueryParams { path, overwrite } = params.into_inner();
+    let upload = StreamingUpload {
+        payload,
+        service,
+        rate_limiter,
+        user,
+        path,
+        overwrite,
+        limit,
+        done: 0,
+        finished: false,
+    };
+
+    let lines = futures_util::stream::unfold(upload, |mut upload| async move {
+        let line = upload.next_line().await?;
+        let mut body = serde_json::to_vec(&line).unwrap_or_default();
+        body.push(b'\n');
+        Some((Ok::<_, std::io::Error>(web::Bytes::from(body)), upload))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines))
+}
+
+/// Batch of [`upload_files_streaming`] between two progress lines.
+struct StreamingUpload {
+    payload: Multipart,
+    service: web::Data<FileService>,
+    rate_limiter: web::Data<Option<UploadRateLimiter>>,
+    user: AuthenticatedUser,
+    path: Option<String>,
+    overwrite: bool,
+    limit: u64,
+    done: usize,
+    finished: bool,
+}
+
+impl StreamingUpload {
+    /// Store the next file of the batch and describe the outcome; `None` once
+    /// the batch is over. A broken multipart body ends the batch.
+    async fn next_line(&mut self) -> Option<UploadProgressDto> {
+        if self.finished {
+            return None;
+        }
+        loop {
+            let mut field = match self.payload.try_next().await {
+                Ok(Some(field)) => field,
+                Ok(None) => return None,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(UploadProgressDto {
+                        name: String::new(),
+                        done: self.done,
+                        stored: false,
+                        error: Some(e.to_string()),
+                        code: None,
+                    });
+                }
+            };
+            if field.name() != Some("file") {
+                continue;
+            }
+            let name = field
+                .content_disposition()
+                .and_then(|disposition| disposition.get_filename())
+                .map(str::to_string);
+            let result = self.store(&mut field, name.clone()).await;
+            self.done += 1;
+            return Some(match result {
+                Ok(info) => UploadProgressDto {
+                    name: info.name,
+                    done: self.done,
+                    stored: true,
+                    error: None,
+                    code: None,
+                },
+                Err(e) => {
+                    let err = ApiError::from(e);
+                    UploadProgressDto {
+                        name: name.unwrap_or_default(),
+                        done: self.done,
+                        stored: false,
+                        error: Some(err.to_string()),
+                        code: Some(err.code()),
+                    }
+                }
+            });
+        }
+    }
+
+    async fn store(&self, field: &mut Field, name: Option<String>) -> ServiceResult<FileInfoDto> {
+        if let Some(limiter) = self.rate_limiter.get_ref() {
+            limiter.check(&self.user.sub)?;
+        }
+        let file = receive_upload(field, self.limit).await?;
+        self.service
+            .persist_upload_file(&self.user, self.path.as_deref(), name, file, self.overwrite)
+            .await
+    }
+}
+
+/// Write one multipart field to a temp file, refusing it as soon as it grows
+/// past `limit`.
+async fn receive_upload(field: &mut Field, limit: u64) -> ServiceResult<NamedTempFile> {
+    let file = NamedTempFile::new().map_err(ServiceError::SaveFile)?;
+    let mut out = tokio::fs::File::from_std(file.reopen().map_err(ServiceError::SaveFile)?);
+    let mut received: u64 = 0;
+    while let Some(chunk) = field
+        .try_next()
+        .await
+        .map_err(|e| ServiceError::SaveFile(std::io::Error::other(e.to_string())))?
+    {
+        received = received.saturating_add(chunk.len() as u64);
+        if received > limit {
+            return Err(ServiceError::FileTooLarge { limit });
+        }
+        out.write_all(&chunk)
+            .await
+            .map_err(ServiceError::SaveFile)?;
+    }
+    out.flush().await.map_err(ServiceError::SaveFile)?;
+    Ok(file)
+}
+
+/// Receive one part of a chunked upload, sent as the multipart `file` field.
+#[post("/files/upload/chunk")]
+pub async fn upload_chunk(
+    params: web::Query<ChunkQueryParams>,
+    user: AuthenticatedUser,
+    MultipartForm(form): MultipartForm<UploadFileForm>,
+    service: web::Data<FileService>,
+) -> Result<HttpResponse, ApiError> {
+    service
+        .store_chunk(
+            &user,
+            &params.upload_id,
+            params.index,
+            params.total,
+            form.file.file,
+        )
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Join the parts of a chunked upload into a file of the current folder.
+#[post("/files/upload/complete")]
+pub async fn complete_upload(
+    req: HttpRequest,
+    params: web::Query<UploadQueryParams>,
+    user: AuthenticatedUser,
+    web::Form(form): web::Form<CompleteUploadForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let result = service
+        .complete_chunked_upload(
+            &user,
+            params.path.as_deref(),
+            &form.upload_id,
+            &form.name,
+            params.overwrite,
+        )
+        .await;
+
+    upload_response(&req, params.path.as_deref(), result, "Файл загружен.")
+}
+
+/// Browser forms get a flash message and a redirect back to the folder;
+/// programmatic clients get the result, such as the stored file's metadata,
+/// as JSON.
+fn upload_response<T: Serialize>(
+    req: &HttpRequest,
+    path: Option<&str>,
+    result: ServiceResult<T>,
+    success: &str,
+) -> HttpResponse {
+    if wants_html(req) {
+        match result {
+            Ok(_) => FlashMessage::success(success).send(),
+            Err(ServiceError::FileExists) => FlashMessage::warning(
+                "Файл с таким именем уже существует. Загрузите его ещё раз с подтверждением перезаписи (overwrite=true).",
+            )
+            .send(),
+            Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+                FlashMessage::error("Некорректный файл или путь для загрузки.").send()
+            }
+            Err(ServiceError::AspectRatioExceeded) => {
+                FlashMessage::error("Недопустимое соотношение сторон изображения.").send()
+            }
+            Err(ServiceError::DisallowedExtension) | Err(ServiceError::ForbiddenExtension) => {
+                FlashMessage::error("Недопустимое расширение файла.").send()
+            }
+            Err(ServiceError::ContentTypeMismatch) => {
+                FlashMessage::error("Содержимое файла не соответствует его расширению.").send()
+            }
+            Err(ServiceError::FileTooLarge { limit }) => {
+                FlashMessage::error(format!("Файл больше допустимых {limit} байт.")).send()
+            }
+            Err(ServiceError::Validation(msg)) => FlashMessage::error(msg).send(),
+            Err(ServiceError::TooManyUploads) => {
+                FlashMessage::warning("Слишком много загрузок, попробуйте через минуту.").send()
+            }
+            Err(ServiceError::QuotaExceeded { folder, limit }) => FlashMessage::error(format!(
+                "Превышена квота папки {folder} ({limit} байт)."
+            ))
+            .send(),
+            Err(ServiceError::Unauthorized) => FlashMessage::error("Недостаточно прав.").send(),
+            Err(e) => {
+                log::error!("File upload error: {e:?}");
+                FlashMessage::error("Не удалось загрузить файл.").send()
+            }
+        }
+        return redirect_to_current(path);
+    }
+
+    match result {
+        Ok(info) => HttpResponse::Ok().json(info),
+        Err(e) => ApiError::from(e).error_response(),
+    }
+}
+
+/// Atomically replace a live folder with its staging counterpart.
+#[post("/api/files/publish")]
+pub async fn publish_folder(
+    user: AuthenticatedUser,
+    web::Json(form): web::Json<PublishForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.publish(&user, &form) {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
         Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
-            HttpResponse::BadRequest().body("Некорректный файл или путь для загрузки.")
+            HttpResponse::BadRequest().body("Некорректная папка или путь.")
         }
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::FileExists) => HttpResponse::Conflict().finish(),
         Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
         Err(e) => {
-            log::error!("File upload error: {e:?}");
+            log::error!("Failed to publish folder: {e:?}");
             HttpResponse::InternalServerError().finish()
         }
     }
 }
 
-/// Create a new folder in the user's upload directory.
-#[post("/folder/create")]
-pub async fn create_folder(
+/// Rename a file or folder in place.
+#[post("/rename")]
+pub async fn rename_entry(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    web::Form(form): web::Form<RenameForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let result = service.rename(&user, form.path.as_deref(), &form.from, &form.to);
+
+    if wants_html(&req) {
+        match result {
+            Ok(()) => FlashMessage::success("Переименовано.").send(),
+            Err(ServiceError::FileExists) => {
+                FlashMessage::warning("Файл или папка с таким именем уже существует.").send()
+            }
+            Err(ServiceError::NotFound) => FlashMessage::warning("Файл не найден.").send(),
+            Err(ServiceError::Validation(msg)) => FlashMessage::error(msg).send(),
+            Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+                FlashMessage::error("Некорректное имя или путь.").send()
+            }
+            Err(ServiceError::Unauthorized) => FlashMessage::error("Недостаточно прав.").send(),
+            Err(e) => {
+                log::error!("Failed to rename entry: {e:?}");
+                FlashMessage::error("Не удалось переименовать.").send()
+            }
+        }
+        return redirect_to_current(form.path.as_deref());
+    }
+
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(ServiceError::FileExists) => {
+            HttpResponse::Conflict().body("Файл или папка с таким именем уже существует.")
+        }
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+            HttpResponse::BadRequest().body("Некорректное имя или путь.")
+        }
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to rename entry: {e:?}");
+            HttpResponse::InternalServerError().body("Не удалось переименовать.")
+        }
+    }
+}
+
+/// Move a file or folder into another folder of the hub.
+#[post("/move")]
+pub async fn move_entry(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    web::Form(form): web::Form<MoveEntryForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let result = service.move_entry(
+        &user,
+        form.from_path.as_deref(),
+        form.to_path.as_deref(),
+        &form.name,
+    );
+
+    if wants_html(&req) {
+        match result {
+            Ok(()) => FlashMessage::success("Перемещено.").send(),
+            Err(ServiceError::FileExists) => {
+                FlashMessage::warning("В папке назначения уже есть файл или папка с таким именем.")
+                    .send()
+            }
+            Err(ServiceError::NotFound) => FlashMessage::warning("Файл не найден.").send(),
+            Err(ServiceError::Validation(msg)) => FlashMessage::error(msg).send(),
+            Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+                FlashMessage::error("Некорректное имя или папка назначения.").send()
+            }
+            Err(ServiceError::Unauthorized) => FlashMessage::error("Недостаточно прав.").send(),
+            Err(e) => {
+                log::error!("Failed to move entry: {e:?}");
+                FlashMessage::error("Не удалось переместить.").send()
+            }
+        }
+        return redirect_to_current(form.from_path.as_deref());
+    }
+
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(ServiceError::FileExists) => HttpResponse::Conflict()
+            .body("В папке назначения уже есть файл или папка с таким именем."),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+            HttpResponse::BadRequest().body("Некорректное имя или папка назначения.")
+        }
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to move entry: {e:?}");
+            HttpResponse::InternalServerError().body("Не удалось переместить.")
+        }
+    }
+}
+
+/// Copy a file or folder into another folder of the hub.
+#[post("/copy")]
+pub async fn copy_entry(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    web::Form(form): web::Form<MoveEntryForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let result = service.copy_entry(
+        &user,
+        form.from_path.as_deref(),
+        form.to_path.as_deref(),
+        &form.name,
+    );
+
+    if wants_html(&req) {
+        match result {
+            Ok(()) => FlashMessage::success("Скопировано.").send(),
+            Err(ServiceError::FileExists) => {
+                FlashMessage::warning("В папке назначения уже есть файл или папка с таким именем.")
+                    .send()
+            }
+            Err(ServiceError::CopyConflict(paths)) => FlashMessage::warning(format!(
+                "В папке назначения уже есть: {}. Ничего не скопировано.",
+                paths.join(", ")
+            ))
+            .send(),
+            Err(ServiceError::NotFound) => FlashMessage::warning("Файл не найден.").send(),
+            Err(ServiceError::Validation(msg)) => FlashMessage::error(msg).send(),
+            Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+                FlashMessage::error("Некорректное имя или папка назначения.").send()
+            }
+            Err(ServiceError::QuotaExceeded { folder, limit }) => {
+                FlashMessage::error(format!("Превышена квота папки {folder} ({limit} байт)."))
+                    .send()
+            }
+            Err(ServiceError::Unauthorized) => FlashMessage::error("Недостаточно прав.").send(),
+            Err(e) => {
+                log::error!("Failed to copy entry: {e:?}");
+                FlashMessage::error("Не удалось скопировать.").send()
+            }
+        }
+        return redirect_to_current(form.from_path.as_deref());
+    }
+
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => ApiError::from(e).error_response(),
+    }
+}
+
+/// Delete a file from the current folder.
+#[post("/files/delete")]
+pub async fn delete_file(
+    req: HttpRequest,
     params: web::Query<IndexQueryParams>,
     user: AuthenticatedUser,
-    web::Form(form): web::Form<CreateFolderForm>,
-    server_config: web::Data<ServerConfig>,
+    web::Form(form): web::Form<DeleteFileForm>,
+    service: web::Data<FileService>,
 ) -> impl Responder {
-    let service = file_service(&server_config);
+    let result = service.delete_file(&user, params.path.as_deref(), &form.name);
+
+    if wants_html(&req) {
+        match result {
+            Ok(()) => FlashMessage::success("Файл удалён.").send(),
+            Err(ServiceError::NotFound) => FlashMessage::warning("Файл не найден.").send(),
+            Err(ServiceError::Validation(msg)) => FlashMessage::error(msg).send(),
+            Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+                FlashMessage::error("Некорректный файл или путь.").send()
+            }
+            Err(ServiceError::Unauthorized) => FlashMessage::error("Недостаточно прав.").send(),
+            Err(e) => {
+                log::error!("Failed to delete file: {e:?}");
+                FlashMessage::error("Не удалось удалить файл.").send()
+            }
+        }
+        return redirect_to_current(params.path.as_deref());
+    }
 
-    match service.create_folder(&user, params.path.as_deref(), &form) {
-        Ok(()) => HttpResponse::Created().finish(),
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
         Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
-        Err(ServiceError::InvalidPath) => {
-            HttpResponse::BadRequest().body("Недопустимый путь для загрузки файла.")
+        Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+            HttpResponse::BadRequest().body("Некорректный файл или путь.")
         }
-        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().body("Недостаточно прав."),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
         Err(e) => {
-            log::error!("Failed to create upload directory: {e:?}");
-            HttpResponse::InternalServerError().body("Не удалось создать папку")
+            log::error!("Failed to delete file: {e:?}");
+            HttpResponse::InternalServerError().body("Не удалось удалить файл.")
+        }
+    }
+}
+
+/// Delete several files of the current folder, named by repeated `name`
+/// form fields. Browsers get a summary flash message, other clients the
+/// result of every name as JSON.
+#[post("/files/delete-bulk")]
+pub async fn delete_many(
+    req: HttpRequest,
+    params: web::Query<IndexQueryParams>,
+    user: AuthenticatedUser,
+    web::Form(fields): web::Form<Vec<(String, String)>>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let names = fields
+        .into_iter()
+        .filter(|(key, _)| key == "name")
+        .map(|(_, value)| value)
+        .collect();
+    let result = service.delete_many(&user, params.path.as_deref(), names);
+
+    if wants_html(&req) {
+        match result {
+            Ok(results) => {
+                let deleted = results.iter().filter(|r| r.deleted).count();
+                let failed: Vec<&str> = results
+                    .iter()
+                    .filter(|r| !r.deleted)
+                    .map(|r| r.name.as_str())
+                    .collect();
+                if failed.is_empty() {
+                    FlashMessage::success(format!("Удалено файлов: {deleted}.")).send();
+                } else {
+                    FlashMessage::warning(format!(
+                        "Удалено файлов: {deleted}. Не удалось удалить: {}.",
+                        failed.join(", ")
+                    ))
+                    .send();
+                }
+            }
+            Err(ServiceError::Validation(msg)) => FlashMessage::error(msg).send(),
+            Err(ServiceError::InvalidPath) => FlashMessage::error("Некорректный путь.").send(),
+            Err(ServiceError::Unauthorized) => FlashMessage::error("Недостаточно прав.").send(),
+            Err(e) => {
+                log::error!("Failed to delete files: {e:?}");
+                FlashMessage::error("Не удалось удалить файлы.").send()
+            }
+        }
+        return redirect_to_current(params.path.as_deref());
+    }
+
+    match result {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(e) => ApiError::from(e).error_response(),
+    }
+}
+
+/// Delete a folder, optionally with its contents.
+#[post("/folder/delete")]
+pub async fn delete_folder(
+    req: HttpRequest,
+    params: web::Query<IndexQueryParams>,
+    user: AuthenticatedUser,
+    web::Form(form): web::Form<DeleteFolderForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let result = service.delete_folder(&user, params.path.as_deref(), &form.name, form.recursive);
+
+    if wants_html(&req) {
+        match result {
+            Ok(()) => FlashMessage::success("Папка удалена.").send(),
+            Err(ServiceError::FolderNotEmpty) => {
+                FlashMessage::warning("Папка не пуста. Подтвердите удаление вместе с содержимым.")
+                    .send()
+            }
+            Err(ServiceError::NotFound) => FlashMessage::warning("Папка не найдена.").send(),
+            Err(ServiceError::Validation(msg)) => FlashMessage::error(msg).send(),
+            Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+                FlashMessage::error("Некорректная папка или путь.").send()
+            }
+            Err(ServiceError::Unauthorized) => FlashMessage::error("Недостаточно прав.").send(),
+            Err(e) => {
+                log::error!("Failed to delete folder: {e:?}");
+                FlashMessage::error("Не удалось удалить папку.").send()
+            }
+        }
+        return redirect_to_current(params.path.as_deref());
+    }
+
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(ServiceError::FolderNotEmpty) => HttpResponse::Conflict().body("Папка не пуста."),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+            HttpResponse::BadRequest().body("Некорректная папка или путь.")
+        }
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to delete folder: {e:?}");
+            HttpResponse::InternalServerError().body("Не удалось удалить папку.")
+        }
+    }
+}
+
+/// Create a new folder in the user's upload directory.
+#[post("/folder/create")]
+pub async fn create_folder(
+    req: HttpRequest,
+    params: web::Query<IndexQueryParams>,
+    user: AuthenticatedUser,
+    web::Form(form): web::Form<CreateFolderForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let result = service
+        .create_folder(&user, params.path.as_deref(), &form)
+        .await;
+
+    if wants_html(&req) {
+        match result {
+            Ok(_) => FlashMessage::success("Папка создана.").send(),
+            Err(ServiceError::FileExists) => {
+                FlashMessage::warning("Папка с таким именем уже существует.").send()
+            }
+            Err(ServiceError::Validation(msg)) => FlashMessage::error(msg).send(),
+            Err(ServiceError::InvalidPath) => {
+                FlashMessage::error("Недопустимый путь для загрузки файла.").send()
+            }
+            Err(ServiceError::Unauthorized) => FlashMessage::error("Недостаточно прав.").send(),
+            Err(e) => {
+                log::error!("Failed to create upload directory: {e:?}");
+                FlashMessage::error("Не удалось создать папку").send()
+            }
+        }
+        return redirect_to_current(params.path.as_deref());
+    }
+
+    match result {
+        Ok(true) => HttpResponse::Created().finish(),
+        Ok(false) => HttpResponse::Ok().finish(),
+        Err(ServiceError::FileExists) => {
+            HttpResponse::Conflict().body("Папка с таким именем уже существует.")
+        }
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::InvalidPath) => {
+            HttpResponse::BadRequest().body("Недопустимый путь для загрузки файла.")
+        }
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().body("Недостаточно прав."),
+        Err(e) => {
+            log::error!("Failed to create upload directory: {e:?}");
+            HttpResponse::InternalServerError().body("Не удалось создать папку")
+        }
+    }
+}
+
+/// Create an empty file in the user's current folder.
+#[post("/files/create")]
+pub async fn create_file(
+    req: HttpRequest,
+    params: web::Query<IndexQueryParams>,
+    user: AuthenticatedUser,
+    web::Form(form): web::Form<CreateFileForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let result = service.create_file(&user, params.path.as_deref(), &form.name);
+
+    if wants_html(&req) {
+        match result {
+            Ok(_) => FlashMessage::success("Файл создан.").send(),
+            Err(ServiceError::FileExists) => {
+                FlashMessage::warning("Файл с таким именем уже существует.").send()
+            }
+            Err(ServiceError::Validation(msg)) => FlashMessage::error(msg).send(),
+            Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+                FlashMessage::error("Некорректное имя файла или путь.").send()
+            }
+            Err(ServiceError::DisallowedExtension) | Err(ServiceError::ForbiddenExtension) => {
+                FlashMessage::error("Недопустимое расширение файла.").send()
+            }
+            Err(ServiceError::NotFound) => FlashMessage::error("Папка не найдена.").send(),
+            Err(ServiceError::Unauthorized) => FlashMessage::error("Недостаточно прав.").send(),
+            Err(e) => {
+                log::error!("Failed to create file: {e:?}");
+                FlashMessage::error("Не удалось создать файл").send()
+            }
+        }
+        return redirect_to_current(params.path.as_deref());
+    }
+
+    match result {
+        Ok(entry) => HttpResponse::Created().json(entry),
+        Err(ServiceError::FileExists) => {
+            HttpResponse::Conflict().body("Файл с таким именем уже существует.")
+        }
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+            HttpResponse::BadRequest().body("Некорректное имя файла или путь.")
+        }
+        Err(ServiceError::DisallowedExtension) | Err(ServiceError::ForbiddenExtension) => {
+            HttpResponse::BadRequest().body("Недопустимое расширение файла.")
+        }
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().body("Папка не найдена."),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().body("Недостаточно прав."),
+        Err(e) => {
+            log::error!("Failed to create file: {e:?}");
+            HttpResponse::InternalServerError().body("Не удалось создать файл")
+        }
+    }
+}
+
+/// Report hub storage usage grouped by file category.
+#[get("/api/files/breakdown")]
+pub async fn storage_breakdown(
+    params: web::Query<DepthQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.storage_breakdown(&user, params.depth) {
+        Ok(breakdown) => HttpResponse::Ok().json(breakdown),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to compute storage breakdown: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Count entries of the folder at `path` by kind.
+#[get("/api/files/count")]
+pub async fn count_entries(
+    params: web::Query<CountQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let filter = params
+        .filter
+        .as_deref()
+        .map(EntryFilter::parse)
+        .unwrap_or_default();
+
+    match service.count_entries(&user, params.path.as_deref(), &filter) {
+        Ok(counts) => HttpResponse::Ok().json(counts),
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to count entries: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Count the files of the folder at `path` by extension for the filter bar.
+#[get("/api/files/extension-counts")]
+pub async fn extension_counts(
+    params: web::Query<IndexQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.extension_counts(&user, params.path.as_deref()) {
+        Ok(counts) => HttpResponse::Ok().json(counts),
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to count extensions: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Report how deeply folders are nested below `path`.
+#[get("/api/files/depth")]
+pub async fn folder_depth(
+    params: web::Query<DepthQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.folder_depth(&user, params.path.as_deref(), params.depth) {
+        Ok(depth) => HttpResponse::Ok().json(depth),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to compute folder depth: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// List the most recently modified files of the whole hub, newest first.
+#[get("/api/recent")]
+pub async fn recent_files(
+    params: web::Query<RecentQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> Result<web::Json<Vec<FileEntryDto>>, ApiError> {
+    Ok(web::Json(service.recent(&user, params.limit)?))
+}
+
+/// Return the folders and files of the whole hub as a nested tree.
+#[get("/api/tree")]
+pub async fn hub_tree(
+    params: web::Query<TreeQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.list_tree(&user, params.max_depth) {
+        Ok(tree) => HttpResponse::Ok().json(tree),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to build hub tree: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Save text content posted as JSON into a file and return its metadata.
+#[post("/api/files/text")]
+pub async fn save_text_file(
+    params: web::Query<FileQueryParams>,
+    user: AuthenticatedUser,
+    web::Json(form): web::Json<SaveTextForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.save_text(
+        &user,
+        params.path.as_deref(),
+        &params.name,
+        &form.content,
+        params.overwrite,
+    ) {
+        Ok(entry) => HttpResponse::Ok().json(entry),
+        Err(ServiceError::FileExists) => {
+            HttpResponse::Conflict().body("Файл с таким именем уже существует.")
+        }
+        Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+            HttpResponse::BadRequest().body("Некорректный файл или путь для загрузки.")
+        }
+        Err(ServiceError::ForbiddenExtension) => {
+            HttpResponse::BadRequest().body("Недопустимое расширение файла.")
+        }
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to save text file: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Atomically exchange two files inside the folder at `path`.
+#[post("/api/files/swap")]
+pub async fn swap_entries(
+    params: web::Query<IndexQueryParams>,
+    user: AuthenticatedUser,
+    web::Json(form): web::Json<SwapEntriesForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.swap_entries(&user, params.path.as_deref(), &form.name_a, &form.name_b) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+            HttpResponse::BadRequest().body("Некорректный файл или путь.")
+        }
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to swap entries: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Move a soft-deleted entry back out of the trash.
+#[post("/api/trash/restore")]
+pub async fn restore_trashed(
+    user: AuthenticatedUser,
+    web::Json(form): web::Json<RestoreForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.restore(&user, &form.path) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+            HttpResponse::BadRequest().body("Некорректный путь в корзине.")
+        }
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::FileExists) => HttpResponse::Conflict().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to restore entry: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Permanently remove everything in the hub's trash.
+#[post("/api/trash/empty")]
+pub async fn empty_trash(
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.empty_trash(&user) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to empty trash: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Long-poll a folder: respond with the fresh listing once it changes, or
+/// `304 Not Modified` when the timeout elapses first.
+#[get("/api/files/watch")]
+pub async fn watch_entries(
+    params: web::Query<WatchQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let timeout = Duration::from_secs(
+        params
+            .timeout
+            .unwrap_or(DEFAULT_WATCH_TIMEOUT_SECS)
+            .min(MAX_WATCH_TIMEOUT_SECS),
+    );
+    let result = match service.watch(&user, params.path.as_deref()) {
+        Ok(pending) => match web::block(move || pending.wait(timeout)).await {
+            Ok(changed) => changed,
+            Err(e) => {
+                log::error!("Watch task failed: {e:?}");
+                return HttpResponse::InternalServerError().finish();
+            }
+        },
+        Err(e) => Err(e),
+    };
+
+    let result = match result {
+        Ok(true) => service
+            .list_entries(
+                &user,
+                params.path.as_deref(),
+                &EntryFilter::All,
+                ListingSort::Default,
+                false,
+            )
+            .await
+            .map(Some),
+        Ok(false) => Ok(None),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(Some(entries)) => listing_json(&entries, params.fields.as_deref()),
+        Ok(None) => HttpResponse::NotModified().finish(),
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::TooManyWatchers) => HttpResponse::TooManyRequests().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to watch folder: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Report the upload limits of the user's hub.
+#[get("/api/limits")]
+pub async fn upload_limits(
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.upload_limits(&user) {
+        Ok(limits) => HttpResponse::Ok().json(limits),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to report upload limits: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Return the aggregated metadata of a single file.
+#[get("/api/files/info")]
+pub async fn file_info(
+    params: web::Query<FileQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.file_info(&user, params.path.as_deref(), &params.name) {
+        Ok(info) => HttpResponse::Ok().json(info),
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::InvalidFileName) => HttpResponse::BadRequest().body("Invalid file name"),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to read file info: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Return the details of one file or folder for the details panel.
+#[get("/files/stat")]
+pub async fn stat_entry(
+    params: web::Query<FileQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.stat(&user, params.path.as_deref(), &params.name) {
+        Ok(stat) => HttpResponse::Ok().json(stat),
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::InvalidFileName) => HttpResponse::BadRequest().body("Invalid file name"),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to stat entry: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Return container, codec and duration metadata of a video for the player.
+#[get("/api/files/media-info")]
+pub async fn media_info(
+    params: web::Query<FileQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.media_info(&user, params.path.as_deref(), &params.name) {
+        Ok(info) => HttpResponse::Ok().json(info),
+        Err(ServiceError::UnsupportedMedia) => HttpResponse::UnsupportedMediaType().finish(),
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::InvalidFileName) => HttpResponse::BadRequest().body("Invalid file name"),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to read media info: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// `Content-Disposition` offering `file_name` as a download, with the UTF-8
+/// form for names browsers cannot take as plain ASCII.
+fn attachment(file_name: &str) -> ContentDisposition {
+    content_disposition(DispositionType::Attachment, file_name)
+}
+
+fn content_disposition(disposition: DispositionType, file_name: &str) -> ContentDisposition {
+    let mut parameters = vec![DispositionParam::Filename(file_name.to_string())];
+    if !file_name.is_ascii() {
+        parameters.push(DispositionParam::FilenameExt(header::ExtendedValue {
+            charset: header::Charset::Ext("UTF-8".into()),
+            language_tag: None,
+            value: file_name.as_bytes().to_vec(),
+        }));
+    }
+    ContentDisposition {
+        disposition,
+        parameters,
+    }
+}
+
+/// Weak `ETag` of a file, derived from its size and modification time.
+fn weak_etag(meta: &std::fs::Metadata) -> Option<header::EntityTag> {
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(header::EntityTag::new_weak(format!(
+        "{:x}-{:x}",
+        meta.len(),
+        modified.as_nanos()
+    )))
+}
+
+/// Send `file` as the attachment `name` with `Last-Modified` and a weak
+/// `ETag`, answering `304 Not Modified` when the client's `If-None-Match` or,
+/// without one, its `If-Modified-Since` shows its copy is current.
+fn conditional_download(
+    req: &HttpRequest,
+    file: NamedFile,
+    disposition: ContentDisposition,
+) -> HttpResponse {
+    let etag = weak_etag(file.metadata());
+    let modified = file.metadata().modified().ok();
+
+    let not_modified = match req.get_header::<header::IfNoneMatch>() {
+        Some(header::IfNoneMatch::Any) => true,
+        Some(header::IfNoneMatch::Items(tags)) => etag
+            .as_ref()
+            .is_some_and(|etag| tags.iter().any(|tag| tag.weak_eq(etag))),
+        None => match (modified, req.get_header::<header::IfModifiedSince>()) {
+            (Some(modified), Some(header::IfModifiedSince(since))) => {
+                // HTTP dates have second precision.
+                let secs = |time: SystemTime| {
+                    time.duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0)
+                };
+                secs(modified) <= secs(since.into())
+            }
+            _ => false,
+        },
+    };
+
+    let mut response = if not_modified {
+        let mut response = HttpResponse::NotModified();
+        if let Some(modified) = modified {
+            response.insert_header(header::LastModified(modified.into()));
+        }
+        response.finish()
+    } else {
+        file.use_etag(false)
+            .set_content_disposition(disposition)
+            .into_response(req)
+    };
+    if let Some(value) = etag.and_then(|etag| header::HeaderValue::from_str(&etag.to_string()).ok())
+    {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+/// Download a folder of the user's hub as a zip archive named after it.
+#[get("/folder/download")]
+pub async fn download_folder(
+    params: web::Query<IndexQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+    quota: web::Data<Option<DownloadQuota>>,
+) -> impl Responder {
+    let Ok(hub_id) = service.user_hub(&user) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if let Some(quota) = quota.get_ref() {
+        if quota.check(&hub_id).is_err() {
+            return HttpResponse::TooManyRequests()
+                .body("Превышен лимит скачивания для этого периода.");
+        }
+    }
+
+    let archive = {
+        let service = service.clone();
+        let path = params.path.clone();
+        web::block(move || service.zip_folder(&user, path.as_deref())).await
+    };
+    let archive = match archive {
+        Ok(archive) => archive,
+        Err(e) => {
+            log::error!("Zip task failed: {e:?}");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    match archive {
+        Ok(archive) => {
+            // Another download may have used up the budget while zipping.
+            if let Some(quota) = quota.get_ref() {
+                if quota.try_record(&hub_id, archive.len() as u64).is_err() {
+                    return HttpResponse::TooManyRequests()
+                        .body("Превышен лимит скачивания для этого периода.");
+                }
+            }
+            let folder = params
+                .path
+                .as_deref()
+                .and_then(|path| path.rsplit('/').find(|segment| !segment.is_empty()))
+                .unwrap_or("files");
+            HttpResponse::Ok()
+                .content_type("application/zip")
+                .insert_header(attachment(&format!("{folder}.zip")))
+                .body(archive)
+        }
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to zip folder: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Show a small text file of the user's hub as plain text. Binary and
+/// oversized files are answered with an [`ApiError`] so the UI can offer a
+/// download instead.
+#[get("/files/view")]
+pub async fn view_file(
+    params: web::Query<FileQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+    server_config: web::Data<ServerConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let text = service.read_text(
+        &user,
+        params.path.as_deref(),
+        &params.name,
+        server_config.max_view_bytes,
+    )?;
+    Ok(HttpResponse::Ok()
+        .content_type(mime::TEXT_PLAIN_UTF_8)
+        .body(text))
+}
+
+/// Save the text posted by the in-browser editor over a file of the user's
+/// hub, up to `max_save_bytes`.
+#[post("/files/save")]
+pub async fn save_file(
+    params: web::Query<FileQueryParams>,
+    user: AuthenticatedUser,
+    web::Json(form): web::Json<SaveTextForm>,
+    service: web::Data<FileService>,
+    server_config: web::Data<ServerConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let entry = service.write_text(
+        &user,
+        params.path.as_deref(),
+        &params.name,
+        &form.content,
+        server_config.max_save_bytes,
+    )?;
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+/// Download a file of the user's hub as an attachment, or with `inline=true`
+/// show it in the browser as file and folder previews do. Counts towards the
+/// hub's download quota; only attachments count towards the file's download
+/// count.
+#[get("/files/download")]
+pub async fn download_file(
+    req: HttpRequest,
+    params: web::Query<FileQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+    quota: web::Data<Option<DownloadQuota>>,
+) -> impl Responder {
+    let Ok(hub_id) = service.user_hub(&user) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if let Some(quota) = quota.get_ref() {
+        if quota.check(&hub_id).is_err() {
+            return HttpResponse::TooManyRequests()
+                .body("Превышен лимит скачивания для этого периода.");
+        }
+    }
+
+    let file = service
+        .download_file(&user, params.path.as_deref(), &params.name)
+        .and_then(|path| NamedFile::open(path).map_err(|_| ServiceError::NotFound));
+    match file {
+        Ok(file) => {
+            let size = file.metadata().len();
+            let file = if file.path().extension().is_none() {
+                file.set_content_type(service.default_mime_extensionless().clone())
+            } else {
+                file
+            };
+            let disposition = if params.inline {
+                content_disposition(DispositionType::Inline, &params.name)
+            } else {
+                attachment(&params.name)
+            };
+            let response = conditional_download(&req, file, disposition);
+            if response.status().is_success() {
+                if let Some(quota) = quota.get_ref() {
+                    quota.record(&hub_id, size);
+                }
+                if !params.inline {
+                    service.record_download(&user, params.path.as_deref(), &params.name);
+                }
+            }
+            response
+        }
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::InvalidFileName) => HttpResponse::BadRequest().body("Invalid file name"),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to download file: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Return the first bytes of a file for previews (logs, CSV).
+#[get("/api/files/head")]
+pub async fn file_head(
+    params: web::Query<HeadQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.read_head(&user, params.path.as_deref(), &params.name, params.bytes) {
+        Ok(head) => {
+            let content_type = if looks_textual(&head) {
+                "text/plain; charset=utf-8"
+            } else {
+                "application/octet-stream"
+            };
+            HttpResponse::Ok().content_type(content_type).body(head)
+        }
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::InvalidFileName) => HttpResponse::BadRequest().body("Invalid file name"),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to read file head: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Describe a file as hashed chunks for verified range downloads.
+#[get("/api/files/chunks")]
+pub async fn chunk_manifest(
+    params: web::Query<ChunksQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.chunk_manifest(
+        &user,
+        params.path.as_deref(),
+        &params.name,
+        params.chunk_size,
+    ) {
+        Ok(manifest) => HttpResponse::Ok().json(manifest),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::InvalidFileName) => HttpResponse::BadRequest().body("Invalid file name"),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to build chunk manifest: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Remove every empty folder of a hub.
+#[post("/api/admin/prune-empty")]
+pub async fn prune_empty(
+    params: web::Query<HubQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let Ok(hub_id) = HubId::try_new(params.hub_id) else {
+        return HttpResponse::BadRequest().body("Invalid hub id");
+    };
+    match service.prune_empty(&user, hub_id) {
+        Ok(removed) => HttpResponse::Ok().json(removed),
+        Err(ServiceError::Unauthorized) => HttpResponse::Forbidden().finish(),
+        Err(e) => {
+            log::error!("Failed to prune empty folders: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Rename entries of a hub whose names break the file name rules.
+#[post("/api/admin/sanitize")]
+pub async fn sanitize_names(
+    params: web::Query<SanitizeQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let Ok(hub_id) = HubId::try_new(params.hub_id) else {
+        return HttpResponse::BadRequest().body("Invalid hub id");
+    };
+    match service.sanitize_names(&user, hub_id, params.dry_run) {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(ServiceError::Unauthorized) => HttpResponse::Forbidden().finish(),
+        Err(e) => {
+            log::error!("Failed to sanitize names: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Inspect an uploaded zip archive and report on its safety.
+#[post("/api/files/inspect-archive")]
+pub async fn inspect_archive(
+    user: AuthenticatedUser,
+    MultipartForm(form): MultipartForm<UploadFileForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.inspect_archive(&user, form.file) {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to inspect archive: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Rename files in a folder using a regex find/replace.
+#[post("/api/files/rename-pattern")]
+pub async fn rename_by_pattern(
+    user: AuthenticatedUser,
+    web::Json(form): web::Json<RenamePatternForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.rename_by_pattern(&user, form.path.as_deref(), &form.find, &form.replace) {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to rename by pattern: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Move every file of a folder matching a glob and/or age filter into another
+/// folder.
+#[post("/api/files/move-matching")]
+pub async fn move_matching(
+    user: AuthenticatedUser,
+    web::Json(form): web::Json<MoveMatchingForm>,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    match service.move_matching(&user, &form) {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(ServiceError::Validation(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to move matching files: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// List or download from the shared library available to every hub.
+#[get("/api/files/shared")]
+pub async fn shared_files(
+    req: HttpRequest,
+    params: web::Query<SharedQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let result = match params.name.as_deref() {
+        Some(name) => service
+            .shared_file(&user, params.path.as_deref(), name)
+            .and_then(|path| {
+                let extensionless = path.extension().is_none();
+                NamedFile::open(path)
+                    .map(|file| {
+                        if extensionless {
+                            file.set_content_type(service.default_mime_extensionless().clone())
+                        } else {
+                            file
+                        }
+                    })
+                    .map_err(|_| ServiceError::NotFound)
+            })
+            .map(|file| file.into_response(&req)),
+        None => service
+            .list_shared(&user, params.path.as_deref())
+            .map(|entries| listing_json(&entries, params.fields.as_deref())),
+    };
+
+    match result {
+        Ok(response) => response,
+        Err(ServiceError::InvalidPath) => HttpResponse::BadRequest().body("Invalid path"),
+        Err(ServiceError::InvalidFileName) => HttpResponse::BadRequest().body("Invalid file name"),
+        Err(ServiceError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(ServiceError::Unauthorized) => HttpResponse::Unauthorized().finish(),
+        Err(e) => {
+            log::error!("Failed to read shared library: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// The shared library is read-only; refuse every write method explicitly.
+#[route(
+    "/api/files/shared",
+    method = "POST",
+    method = "PUT",
+    method = "DELETE"
+)]
+pub async fn reject_shared_write() -> impl Responder {
+    HttpResponse::MethodNotAllowed()
+        .insert_header((header::ALLOW, "GET"))
+        .body("Общая библиотека доступна только для чтения")
+}
+
+/// Delete orphaned thumbnails of a hub and regenerate stale ones.
+#[post("/api/admin/thumbnails/repair")]
+pub async fn repair_thumbnails(
+    params: web::Query<HubQueryParams>,
+    user: AuthenticatedUser,
+    service: web::Data<FileService>,
+) -> impl Responder {
+    let Ok(hub_id) = HubId::try_new(params.hub_id) else {
+        return HttpResponse::BadRequest().body("Invalid hub id");
+    };
+    match service.repair_thumbnails(&user, hub_id) {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(ServiceError::Unauthorized) => HttpResponse::Forbidden().finish(),
+        Err(e) => {
+            log::error!("Failed to repair thumbnails: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::App;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+
+    use super::*;
+
+    fn location(response: &HttpResponse) -> &str {
+        response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .unwrap()
+    }
+
+    #[test]
+    fn redirect_to_current_preserves_nested_path() {
+        let response = redirect_to_current(Some("alpha/beta"));
+        assert_eq!(location(&response), "/?path=alpha%2Fbeta");
+    }
+
+    #[test]
+    fn breadcrumbs_link_every_ancestor() {
+        let crumbs = breadcrumbs(&RelativePath::try_from_str("docs/отчёты 2024").unwrap());
+        let pairs: Vec<(&str, &str)> = crumbs
+            .iter()
+            .map(|crumb| (crumb.name.as_str(), crumb.href.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("Home", "/"),
+                ("docs", "/?path=docs"),
+                (
+                    "отчёты 2024",
+                    "/?path=docs%2F%D0%BE%D1%82%D1%87%D1%91%D1%82%D1%8B%202024"
+                ),
+            ]
+        );
+        assert_eq!(breadcrumbs(&RelativePath::root()).len(), 1);
+    }
+
+    #[test]
+    fn redirect_to_current_defaults_to_root() {
+        assert_eq!(location(&redirect_to_current(None)), "/");
+        assert_eq!(location(&redirect_to_current(Some(""))), "/");
+    }
+
+    #[test]
+    fn wants_html_only_for_browser_forms() {
+        let browser = TestRequest::default()
+            .insert_header((header::ACCEPT, "text/html,application/xhtml+xml"))
+            .to_http_request();
+        let fetch = TestRequest::default()
+            .insert_header((header::ACCEPT, "*/*"))
+            .to_http_request();
+
+        assert!(wants_html(&browser));
+        assert!(!wants_html(&fetch));
+        assert!(!wants_html(&TestRequest::default().to_http_request()));
+    }
+
+    #[actix_web::test]
+    async fn upload_response_negotiates_json_or_redirect() {
+        use actix_web::cookie::Key;
+        use actix_web_flash_messages::FlashMessagesFramework;
+        use actix_web_flash_messages::storage::CookieMessageStore;
+
+        let store = CookieMessageStore::builder(Key::from(&[7; 64])).build();
+        let app = test::init_service(
+            App::new()
+                .wrap(FlashMessagesFramework::builder(store).build())
+                .route(
+                    "/files/upload",
+                    web::post().to(|req: HttpRequest| async move {
+                        let info = FileInfoDto {
+                            name: "report.pdf".into(),
+                            size: 3,
+                            content_type: "application/pdf".into(),
+                            ..FileInfoDto::default()
+                        };
+                        upload_response(&req, Some("docs"), Ok(info), "Файл загружен.")
+                    }),
+                ),
+        )
+        .await;
+
+        let json = TestRequest::post()
+            .uri("/files/upload")
+            .insert_header((header::ACCEPT, "application/json"))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, json).await;
+        assert_eq!(body["name"], "report.pdf");
+        assert_eq!(body["size"], 3);
+        assert_eq!(body["content_type"], "application/pdf");
+
+        let form = TestRequest::post()
+            .uri("/files/upload")
+            .insert_header((header::ACCEPT, "text/html"))
+            .to_request();
+        let response = test::call_service(&app, form).await;
+        assert!(response.status().is_redirection());
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "/?path=docs"
+        );
+    }
+
+    #[actix_web::test]
+    async fn downloads_answer_conditional_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.pdf");
+        std::fs::write(&path, b"pdf").unwrap();
+
+        let app = test::init_service(App::new().route(
+            "/files/download",
+            web::get().to(move |req: HttpRequest| {
+                let path = path.clone();
+                async move {
+                    conditional_download(
+                        &req,
+                        NamedFile::open(path).unwrap(),
+                        attachment("report.pdf"),
+                    )
+                }
+            }),
+        ))
+        .await;
+
+        let plain = TestRequest::get().uri("/files/download").to_request();
+        let response = test::call_service(&app, plain).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let last_modified = response
+            .headers()
+            .get(header::LAST_MODIFIED)
+            .unwrap()
+            .clone();
+        let etag = response.headers().get(header::ETAG).unwrap().clone();
+        assert!(etag.to_str().unwrap().starts_with("W/\""));
+
+        let since = TestRequest::get()
+            .uri("/files/download")
+            .insert_header((header::IF_MODIFIED_SINCE, last_modified.clone()))
+            .to_request();
+        let response = test::call_service(&app, since).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(header::ETAG), Some(&etag));
+
+        let stale = TestRequest::get()
+            .uri("/files/download")
+            .insert_header((header::IF_MODIFIED_SINCE, "Mon, 01 Jan 2001 00:00:00 GMT"))
+            .to_request();
+        let response = test::call_service(&app, stale).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let matching = TestRequest::get()
+            .uri("/files/download")
+            .insert_header((header::IF_NONE_MATCH, etag))
+            .to_request();
+        let response = test::call_service(&app, matching).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let changed = TestRequest::get()
+            .uri("/files/download")
+            .insert_header((header::IF_NONE_MATCH, "W/\"other\""))
+            .insert_header((header::IF_MODIFIED_SINCE, last_modified))
+            .to_request();
+        let response = test::call_service(&app, changed).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn listing_projects_requested_fields() {
+        let entries = vec![FileEntryDto {
+            name: "cat.png".into(),
+            is_directory: false,
+            is_image: true,
+            folder_preview_image: None,
+            path: None,
+            content_type: None,
+            downloads: 0,
+        }];
+
+        let response = listing_json(&entries, Some("name"));
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json, serde_json::json!([{ "name": "cat.png" }]));
+
+        let response = listing_json(&entries, Some("name,size"));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn streamed_listing_contains_every_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let hub_root = dir.path().join("3");
+        std::fs::create_dir_all(hub_root.join("nested")).unwrap();
+        for index in 0..500 {
+            std::fs::write(hub_root.join(format!("file-{index:03}.txt")), b"x").unwrap();
+        }
+
+        let service = FileService::new(crate::domain::UploadRoot::from(dir.path().to_path_buf()));
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 3,
+            name: "User".into(),
+            roles: vec![crate::SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let tera = Tera::new("templates/**/*").unwrap();
+        let mut context = Context::new();
+        context.insert("current_user", &serde_json::json!({ "hub_id": 3 }));
+        context.insert("path", "");
+
+        let entries = service
+            .stream_entries(&user, None, EntryFilter::All, false)
+            .unwrap();
+        let response = stream_listing(std::sync::Arc::new(tera), context, entries);
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let html = std::str::from_utf8(&body).unwrap();
+
+        assert!(html.contains("data-file-browser-root"));
+        assert!(html.trim_end().ends_with("</div>"));
+        assert!(!html.contains(STREAM_MARKER));
+        assert!(html.contains("data-filebrowser-target=\"nested\""));
+        for index in 0..500 {
+            assert!(html.contains(&format!("file-{index:03}.txt")));
+        }
+    }
+
+    #[actix_web::test]
+    async fn streaming_upload_reports_each_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = FileService::new(crate::domain::UploadRoot::from(dir.path().to_path_buf()))
+            .with_max_upload_bytes(8);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 3,
+            name: "User".into(),
+            roles: vec![crate::SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let body = "--b\r\n\
+            Content-Disposition: form-data; name=\"note\"\r\n\r\nskipped\r\n--b\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\r\nhello\r\n--b\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"big.txt\"\r\n\r\n0123456789\r\n\
+            --b--\r\n";
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("multipart/form-data; boundary=b"),
+        );
+        let stream = futures_util::stream::once(async move {
+            Ok::<_, actix_web::error::PayloadError>(web::Bytes::from(body))
+        });
+        let mut upload = StreamingUpload {
+            payload: Multipart::new(&headers, stream),
+            service: web::Data::new(service),
+            rate_limiter: web::Data::new(None),
+            user,
+            path: None,
+            overwrite: false,
+            limit: 8,
+            done: 0,
+            finished: false,
+        };
+
+        let first = upload.next_line().await.unwrap();
+        assert_eq!(
+            (first.name.as_str(), first.done, first.stored),
+            ("a.txt", 1, true)
+        );
+        assert_eq!(std::fs::read(dir.path().join("3/a.txt")).unwrap(), b"hello");
+
+        let second = upload.next_line().await.unwrap();
+        assert_eq!(
+            (second.name.as_str(), second.done, second.stored),
+            ("big.txt", 2, false)
+        );
+        assert_eq!(second.code, Some("file_too_large"));
+        assert!(!dir.path().join("3/big.txt").exists());
+
+        assert!(upload.next_line().await.is_none());
+    }
+
+    #[actix_web::test]
+    async fn shared_library_refuses_writes() {
+        let app = test::init_service(App::new().service(reject_shared_write)).await;
+        for method in [
+            actix_web::http::Method::POST,
+            actix_web::http::Method::PUT,
+            actix_web::http::Method::DELETE,
+        ] {
+            let request = TestRequest::default()
+                .method(method)
+                .uri("/api/files/shared")
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
         }
     }
 }
@@ -0,0 +1,18 @@
+//! Prometheus scrape endpoint, served without authentication.
+use actix_web::{HttpResponse, Responder, get};
+
+use crate::metrics;
+
+/// Expose the collected metrics in the Prometheus text format.
+#[get("/metrics")]
+pub async fn metrics_endpoint() -> impl Responder {
+    match metrics::encode() {
+        Ok(text) => HttpResponse::Ok()
+            .content_type(prometheus::TEXT_FORMAT)
+            .body(text),
+        Err(e) => {
+            log::error!("Failed to encode metrics: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1 +1,46 @@
+use actix_web::HttpResponse;
+use actix_web::http::header::ContentType;
+use tera::{Context, Tera};
+
+pub mod error;
+pub mod health;
 pub mod main;
+pub mod metrics;
+
+/// Render `template` as an HTML page. A failing template answers `500` with
+/// the error logged instead of an empty page.
+pub fn render_template(tera: &Tera, template: &str, context: &Context) -> HttpResponse {
+    match tera.render(template, context) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(body),
+        Err(e) => {
+            log::error!("Failed to render template {template}: {e:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn broken_templates_answer_internal_server_error() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("ok.html", "<p>{{ name }}</p>")
+            .unwrap();
+        tera.add_raw_template("broken.html", "<p>{{ missing.field }}</p>")
+            .unwrap();
+        let mut context = Context::new();
+        context.insert("name", "docs");
+
+        let ok = render_template(&tera, "ok.html", &context);
+        assert_eq!(ok.status(), StatusCode::OK);
+
+        let broken = render_template(&tera, "broken.html", &context);
+        assert_eq!(broken.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,54 @@
+//! Artifacts derived from hub entries, such as thumbnails, that have to
+//! follow an entry when it is moved or renamed.
+use std::fs;
+use std::path::Path;
+
+use crate::domain::{RelativePath, THUMBNAIL_DIR};
+
+/// Move the thumbnail of the entry at `from`, or for a folder the thumbnails
+/// below it, to `to`. Both paths are relative to `hub_root`. Best effort;
+/// failures are only logged.
+pub fn relocate_thumbnails(hub_root: &Path, from: &RelativePath, to: &RelativePath) {
+    let thumbnails = hub_root.join(THUMBNAIL_DIR);
+    let source = thumbnails.join(from.as_path());
+    if source.symlink_metadata().is_err() {
+        return;
+    }
+    let target = thumbnails.join(to.as_path());
+    let moved = target
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::rename(&source, &target));
+    if let Err(err) = moved {
+        log::warn!("Failed to move thumbnail {source:?} to {target:?}: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thumbnails_follow_moved_files_and_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let hub_root = dir.path();
+        fs::create_dir_all(hub_root.join(".thumbnails/inbox/trip")).unwrap();
+        fs::write(hub_root.join(".thumbnails/inbox/cover.png"), b"thumb").unwrap();
+        fs::write(hub_root.join(".thumbnails/inbox/trip/beach.png"), b"thumb").unwrap();
+        let path = |raw: &str| RelativePath::try_from_str(raw).unwrap();
+
+        relocate_thumbnails(hub_root, &path("inbox/cover.png"), &path("inbox/front.png"));
+        relocate_thumbnails(hub_root, &path("inbox/trip"), &path("archive/trip"));
+        relocate_thumbnails(hub_root, &path("inbox/none.png"), &path("archive/none.png"));
+
+        assert!(hub_root.join(".thumbnails/inbox/front.png").is_file());
+        assert!(!hub_root.join(".thumbnails/inbox/cover.png").exists());
+        assert!(
+            hub_root
+                .join(".thumbnails/archive/trip/beach.png")
+                .is_file()
+        );
+        assert!(!hub_root.join(".thumbnails/inbox/trip").exists());
+        assert!(!hub_root.join(".thumbnails/archive/none.png").exists());
+    }
+}
@@ -0,0 +1,156 @@
+//! Audit trail of changes members make to their hub's files.
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+/// Kind of change recorded in the audit log.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    Upload,
+    CreateFile,
+    CreateFolder,
+    DeleteFile,
+    DeleteFolder,
+    Rename,
+    /// Text written from the editor, creating or replacing the file.
+    Save,
+    /// Entry moved back from the trash to its original path.
+    Restore,
+    EmptyTrash,
+}
+
+/// One audited change: who changed which path of which hub, and when.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct AuditRecord {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// `sub` of the member making the change.
+    pub user: String,
+    pub hub_id: i32,
+    pub operation: AuditOperation,
+    /// Path of the entry relative to the hub root; for renames the old path.
+    pub path: String,
+    /// New path of a renamed entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(user: &str, hub_id: i32, operation: AuditOperation, path: String) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        Self {
+            timestamp,
+            user: user.to_string(),
+            hub_id,
+            operation,
+            path,
+            target: None,
+        }
+    }
+
+    pub fn with_target(mut self, target: String) -> Self {
+        self.target = Some(target);
+        self
+    }
+}
+
+/// Destination of [`AuditRecord`]s. Recording never fails the audited
+/// operation; implementations log their own errors.
+pub trait AuditLog: fmt::Debug + Send + Sync {
+    fn record(&self, record: &AuditRecord);
+}
+
+/// Discards every record. Used unless an audit log is configured.
+#[derive(Debug, Default)]
+pub struct NoopAuditLog;
+
+impl AuditLog for NoopAuditLog {
+    fn record(&self, _record: &AuditRecord) {}
+}
+
+/// Appends one JSON line per record to a file. Lines are written whole under
+/// a lock, so concurrent requests never interleave them.
+#[derive(Debug)]
+pub struct FileAuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl FileAuditLog {
+    /// Open `path` for appending, creating it when missing.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditLog for FileAuditLog {
+    fn record(&self, record: &AuditRecord) {
+        let mut line = match serde_json::to_vec(record) {
+            Ok(line) => line,
+            Err(err) => {
+                log::warn!("Failed to serialize audit record {record:?}: {err}");
+                return;
+            }
+        };
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(err) = file.write_all(&line) {
+            log::warn!("Failed to append to audit log {:?}: {err}", self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn concurrent_records_are_appended_as_whole_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = Arc::new(FileAuditLog::open(&path).unwrap());
+
+        let workers: Vec<_> = (0..8)
+            .map(|worker| {
+                let log = Arc::clone(&log);
+                std::thread::spawn(move || {
+                    for n in 0..25 {
+                        log.record(&AuditRecord::new(
+                            "user",
+                            worker,
+                            AuditOperation::Upload,
+                            format!("docs/{n}.txt"),
+                        ));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 200);
+        assert!(records.iter().all(|record| record["operation"] == "upload"));
+    }
+}
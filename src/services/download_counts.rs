@@ -0,0 +1,216 @@
+//! Per-file download counters shown in listings.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::domain::StorageKey;
+
+/// Counts how often each file was downloaded, keyed by its [`StorageKey`].
+/// Every worker shares the same counters, and they are persisted to a JSON
+/// sidecar after each download so restarts do not reset them. Counters of
+/// deleted files are dropped, so the map only tracks files that exist.
+#[derive(Clone, Debug)]
+pub struct DownloadCounts {
+    sidecar: PathBuf,
+    shared: bool,
+    counts: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl DownloadCounts {
+    /// Create the counters, restoring them from `sidecar` when present.
+    pub fn load(sidecar: PathBuf) -> Self {
+        let counts = read_sidecar(&sidecar).unwrap_or_default();
+
+        Self {
+            sidecar,
+            shared: false,
+            counts: Arc::new(Mutex::new(counts)),
+        }
+    }
+
+    /// Treat the sidecar as the source of truth, for instances sharing an
+    /// upload root: it is re-read before every read and update instead of
+    /// trusting the counters in memory. Updates are read-modify-write, so
+    /// instances still need external coordination not to lose increments.
+    pub fn with_shared_storage(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Count one download of the file at `key`.
+    pub fn record(&self, key: &StorageKey) {
+        let mut counts = self.lock();
+        let count = counts.entry(key.as_str().to_string()).or_default();
+        *count = count.saturating_add(1);
+        self.persist(&counts);
+    }
+
+    /// Downloads of the file at `key` so far.
+    pub fn get(&self, key: &StorageKey) -> u64 {
+        let counts = self.lock();
+        counts.get(key.as_str()).copied().unwrap_or(0)
+    }
+
+    /// Drop the counters of the deleted entry at `key` and, for a folder, of
+    /// every file below it.
+    pub fn forget(&self, key: &StorageKey) {
+        let mut counts = self.lock();
+        let prefix = format!("{key}/");
+        let before = counts.len();
+        counts.retain(|counted, _| counted != key.as_str() && !counted.starts_with(&prefix));
+        if counts.len() != before {
+            self.persist(&counts);
+        }
+    }
+
+    /// Carry the counters of the entry at `from` and, for a folder, of every
+    /// file below it over to `to` after a move or rename.
+    pub fn relocate(&self, from: &StorageKey, to: &StorageKey) {
+        let mut counts = self.lock();
+        let prefix = format!("{from}/");
+        let moved: Vec<(String, u64)> = counts
+            .iter()
+            .filter(|(counted, _)| *counted == from.as_str() || counted.starts_with(&prefix))
+            .map(|(counted, count)| (counted.clone(), *count))
+            .collect();
+        if moved.is_empty() {
+            return;
+        }
+        for (counted, count) in moved {
+            counts.remove(&counted);
+            let below = &counted[from.as_str().len()..];
+            counts.insert(format!("{to}{below}"), count);
+        }
+        self.persist(&counts);
+    }
+
+    /// Lock the counters, refreshed from the sidecar in shared mode.
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, u64>> {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        if self.shared {
+            if let Some(stored) = read_sidecar(&self.sidecar) {
+                *counts = stored;
+            }
+        }
+        counts
+    }
+
+    fn persist(&self, counts: &HashMap<String, u64>) {
+        let result = serde_json::to_vec(counts)
+            .map_err(std::io::Error::other)
+            .and_then(|data| fs::write(&self.sidecar, data));
+        if let Err(err) = result {
+            log::warn!(
+                "Failed to persist download counts to {:?}: {err}",
+                self.sidecar
+            );
+        }
+    }
+}
+
+/// Counters stored in `sidecar`, if it exists and is readable.
+fn read_sidecar(sidecar: &Path) -> Option<HashMap<String, u64>> {
+    let data = fs::read(sidecar).ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(counts) => Some(counts),
+        Err(err) => {
+            log::warn!("Ignoring unreadable download counts {sidecar:?}: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::domain::{FileName, HubId, HubStorage, RelativePath, UploadRoot};
+
+    #[test]
+    fn concurrent_downloads_are_all_counted_and_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let sidecar = dir.path().join(".download_counts.json");
+        let counts = DownloadCounts::load(sidecar.clone());
+        let storage = HubStorage::new(
+            UploadRoot::from(Path::new("upload").to_path_buf()),
+            HubId::new(4),
+        );
+        let key = storage.file_key(
+            &RelativePath::try_from_str("docs").unwrap(),
+            &FileName::try_from_str("a.pdf").unwrap(),
+        );
+
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let counts = counts.clone();
+                let key = key.clone();
+                thread::spawn(move || {
+                    for _ in 0..25 {
+                        counts.record(&key);
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(counts.get(&key), 200);
+        assert_eq!(DownloadCounts::load(sidecar).get(&key), 200);
+        assert_eq!(counts.get(&storage.dir_key(&RelativePath::root())), 0);
+    }
+
+    #[test]
+    fn deleted_entries_are_forgotten() {
+        let dir = tempfile::tempdir().unwrap();
+        let counts = DownloadCounts::load(dir.path().join(".download_counts.json"));
+        let storage = HubStorage::new(
+            UploadRoot::from(Path::new("upload").to_path_buf()),
+            HubId::new(4),
+        );
+        let docs = RelativePath::try_from_str("docs").unwrap();
+        let key = |folder: &str, name: &str| {
+            storage.file_key(
+                &RelativePath::try_from_str(folder).unwrap(),
+                &FileName::try_from_str(name).unwrap(),
+            )
+        };
+        for file in [
+            key("docs", "a.pdf"),
+            key("docs/old", "b.pdf"),
+            key("", "docs2.pdf"),
+        ] {
+            counts.record(&file);
+        }
+
+        counts.forget(&storage.dir_key(&docs));
+
+        assert_eq!(counts.get(&key("docs", "a.pdf")), 0);
+        assert_eq!(counts.get(&key("docs/old", "b.pdf")), 0);
+        assert_eq!(counts.get(&key("", "docs2.pdf")), 1);
+    }
+
+    #[test]
+    fn shared_counters_see_other_instances() {
+        let dir = tempfile::tempdir().unwrap();
+        let sidecar = dir.path().join(".download_counts.json");
+        let first = DownloadCounts::load(sidecar.clone()).with_shared_storage(true);
+        let second = DownloadCounts::load(sidecar).with_shared_storage(true);
+        let storage = HubStorage::new(
+            UploadRoot::from(Path::new("upload").to_path_buf()),
+            HubId::new(4),
+        );
+        let key = storage.file_key(
+            &RelativePath::root(),
+            &FileName::try_from_str("a.pdf").unwrap(),
+        );
+
+        first.record(&key);
+        second.record(&key);
+
+        assert_eq!(first.get(&key), 2);
+        assert_eq!(second.get(&key), 2);
+    }
+}
@@ -1,29 +1,554 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
 use std::fs;
-use std::time::SystemTime;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use actix_multipart::form::tempfile::TempFile;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
 use pushkind_common::domain::auth::AuthenticatedUser;
 use pushkind_common::routes::check_role;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
 use uuid::Uuid;
 use validator::Validate;
+use walkdir::WalkDir;
 
-use crate::SERVICE_ACCESS_ROLE;
 use crate::domain::{
-    EntryKind, FileName, HubId, HubStorage, RelativePath, StorageEntry, UploadRoot,
+    CHUNKED_UPLOAD_DIR, ConflictPolicy, DEFAULT_IMAGE_EXTENSIONS, EntryFilter, EntryKind,
+    FileCategory, FileName, FolderNameCharset, HubId, HubStorage, ListingSort, RESERVED_DIRS,
+    RelativePath, SharedStorage, SortKey, StorageEntry, StorageKey, THUMBNAIL_DIR, TRASH_DIR,
+    UploadRoot, natural_cmp,
 };
-use crate::dto::FileEntryDto;
-use crate::forms::main::CreateFolderForm;
+use crate::dto::{
+    ArchiveEntryDto, ArchiveReportDto, ChunkDto, ChunkManifestDto, DeleteResultDto, EntryCountsDto,
+    EntryStatDto, ExtractResultDto, FileEntryDto, FileInfoDto, FolderDepthDto, MediaInfoDto,
+    MoveResultDto, PublishResultDto, RenameResultDto, StorageBreakdownDto, ThumbnailRepairDto,
+    UploadLimitsDto,
+};
+use crate::forms::main::{CreateFolderForm, MoveMatchingForm, PublishForm};
+use crate::metrics;
+use crate::services::artifacts;
+use crate::services::audit::{AuditLog, AuditOperation, AuditRecord, NoopAuditLog};
+use crate::services::download_counts::DownloadCounts;
+use crate::services::media;
+use crate::services::mirror::{Mirror, MirrorOp};
+use crate::services::storage::{BackendEntry, LocalBackend, StorageBackend, persist_file};
+use crate::services::watch::{PendingWatch, WatchLimiter};
 use crate::services::{ServiceError, ServiceResult};
+use crate::{SERVICE_ACCESS_ROLE, SERVICE_ADMIN_ROLE};
+
+/// Default cap for recursive traversals below a folder.
+pub const DEFAULT_MAX_TREE_DEPTH: usize = 16;
+
+/// Longest side, in pixels, of thumbnails regenerated by a cache repair.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Chunk size used for download manifests when the client does not pick one.
+pub const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Largest number of chunks a single manifest may describe.
+pub const MAX_MANIFEST_CHUNKS: u64 = 10_000;
+
+/// How many entries of a sub-folder are inspected when looking for a preview.
+const FOLDER_PREVIEW_SCAN_LIMIT: usize = 64;
+
+/// Characters escaped in a URL query value.
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Compression ratio above which an archive is reported as a possible zip bomb.
+pub const ZIP_BOMB_RATIO: f64 = 100.0;
+
+/// Upload size limit used when a hub has no override.
+pub const DEFAULT_MAX_UPLOAD_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Metadata of a listed entry used to order the listing.
+#[derive(Clone, Copy, Debug, Default)]
+struct EntryStats {
+    created: Option<SystemTime>,
+    modified: Option<SystemTime>,
+    size: u64,
+}
+
+impl From<&fs::Metadata> for EntryStats {
+    fn from(metadata: &fs::Metadata) -> Self {
+        Self {
+            created: metadata.created().ok(),
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+        }
+    }
+}
+
+impl From<&BackendEntry> for EntryStats {
+    fn from(entry: &BackendEntry) -> Self {
+        Self {
+            created: entry.created,
+            modified: entry.modified,
+            size: entry.size,
+        }
+    }
+}
+
+/// Most entries a single [`FileService::search`] returns.
+pub const MAX_SEARCH_RESULTS: usize = 200;
+
+/// Files [`FileService::recent`] returns when no limit is requested.
+pub const DEFAULT_RECENT_LIMIT: usize = 20;
+
+/// Most files a single [`FileService::recent`] returns.
+pub const MAX_RECENT_RESULTS: usize = 200;
+
+/// Entries [`FileService::recent`] looks at before answering with the newest
+/// files found so far, bounding the work on very large hubs.
+const RECENT_SCAN_LIMIT: usize = 100_000;
+
+/// Highest counter tried when numbering a duplicate upload name.
+const MAX_NAME_SUFFIX: u32 = 10_000;
+
+/// Sidecar file holding the byte cap of the folder it sits in, including all
+/// of its subfolders.
+pub const QUOTA_SIDECAR: &str = ".quota";
+
+/// Sidecar names uploads and renames may not take by default.
+pub const DEFAULT_RESERVED_SIDECARS: &[&str] = &[QUOTA_SIDECAR, ".meta.json", ".acl.json"];
+
+/// Most parts a single chunked upload may be split into.
+pub const MAX_UPLOAD_CHUNKS: u32 = 10_000;
+
+/// How long an unfinished chunked upload is kept after its last part.
+pub const DEFAULT_CHUNKED_UPLOAD_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Default cap for files returned by [`FileService::read_text`].
+pub const DEFAULT_MAX_VIEW_BYTES: u64 = 1024 * 1024;
+
+/// Default cap for text saved by [`FileService::write_text`].
+pub const DEFAULT_MAX_SAVE_BYTES: u64 = 1024 * 1024;
+
+/// Default cap for bytes returned by [`FileService::read_head`].
+pub const DEFAULT_MAX_HEAD_BYTES: u64 = 64 * 1024;
+
+/// Copy `source` (a file, or a folder with everything below it down to
+/// `max_depth` levels) to `target`, creating missing folders and overwriting
+/// existing files. Symlinks are skipped.
+pub(crate) fn copy_tree(source: &Path, target: &Path, max_depth: usize) -> std::io::Result<()> {
+    let walker = WalkDir::new(source)
+        .follow_links(false)
+        .max_depth(max_depth);
+    for item in walker {
+        let item = item?;
+        let inner = item
+            .path()
+            .strip_prefix(source)
+            .map_err(std::io::Error::other)?;
+        let destination = if inner.as_os_str().is_empty() {
+            target.to_path_buf()
+        } else {
+            target.join(inner)
+        };
+        if item.file_type().is_dir() {
+            fs::create_dir_all(&destination)?;
+        } else if item.file_type().is_file() {
+            fs::copy(item.path(), &destination)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `a` and `b` lead to the same entry on disk, as two spellings of one
+/// name do on a case-insensitive file system. Symlinks are not followed.
+fn is_same_entry(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (a.symlink_metadata(), b.symlink_metadata()) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        false
+    }
+}
 
 /// Service responsible for file system operations inside a hub's storage.
 #[derive(Clone, Debug)]
 pub struct FileService {
     upload_root: UploadRoot,
+    hub_roots: HashMap<i32, PathBuf>,
+    backend: Arc<dyn StorageBackend>,
+    ignore_patterns: GlobSet,
+    mirror: Option<Mirror>,
+    allow_symlinked_roots: bool,
+    max_tree_depth: usize,
+    conflict_policy: ConflictPolicy,
+    shared: Option<SharedStorage>,
+    max_aspect_ratio: Option<f64>,
+    strict_mime: bool,
+    max_head_bytes: u64,
+    folder_name_charset: FolderNameCharset,
+    watchers: WatchLimiter,
+    max_upload_bytes: u64,
+    hub_upload_limits: HashMap<i32, u64>,
+    quota_bytes: Option<u64>,
+    chunked_upload_ttl: Duration,
+    folder_previews: bool,
+    default_mime_extensionless: mime::Mime,
+    prune_empty_on_delete: bool,
+    soft_delete: bool,
+    double_extension_blocklist: HashSet<String>,
+    allowed_extensions: Option<HashSet<String>>,
+    image_extensions: HashSet<String>,
+    reserved_sidecars: HashSet<String>,
+    download_counts: Option<DownloadCounts>,
+    audit_log: Arc<dyn AuditLog>,
 }
 
 impl FileService {
     pub fn new(upload_root: UploadRoot) -> Self {
-        Self { upload_root }
+        Self {
+            backend: Arc::new(LocalBackend::new(upload_root.clone())),
+            upload_root,
+            hub_roots: HashMap::new(),
+            ignore_patterns: GlobSet::empty(),
+            mirror: None,
+            allow_symlinked_roots: false,
+            max_tree_depth: DEFAULT_MAX_TREE_DEPTH,
+            conflict_policy: ConflictPolicy::default(),
+            shared: None,
+            max_aspect_ratio: None,
+            strict_mime: false,
+            max_head_bytes: DEFAULT_MAX_HEAD_BYTES,
+            folder_name_charset: FolderNameCharset::default(),
+            watchers: WatchLimiter::default(),
+            max_upload_bytes: DEFAULT_MAX_UPLOAD_BYTES,
+            hub_upload_limits: HashMap::new(),
+            quota_bytes: None,
+            chunked_upload_ttl: DEFAULT_CHUNKED_UPLOAD_TTL,
+            folder_previews: false,
+            default_mime_extensionless: mime::APPLICATION_OCTET_STREAM,
+            prune_empty_on_delete: false,
+            soft_delete: false,
+            double_extension_blocklist: HashSet::new(),
+            allowed_extensions: None,
+            image_extensions: DEFAULT_IMAGE_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            reserved_sidecars: DEFAULT_RESERVED_SIDECARS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            download_counts: None,
+            audit_log: Arc::new(NoopAuditLog),
+        }
+    }
+
+    /// Record uploads, new files and folders, deletions and renames in
+    /// `audit_log`.
+    pub fn with_audit_log(mut self, audit_log: Arc<dyn AuditLog>) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    /// Count downloads per file and report them in listings.
+    pub fn with_download_counts(mut self, counts: DownloadCounts) -> Self {
+        self.download_counts = Some(counts);
+        self
+    }
+
+    /// Store listings, new folders, uploads, previews and deletions through
+    /// `backend` instead of the local disk.
+    pub fn with_backend(mut self, backend: Arc<dyn StorageBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// MIME type reported for files without an extension.
+    pub fn with_default_mime_extensionless(mut self, mime: mime::Mime) -> Self {
+        self.default_mime_extensionless = mime;
+        self
+    }
+
+    pub fn default_mime_extensionless(&self) -> &mime::Mime {
+        &self.default_mime_extensionless
+    }
+
+    /// MIME type of a file, guessed from its extension. Extensionless files
+    /// get the configured default.
+    pub fn content_type_for(&self, name: &FileName) -> mime::Mime {
+        if name.extension().is_none() {
+            return self.default_mime_extensionless.clone();
+        }
+        mime_guess::from_path(name.as_str()).first_or_octet_stream()
+    }
+
+    /// Peek into listed sub-folders for an image to use as their icon.
+    pub fn with_folder_previews(mut self, enabled: bool) -> Self {
+        self.folder_previews = enabled;
+        self
+    }
+
+    /// Remove folders left empty by a delete or move, up to the hub root.
+    pub fn with_prune_empty_on_delete(mut self, enabled: bool) -> Self {
+        self.prune_empty_on_delete = enabled;
+        self
+    }
+
+    /// Move deleted files and folders into the hub's [`TRASH_DIR`] instead
+    /// of removing them.
+    pub fn with_soft_delete(mut self, enabled: bool) -> Self {
+        self.soft_delete = enabled;
+        self
+    }
+
+    /// Reject uploads like `invoice.pdf.exe` whose final extension is one of
+    /// `extensions` while an earlier segment poses as a harmless type.
+    pub fn with_double_extension_blocklist(mut self, extensions: &[String]) -> Self {
+        self.double_extension_blocklist = extensions
+            .iter()
+            .map(|ext| ext.trim_start_matches('.').to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Only accept uploads whose extension is listed. `None` accepts every
+    /// extension, files without one included.
+    pub fn with_allowed_extensions(mut self, extensions: Option<&[String]>) -> Self {
+        self.allowed_extensions = extensions.map(|extensions| {
+            extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_ascii_lowercase())
+                .collect()
+        });
+        self
+    }
+
+    /// Whether the allowlist, when configured, admits the extension of `name`.
+    fn is_allowed_extension(&self, name: &FileName) -> bool {
+        match &self.allowed_extensions {
+            None => true,
+            Some(allowed) => name.extension().is_some_and(|ext| allowed.contains(&ext)),
+        }
+    }
+
+    /// Treat exactly these extensions as images. `None` keeps
+    /// [`DEFAULT_IMAGE_EXTENSIONS`].
+    pub fn with_image_extensions(mut self, extensions: Option<&[String]>) -> Self {
+        if let Some(extensions) = extensions {
+            self.image_extensions = extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_ascii_lowercase())
+                .collect();
+        }
+        self
+    }
+
+    /// Whether `name` is an image under the configured extension set. Every
+    /// image check of the service goes through here or [`Self::category_of`].
+    fn is_image(&self, name: &FileName) -> bool {
+        name.extension()
+            .is_some_and(|ext| self.image_extensions.contains(&ext))
+    }
+
+    /// [`FileName::category`] adjusted to the configured image extensions.
+    fn category_of(&self, name: &FileName) -> FileCategory {
+        match name.category() {
+            _ if self.is_image(name) => FileCategory::Image,
+            FileCategory::Image => FileCategory::Other,
+            category => category,
+        }
+    }
+
+    /// File names reserved for sidecar metadata. [`QUOTA_SIDECAR`] stays
+    /// reserved whatever the configuration says, since quotas rely on it.
+    pub fn with_reserved_sidecars(mut self, names: &[String]) -> Self {
+        self.reserved_sidecars = names
+            .iter()
+            .map(|name| name.to_lowercase())
+            .chain([QUOTA_SIDECAR.to_string()])
+            .collect();
+        self
+    }
+
+    /// Whether `name` collides with a reserved sidecar file, ignoring case
+    /// for the benefit of case-insensitive file systems.
+    fn is_reserved_sidecar(&self, name: &FileName) -> bool {
+        self.reserved_sidecars
+            .contains(&name.as_str().to_lowercase())
+    }
+
+    /// Whether `name` is the [`QUOTA_SIDECAR`], ignoring case like
+    /// [`FileService::is_reserved_sidecar`].
+    fn is_quota_sidecar(name: &FileName) -> bool {
+        name.as_str().eq_ignore_ascii_case(QUOTA_SIDECAR)
+    }
+
+    /// Whether `name` hides a blocked extension behind another one.
+    fn is_spoofed_extension(&self, name: &FileName) -> bool {
+        let segments = name.as_str().trim_start_matches('.').split('.').count();
+        segments > 2
+            && name
+                .extension()
+                .is_some_and(|ext| self.double_extension_blocklist.contains(&ext))
+    }
+
+    /// Upload size limit for hubs without an override.
+    pub fn with_max_upload_bytes(mut self, bytes: u64) -> Self {
+        self.max_upload_bytes = bytes;
+        self
+    }
+
+    /// Override the upload size limit for specific hubs.
+    pub fn with_hub_upload_limits(mut self, limits: HashMap<i32, u64>) -> Self {
+        self.hub_upload_limits = limits;
+        self
+    }
+
+    /// Largest upload any hub may send, used to size the multipart limit.
+    pub fn largest_upload_limit(&self) -> u64 {
+        self.hub_upload_limits
+            .values()
+            .copied()
+            .fold(self.max_upload_bytes, u64::max)
+    }
+
+    fn upload_limit_for(&self, hub_id: &HubId) -> u64 {
+        self.hub_upload_limits
+            .get(&hub_id.value())
+            .copied()
+            .unwrap_or(self.max_upload_bytes)
+    }
+
+    /// Cap the total bytes a hub may store.
+    pub fn with_quota_bytes(mut self, quota_bytes: Option<u64>) -> Self {
+        self.quota_bytes = quota_bytes;
+        self
+    }
+
+    /// Remove unfinished chunked uploads whose last part is older than `ttl`.
+    pub fn with_chunked_upload_ttl(mut self, ttl: Duration) -> Self {
+        self.chunked_upload_ttl = ttl;
+        self
+    }
+
+    /// Total bytes each hub may store, if capped.
+    pub fn quota_bytes(&self) -> Option<u64> {
+        self.quota_bytes
+    }
+
+    /// Sum the sizes of every file stored in the user's hub.
+    pub fn hub_usage(&self, user: &AuthenticatedUser) -> ServiceResult<u64> {
+        let storage = self.authorize(user)?;
+        Ok(Self::used_bytes(&storage, &storage.hub_root()))
+    }
+
+    /// Report the upload limits that apply to the user's hub.
+    pub fn upload_limits(&self, user: &AuthenticatedUser) -> ServiceResult<UploadLimitsDto> {
+        let storage = self.authorize(user)?;
+        Ok(UploadLimitsDto {
+            max_upload_bytes: self.upload_limit_for(storage.hub_id()),
+        })
+    }
+
+    /// Bound how many long-poll watchers a single hub may hold open.
+    pub fn with_max_watchers_per_hub(mut self, max_watchers: usize) -> Self {
+        self.watchers = WatchLimiter::new(max_watchers);
+        self
+    }
+
+    /// Restrict the characters accepted in new folder names.
+    pub fn with_folder_name_charset(mut self, charset: FolderNameCharset) -> Self {
+        self.folder_name_charset = charset;
+        self
+    }
+
+    /// Cap how many bytes [`FileService::read_head`] returns.
+    pub fn with_max_head_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_head_bytes = max_bytes;
+        self
+    }
+
+    /// Reject uploaded images whose longer side exceeds the shorter one by
+    /// more than `ratio`.
+    pub fn with_max_aspect_ratio(mut self, ratio: f64) -> Self {
+        self.max_aspect_ratio = Some(ratio);
+        self
+    }
+
+    /// Reject uploads whose content, sniffed from the first bytes, does not
+    /// match their extension.
+    pub fn with_strict_mime(mut self, enabled: bool) -> Self {
+        self.strict_mime = enabled;
+        self
+    }
+
+    /// Expose a read-only library visible to every hub.
+    pub fn with_shared_root(mut self, shared_root: PathBuf) -> Self {
+        self.shared = Some(SharedStorage::new(shared_root));
+        self
+    }
+
+    /// Choose how writes onto an existing file name are handled.
+    pub fn with_conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.conflict_policy = policy;
+        self
+    }
+
+    /// Cap how deep recursive operations descend below their starting folder.
+    pub fn with_max_tree_depth(mut self, max_depth: usize) -> Self {
+        self.max_tree_depth = max_depth;
+        self
+    }
+
+    /// Accept hub roots that are symlinks to other volumes, using the link
+    /// target as the containment boundary.
+    pub fn with_symlinked_roots(mut self, allowed: bool) -> Self {
+        self.allow_symlinked_roots = allowed;
+        self
+    }
+
+    /// Replicate successful writes to a secondary root on a best-effort basis.
+    pub fn with_mirror(mut self, mirror_root: UploadRoot) -> Self {
+        self.mirror = Some(
+            Mirror::new(self.upload_root.clone(), mirror_root)
+                .with_primary_hub_roots(self.hub_roots.clone()),
+        );
+        self
+    }
+
+    /// Keep the listed hubs in their own directories instead of below the
+    /// upload root. This replaces the storage backend with a local one, so
+    /// it must come before [`FileService::with_backend`].
+    pub fn with_hub_roots(mut self, hub_roots: HashMap<i32, PathBuf>) -> Self {
+        self.backend =
+            Arc::new(LocalBackend::new(self.upload_root.clone()).with_hub_roots(hub_roots.clone()));
+        self.mirror = self
+            .mirror
+            .map(|mirror| mirror.with_primary_hub_roots(hub_roots.clone()));
+        self.hub_roots = hub_roots;
+        self
+    }
+
+    /// Hide entries whose names match any of the given glob patterns from
+    /// listings. Patterns are compiled once here.
+    pub fn with_ignore_patterns(mut self, patterns: &[String]) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(Glob::new(pattern)?);
+        }
+        self.ignore_patterns = builder.build()?;
+        Ok(self)
     }
 
     fn sanitize_path_param(path: Option<&str>) -> ServiceResult<RelativePath> {
@@ -33,328 +558,6948 @@ impl FileService {
         }
     }
 
+    /// Refuse writes into the service's reserved directories.
+    fn ensure_writable(relative: &RelativePath) -> ServiceResult<()> {
+        if relative.is_reserved() {
+            Err(ServiceError::InvalidPath)
+        } else {
+            Ok(())
+        }
+    }
+
     fn sanitize_file_name(raw: Option<String>) -> ServiceResult<FileName> {
         let generated = format!("upload-{}", Uuid::new_v4());
         let candidate = raw.unwrap_or(generated);
         FileName::try_from_str(&candidate).map_err(|_| ServiceError::InvalidFileName)
     }
 
+    /// Storage of `hub_id`, rooted at its configured override if it has one.
     pub fn storage_for_hub(&self, hub_id: HubId) -> HubStorage {
-        HubStorage::new(self.upload_root.clone(), hub_id)
+        let storage = match self.hub_roots.get(&hub_id.value()) {
+            Some(hub_root) => HubStorage::with_hub_root(hub_root.clone(), hub_id),
+            None => HubStorage::new(self.upload_root.clone(), hub_id),
+        };
+        storage.with_symlinked_root(self.allow_symlinked_roots)
+    }
+
+    /// Enumerate hubs with a directory under the upload root or an existing
+    /// overridden root. Entries that are not directories named after a hub id
+    /// are skipped with a warning.
+    pub fn hub_ids(&self) -> ServiceResult<Vec<HubId>> {
+        let mut hubs: Vec<HubId> = self
+            .hub_roots
+            .iter()
+            .filter(|(_, hub_root)| hub_root.is_dir())
+            .filter_map(|(hub_id, _)| HubId::try_new(*hub_id).ok())
+            .collect();
+
+        let root = self.upload_root.as_path();
+        if root.exists() {
+            let listed = fs::read_dir(root)
+                .map_err(ServiceError::ListEntries)?
+                .filter_map(|e| e.ok())
+                .filter(|entry| entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
+                .filter_map(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    match HubId::try_from_dir_name(&name) {
+                        Ok(hub_id) => Some(hub_id),
+                        Err(_) => {
+                            log::warn!("Skipping non-hub directory in upload root: {name}");
+                            None
+                        }
+                    }
+                })
+                .filter(|hub_id| !self.hub_roots.contains_key(&hub_id.value()));
+            hubs.extend(listed);
+        }
+        hubs.sort_by_key(HubId::value);
+
+        Ok(hubs)
     }
 
     fn authorize(&self, user: &AuthenticatedUser) -> ServiceResult<HubStorage> {
-        if check_role(SERVICE_ACCESS_ROLE, &user.roles) {
-            Ok(self.storage_for_hub(HubId::from(user.hub_id)))
+        if !check_role(SERVICE_ACCESS_ROLE, &user.roles) {
+            return Err(ServiceError::Unauthorized);
+        }
+        let hub_id = HubId::try_new(user.hub_id).map_err(|_| ServiceError::Unauthorized)?;
+        Ok(self.storage_for_hub(hub_id))
+    }
+
+    /// Hub the user's requests are served from, once they are authorized for
+    /// it. Routes key per-hub limits such as the download quota by it.
+    pub fn user_hub(&self, user: &AuthenticatedUser) -> ServiceResult<HubId> {
+        self.authorize(user).map(|storage| storage.hub_id().clone())
+    }
+
+    /// Admin operations address any hub explicitly instead of the user's own.
+    fn authorize_admin(
+        &self,
+        user: &AuthenticatedUser,
+        hub_id: HubId,
+    ) -> ServiceResult<HubStorage> {
+        if check_role(SERVICE_ADMIN_ROLE, &user.roles) {
+            Ok(self.storage_for_hub(hub_id))
         } else {
             Err(ServiceError::Unauthorized)
         }
     }
 
+    /// Record `operation` on the hub entry at `path` in the audit log.
+    fn audit(&self, user: &AuthenticatedUser, operation: AuditOperation, path: &Path) {
+        self.audit_log.record(&AuditRecord::new(
+            &user.sub,
+            user.hub_id,
+            operation,
+            path.to_string_lossy().into_owned(),
+        ));
+    }
+
+    /// Record the rename of the entry at `from` to `to` in hub `hub_id`, which
+    /// differs from the user's own hub for admin operations.
+    fn audit_rename(&self, user: &AuthenticatedUser, hub_id: &HubId, from: &Path, to: &Path) {
+        self.audit_log.record(
+            &AuditRecord::new(
+                &user.sub,
+                hub_id.value(),
+                AuditOperation::Rename,
+                from.to_string_lossy().into_owned(),
+            )
+            .with_target(to.to_string_lossy().into_owned()),
+        );
+    }
+
+    fn replay_on_mirror(&self, op: MirrorOp) {
+        if let Some(mirror) = &self.mirror {
+            mirror.replay(op);
+        }
+    }
+
+    /// Let the derived artifacts of the entry moved from `from` to `to`
+    /// follow it: its download counts and its thumbnail, or for a folder the
+    /// thumbnails below it. Best effort; failures are only logged.
+    fn relocate_artifacts(&self, storage: &HubStorage, from: &RelativePath, to: &RelativePath) {
+        if let Some(counts) = &self.download_counts {
+            counts.relocate(&storage.dir_key(from), &storage.dir_key(to));
+        }
+        artifacts::relocate_thumbnails(&storage.hub_root(), from, to);
+    }
+
+    /// Drop the download counts of a deleted entry.
+    fn forget_downloads(&self, key: &StorageKey) {
+        if let Some(counts) = &self.download_counts {
+            counts.forget(key);
+        }
+    }
+
+    /// Remove `dir` and its ancestors while they are empty, stopping at the
+    /// hub root, when `prune_empty_on_delete` is enabled.
+    fn prune_empty_parents(&self, storage: &HubStorage, dir: &Path) {
+        if !self.prune_empty_on_delete {
+            return;
+        }
+        let hub_root = storage.hub_root();
+        let mut current = dir;
+        while current != hub_root && current.starts_with(&hub_root) {
+            if fs::remove_dir(current).is_err() {
+                break;
+            }
+            let Some(parent) = current.parent() else {
+                break;
+            };
+            current = parent;
+        }
+    }
+
     fn ensure_hub_root(&self, storage: &HubStorage) -> ServiceResult<()> {
         fs::create_dir_all(storage.hub_root()).map_err(ServiceError::StorageSetup)
     }
 
-    /// List entries for the given relative path, returning DTOs for rendering.
-    pub fn list_entries(
+    /// Reject absolute paths that resolve outside the hub root (e.g. through
+    /// symlinks). Call before touching the file system.
+    fn ensure_within_hub(storage: &HubStorage, absolute: &Path) -> ServiceResult<()> {
+        if storage.contains(absolute) {
+            Ok(())
+        } else {
+            Err(ServiceError::InvalidPath)
+        }
+    }
+
+    /// Resolve the directory to list for `relative`. Returns `None` when it
+    /// does not exist yet.
+    fn listing_dir(
         &self,
         user: &AuthenticatedUser,
         relative: Option<&str>,
-    ) -> ServiceResult<Vec<FileEntryDto>> {
+    ) -> ServiceResult<Option<PathBuf>> {
         let storage = self.authorize(user)?;
         let relative = Self::sanitize_path_param(relative)?;
-        self.ensure_hub_root(&storage)?;
+        self.existing_dir(&storage, &relative)
+    }
 
-        let target_path = storage.resolve_dir(&relative);
+    /// [`FileService::listing_dir`] for an already authorized hub.
+    fn existing_dir(
+        &self,
+        storage: &HubStorage,
+        relative: &RelativePath,
+    ) -> ServiceResult<Option<PathBuf>> {
+        self.ensure_hub_root(storage)?;
+
+        let target_path = storage.resolve_dir(relative);
+        Self::ensure_within_hub(storage, &target_path)?;
         if !target_path.exists() {
-            return Ok(vec![]);
+            return Ok(None);
         }
         if !target_path.is_dir() {
             return Err(ServiceError::InvalidPath);
         }
-
-        let mut entries: Vec<(StorageEntry, Option<SystemTime>)> = fs::read_dir(&target_path)
-            .map_err(ServiceError::ListEntries)?
-            .filter_map(|e| e.ok())
-            .filter_map(|entry| {
-                let file_type = entry.file_type().ok();
-                let is_directory = file_type.map(|ft| ft.is_dir()).unwrap_or(false);
-                let name = match FileName::try_from_str(&entry.file_name().to_string_lossy()) {
-                    Ok(name) => name,
-                    Err(_) => return None,
-                };
-                let created_at = entry.metadata().ok().and_then(|m| m.created().ok());
-                let kind = if is_directory {
-                    EntryKind::Directory
-                } else {
-                    EntryKind::File {
-                        is_image: name.is_image(),
-                    }
-                };
-
-                Some((StorageEntry::new(name, kind), created_at))
-            })
-            .collect();
-
-        entries.sort_by(|(a_entry, a_created), (b_entry, b_created)| {
-            match (a_entry.is_directory(), b_entry.is_directory()) {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                (true, true) => a_entry
-                    .name()
-                    .as_str()
-                    .to_lowercase()
-                    .cmp(&b_entry.name().as_str().to_lowercase()),
-                (false, false) => {
-                    let created_order = match (a_created, b_created) {
-                        (Some(a_time), Some(b_time)) => b_time.cmp(a_time),
-                        (Some(_), None) => std::cmp::Ordering::Less,
-                        (None, Some(_)) => std::cmp::Ordering::Greater,
-                        (None, None) => std::cmp::Ordering::Equal,
-                    };
-
-                    if created_order == std::cmp::Ordering::Equal {
-                        a_entry
-                            .name()
-                            .as_str()
-                            .to_lowercase()
-                            .cmp(&b_entry.name().as_str().to_lowercase())
-                    } else {
-                        created_order
-                    }
-                }
-            }
-        });
-
-        Ok(entries
-            .into_iter()
-            .map(|(entry, _)| FileEntryDto::from(entry))
-            .collect())
+        Ok(Some(target_path))
     }
 
-    /// Create a folder (and parents) within the hub storage.
-    pub fn create_folder(
+    /// Resolve an existing regular file of the user's hub. The
+    /// [`QUOTA_SIDECAR`] is reported missing, just like listings hide it.
+    fn existing_file(
         &self,
         user: &AuthenticatedUser,
-        current_path: Option<&str>,
-        form: &CreateFolderForm,
-    ) -> ServiceResult<()> {
-        form.validate()
-            .map_err(|e| ServiceError::Validation(e.to_string()))?;
-
+        relative: Option<&str>,
+        raw_file_name: &str,
+    ) -> ServiceResult<PathBuf> {
         let storage = self.authorize(user)?;
-        self.ensure_hub_root(&storage)?;
-
-        let current_path = Self::sanitize_path_param(current_path)?;
-        let new_path = RelativePath::try_from_str(&form.name)
-            .map_err(|_| ServiceError::Validation("Недопустимое имя папки".into()))?;
-        let combined = current_path.join(&new_path);
+        let relative = Self::sanitize_path_param(relative)?;
+        let file_name =
+            FileName::try_from_str(raw_file_name).map_err(|_| ServiceError::InvalidFileName)?;
+        if Self::is_quota_sidecar(&file_name) {
+            return Err(ServiceError::NotFound);
+        }
 
-        let path = storage.resolve_dir(&combined);
-        fs::create_dir_all(path).map_err(ServiceError::CreateFolder)
+        let path = storage.resolve_file(&relative, &file_name);
+        Self::ensure_within_hub(&storage, &path)?;
+        if !path.is_file() {
+            return Err(ServiceError::NotFound);
+        }
+        Ok(path)
     }
 
-    /// Persist an uploaded file into the hub storage at the provided path.
-    pub fn persist_upload(
+    /// Storage key of an existing regular file of the user's hub, after the
+    /// checks of [`FileService::existing_file`].
+    fn existing_file_key(
         &self,
         user: &AuthenticatedUser,
         relative: Option<&str>,
-        raw_file_name: Option<String>,
-        temp_file: TempFile,
-    ) -> ServiceResult<()> {
+        raw_file_name: &str,
+    ) -> ServiceResult<StorageKey> {
+        self.existing_file(user, relative, raw_file_name)?;
         let storage = self.authorize(user)?;
         let relative = Self::sanitize_path_param(relative)?;
-        let file_name = Self::sanitize_file_name(raw_file_name)?;
-        self.ensure_hub_root(&storage)?;
+        let file_name =
+            FileName::try_from_str(raw_file_name).map_err(|_| ServiceError::InvalidFileName)?;
+        Ok(storage.file_key(&relative, &file_name))
+    }
 
-        let target_dir = storage.resolve_dir(&relative);
-        fs::create_dir_all(&target_dir).map_err(ServiceError::SaveFile)?;
+    /// Read the visible entries of a directory along with the metadata used
+    /// to order them.
+    fn read_entries(&self, dir: &Path) -> ServiceResult<Vec<(StorageEntry, EntryStats)>> {
+        let entries = fs::read_dir(dir)
+            .map_err(ServiceError::ListEntries)?
+            .filter_map(|e| e.ok())
+            .filter_map(|entry| self.visible_entry(&entry))
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// List a folder through the storage backend on tokio's blocking pool, so
+    /// large folders do not stall the calling worker.
+    async fn read_entries_async(
+        &self,
+        key: StorageKey,
+    ) -> ServiceResult<Vec<(StorageEntry, EntryStats)>> {
+        let backend = Arc::clone(&self.backend);
+        let listed = tokio::task::spawn_blocking(move || backend.list(&key))
+            .await
+            .map_err(|err| ServiceError::ListEntries(std::io::Error::other(err)))?
+            .map_err(ServiceError::ListEntries)?;
+
+        Ok(listed
+            .iter()
+            .filter_map(|entry| {
+                let name = self.visible_name(std::ffi::OsStr::new(&entry.name))?;
+                Some(self.listed_entry(name, entry.is_directory, EntryStats::from(entry)))
+            })
+            .collect())
+    }
+
+    /// Turn a directory entry into a [`StorageEntry`] unless it is hidden.
+    fn visible_entry(&self, entry: &fs::DirEntry) -> Option<(StorageEntry, EntryStats)> {
+        let name = self.visible_name(&entry.file_name())?;
+        let is_directory = entry.file_type().is_ok_and(|ft| ft.is_dir());
+        let stats = entry
+            .metadata()
+            .map(|m| EntryStats::from(&m))
+            .unwrap_or_default();
+        Some(self.listed_entry(name, is_directory, stats))
+    }
+
+    /// Whether a hub-wide walk started at the hub root descends into or
+    /// reports `entry`: the [`RESERVED_DIRS`], the quota sidecar and ignored
+    /// names are skipped.
+    fn is_walked(&self, entry: &walkdir::DirEntry) -> bool {
+        let name = entry.file_name().to_string_lossy();
+        let reserved = entry.depth() == 1 && RESERVED_DIRS.contains(&name.as_ref());
+        !reserved && name != QUOTA_SIDECAR && !self.ignore_patterns.is_match(name.as_ref())
+    }
+
+    /// Name of a directory entry unless listings hide it.
+    fn visible_name(&self, raw: &std::ffi::OsStr) -> Option<FileName> {
+        let name = FileName::from_disk(raw).ok()?;
+        if name.as_str() == QUOTA_SIDECAR || self.ignore_patterns.is_match(name.as_str()) {
+            return None;
+        }
+        Some(name)
+    }
+
+    /// Build the listed entry and its ordering metadata.
+    fn listed_entry(
+        &self,
+        name: FileName,
+        is_directory: bool,
+        stats: EntryStats,
+    ) -> (StorageEntry, EntryStats) {
+        let kind = if is_directory {
+            EntryKind::Directory
+        } else {
+            EntryKind::File {
+                is_image: self.is_image(&name),
+            }
+        };
+
+        (StorageEntry::new(name, kind), stats)
+    }
+
+    /// Whether `entry` of the folder `relative` is one of the
+    /// [`RESERVED_DIRS`] at the hub root, which listings leave out.
+    fn is_reserved_entry(relative: &RelativePath, entry: &StorageEntry) -> bool {
+        relative.as_path().as_os_str().is_empty()
+            && entry.is_directory()
+            && RESERVED_DIRS.contains(&entry.name().as_str())
+    }
+
+    /// DTO of a listed entry, with the content type of files filled in.
+    fn entry_dto(&self, entry: StorageEntry) -> FileEntryDto {
+        let content_type =
+            (!entry.is_directory()).then(|| self.content_type_for(entry.name()).to_string());
+        FileEntryDto {
+            content_type,
+            ..FileEntryDto::from(entry)
+        }
+    }
+
+    /// Fill in the folder preview of a directory entry when enabled.
+    fn attach_folder_preview(&self, dto: &mut FileEntryDto, relative: &RelativePath, dir: &Path) {
+        if !self.folder_previews || !dto.is_directory {
+            return;
+        }
+        dto.folder_preview_image = self
+            .first_image(&dir.join(&dto.name))
+            .map(|image| preview_url(relative, &dto.name, &image));
+    }
+
+    /// Fill in how often a listed file of the folder at `dir_key` was
+    /// downloaded, when download counts are enabled.
+    fn attach_download_count(&self, dto: &mut FileEntryDto, dir_key: &StorageKey) {
+        let Some(counts) = &self.download_counts else {
+            return;
+        };
+        if dto.is_directory {
+            return;
+        }
+        if let Ok(name) = FileName::try_from_str(&dto.name) {
+            dto.downloads = counts.get(&dir_key.child(&name));
+        }
+    }
+
+    /// List the entries of the given relative path that pass `filter`, in
+    /// the requested order, returning DTOs for rendering. Dotfiles are left
+    /// out unless `show_hidden` is set.
+    pub async fn list_entries(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        filter: &EntryFilter,
+        sort: ListingSort,
+        show_hidden: bool,
+    ) -> ServiceResult<Vec<FileEntryDto>> {
+        let started = Instant::now();
+        let storage = self.authorize(user)?;
+        let relative = Self::sanitize_path_param(relative)?;
+        let Some(target_path) = self.existing_dir(&storage, &relative)? else {
+            return Ok(vec![]);
+        };
+        let mut entries = self.read_entries_async(storage.dir_key(&relative)).await?;
+        entries.retain(|(entry, _)| {
+            filter.matches(entry)
+                && (show_hidden || !entry.name().is_hidden())
+                && !Self::is_reserved_entry(&relative, entry)
+        });
+        Self::sort_entries(&mut entries, sort);
+
+        let mut dtos: Vec<FileEntryDto> = entries
+            .into_iter()
+            .map(|(entry, _)| self.entry_dto(entry))
+            .collect();
+        // Folder previews read every listed subfolder, so they stay off the
+        // async worker as well.
+        let service = self.clone();
+        let dtos = tokio::task::spawn_blocking(move || {
+            let dir_key = storage.dir_key(&relative);
+            for dto in dtos.iter_mut() {
+                service.attach_folder_preview(dto, &relative, &target_path);
+                service.attach_download_count(dto, &dir_key);
+            }
+            dtos
+        })
+        .await
+        .map_err(|err| ServiceError::ListEntries(std::io::Error::other(err)))?;
+        metrics::observe_listing(started.elapsed());
+        Ok(dtos)
+    }
+
+    /// Find files and folders anywhere in the hub whose name contains `query`,
+    /// ignoring case. The walk stays below `max_tree_depth`, never follows
+    /// symlinks and stops after [`MAX_SEARCH_RESULTS`] matches.
+    pub fn search(
+        &self,
+        user: &AuthenticatedUser,
+        query: &str,
+    ) -> ServiceResult<Vec<FileEntryDto>> {
+        let needle = query.trim().to_lowercase();
+        if needle.is_empty() {
+            return Err(ServiceError::Validation("Пустой поисковый запрос".into()));
+        }
+        let storage = self.authorize(user)?;
+        self.ensure_hub_root(&storage)?;
+        let hub_root = storage.hub_root();
+
+        let walker = WalkDir::new(&hub_root)
+            .follow_links(false)
+            .min_depth(1)
+            .max_depth(self.max_tree_depth.saturating_add(1))
+            .into_iter()
+            .filter_entry(|entry| self.is_walked(entry));
+        let mut results = Vec::new();
+        for entry in walker.filter_map(|e| e.ok()) {
+            let file_type = entry.file_type();
+            if file_type.is_symlink() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy();
+            if !name.to_lowercase().contains(&needle) {
+                continue;
+            }
+            let Ok(name) = FileName::from_disk(entry.file_name()) else {
+                continue;
+            };
+            let Some(folder) = entry
+                .path()
+                .parent()
+                .and_then(|parent| parent.strip_prefix(&hub_root).ok())
+            else {
+                continue;
+            };
+            let kind = if file_type.is_dir() {
+                EntryKind::Directory
+            } else {
+                EntryKind::File {
+                    is_image: self.is_image(&name),
+                }
+            };
+            let mut dto = self.entry_dto(StorageEntry::new(name, kind));
+            dto.path = Some(folder.to_string_lossy().to_string());
+            results.push(dto);
+            if results.len() >= MAX_SEARCH_RESULTS {
+                break;
+            }
+        }
+
+        results.sort_by(|a, b| (&a.path, &a.name).cmp(&(&b.path, &b.name)));
+        Ok(results)
+    }
+
+    /// The `limit` most recently modified files anywhere in the hub, newest
+    /// first, each with the folder holding it. Internal folders such as
+    /// `.trash` are skipped and at most [`RECENT_SCAN_LIMIT`] entries are
+    /// looked at.
+    pub fn recent(
+        &self,
+        user: &AuthenticatedUser,
+        limit: usize,
+    ) -> ServiceResult<Vec<FileEntryDto>> {
+        let limit = limit.clamp(1, MAX_RECENT_RESULTS);
+        let storage = self.authorize(user)?;
+        self.ensure_hub_root(&storage)?;
+        let hub_root = storage.hub_root();
+
+        let walker = WalkDir::new(&hub_root)
+            .follow_links(false)
+            .min_depth(1)
+            .max_depth(self.max_tree_depth.saturating_add(1))
+            .into_iter()
+            .filter_entry(|entry| self.is_walked(entry));
+        // Min-heap of the newest files seen so far.
+        let mut newest = BinaryHeap::new();
+        for entry in walker.take(RECENT_SCAN_LIMIT).filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let Some(modified) = entry.metadata().ok().and_then(|meta| meta.modified().ok()) else {
+                continue;
+            };
+            newest.push(Reverse((modified, entry.into_path())));
+            if newest.len() > limit {
+                newest.pop();
+            }
+        }
+
+        let mut results = Vec::with_capacity(newest.len());
+        for Reverse((_, path)) in newest.into_sorted_vec() {
+            let Some(name) = path
+                .file_name()
+                .and_then(|name| FileName::from_disk(name).ok())
+            else {
+                continue;
+            };
+            let Some(folder) = path
+                .parent()
+                .and_then(|parent| parent.strip_prefix(&hub_root).ok())
+            else {
+                continue;
+            };
+            let kind = EntryKind::File {
+                is_image: self.is_image(&name),
+            };
+            let mut dto = self.entry_dto(StorageEntry::new(name, kind));
+            dto.path = Some(folder.to_string_lossy().to_string());
+            results.push(dto);
+        }
+        Ok(results)
+    }
+
+    /// Read the entries of a folder lazily, in directory order, so large
+    /// listings can be rendered while the folder is still being read. Unlike
+    /// [`FileService::list_entries`] nothing is sorted.
+    pub fn stream_entries(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        filter: EntryFilter,
+        show_hidden: bool,
+    ) -> ServiceResult<Box<dyn Iterator<Item = FileEntryDto> + Send>> {
+        let storage = self.authorize(user)?;
+        let relative = Self::sanitize_path_param(relative)?;
+        let Some(target_path) = self.existing_dir(&storage, &relative)? else {
+            return Ok(Box::new(std::iter::empty()));
+        };
+        let read_dir = fs::read_dir(&target_path).map_err(ServiceError::ListEntries)?;
+        let dir_key = storage.dir_key(&relative);
+        let service = self.clone();
+
+        Ok(Box::new(read_dir.filter_map(|e| e.ok()).filter_map(
+            move |entry| {
+                let (entry, _) = service.visible_entry(&entry)?;
+                if !filter.matches(&entry)
+                    || (!show_hidden && entry.name().is_hidden())
+                    || Self::is_reserved_entry(&relative, &entry)
+                {
+                    return None;
+                }
+                let mut dto = service.entry_dto(entry);
+                service.attach_folder_preview(&mut dto, &relative, &target_path);
+                service.attach_download_count(&mut dto, &dir_key);
+                Some(dto)
+            },
+        )))
+    }
+
+    /// Name of the alphabetically first image among the first few entries of
+    /// `dir`. Only one directory level is read.
+    fn first_image(&self, dir: &Path) -> Option<String> {
+        fs::read_dir(dir)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .take(FOLDER_PREVIEW_SCAN_LIMIT)
+            .filter(|entry| entry.file_type().map(|ft| ft.is_file()).unwrap_or(false))
+            .filter_map(|entry| FileName::from_disk(&entry.file_name()).ok())
+            .filter(|name| self.is_image(name) && !self.ignore_patterns.is_match(name.as_str()))
+            .map(FileName::into_string)
+            .min()
+    }
+
+    /// List a folder of the shared library. Any user with the service role
+    /// may read it regardless of hub; the library itself is never written to.
+    pub fn list_shared(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+    ) -> ServiceResult<Vec<FileEntryDto>> {
+        self.authorize(user)?;
+        let shared = self.shared.as_ref().ok_or(ServiceError::NotFound)?;
+        let relative = Self::sanitize_path_param(relative)?;
+
+        let target_path = shared.resolve_dir(&relative);
+        if !target_path.exists() {
+            return Ok(vec![]);
+        }
+        if !shared.contains(&target_path) || !target_path.is_dir() {
+            return Err(ServiceError::InvalidPath);
+        }
+        let mut entries = self.read_entries(&target_path)?;
+        Self::sort_entries(&mut entries, ListingSort::Default);
+
+        Ok(entries
+            .into_iter()
+            .map(|(entry, _)| self.entry_dto(entry))
+            .collect())
+    }
+
+    /// Resolve a file of the user's hub for download. Quota sidecars stay
+    /// private just like in listings.
+    pub fn download_file(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_name: &str,
+    ) -> ServiceResult<PathBuf> {
+        self.existing_file(user, relative, raw_name)
+    }
+
+    /// Count a served download of a file of the user's hub.
+    pub fn record_download(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_name: &str,
+    ) {
+        let Some(counts) = &self.download_counts else {
+            return;
+        };
+        if let Ok(key) = self.existing_file_key(user, relative, raw_name) {
+            counts.record(&key);
+        }
+    }
+
+    /// Resolve a file of the shared library for download.
+    pub fn shared_file(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_name: &str,
+    ) -> ServiceResult<PathBuf> {
+        self.authorize(user)?;
+        let shared = self.shared.as_ref().ok_or(ServiceError::NotFound)?;
+        let relative = Self::sanitize_path_param(relative)?;
+        let name = FileName::try_from_str(raw_name).map_err(|_| ServiceError::InvalidFileName)?;
+
+        // Check containment first so paths escaping the library are
+        // indistinguishable from missing files.
+        let path = shared.resolve_file(&relative, &name);
+        if !shared.contains(&path) || !path.is_file() {
+            return Err(ServiceError::NotFound);
+        }
+        Ok(path)
+    }
+
+    /// Directories before files, each group ordered by `sort`. Ties go by
+    /// name so the order is stable across requests.
+    fn sort_entries(entries: &mut [(StorageEntry, EntryStats)], sort: ListingSort) {
+        let ListingSort::By { key, descending } = sort else {
+            return Self::sort_entries_default(entries);
+        };
+        let by_name = |a: &StorageEntry, b: &StorageEntry| {
+            a.name()
+                .as_str()
+                .to_lowercase()
+                .cmp(&b.name().as_str().to_lowercase())
+        };
+        entries.sort_by(|(a_entry, a_stats), (b_entry, b_stats)| {
+            let order = match key {
+                SortKey::Name => by_name(a_entry, b_entry),
+                SortKey::Natural => natural_cmp(a_entry.name().as_str(), b_entry.name().as_str()),
+                SortKey::Size => a_stats.size.cmp(&b_stats.size),
+                SortKey::Modified => a_stats.modified.cmp(&b_stats.modified),
+            };
+            let order = if descending { order.reverse() } else { order };
+            b_entry
+                .is_directory()
+                .cmp(&a_entry.is_directory())
+                .then(order)
+                .then_with(|| by_name(a_entry, b_entry))
+        });
+    }
+
+    /// Directories first by name, then files newest first.
+    fn sort_entries_default(entries: &mut [(StorageEntry, EntryStats)]) {
+        entries.sort_by(|(a_entry, a_stats), (b_entry, b_stats)| {
+            match (a_entry.is_directory(), b_entry.is_directory()) {
+                (true, false) => std::cmp::Ordering::Less,
+                (false, true) => std::cmp::Ordering::Greater,
+                (true, true) => a_entry
+                    .name()
+                    .as_str()
+                    .to_lowercase()
+                    .cmp(&b_entry.name().as_str().to_lowercase()),
+                (false, false) => {
+                    let created_order = match (a_stats.created, b_stats.created) {
+                        (Some(a_time), Some(b_time)) => b_time.cmp(a_time),
+                        (Some(_), None) => std::cmp::Ordering::Less,
+                        (None, Some(_)) => std::cmp::Ordering::Greater,
+                        (None, None) => std::cmp::Ordering::Equal,
+                    };
+
+                    if created_order == std::cmp::Ordering::Equal {
+                        a_entry
+                            .name()
+                            .as_str()
+                            .to_lowercase()
+                            .cmp(&b_entry.name().as_str().to_lowercase())
+                    } else {
+                        created_order
+                    }
+                }
+            }
+        });
+    }
+
+    /// Prepare a long-poll watch on an existing folder, reserving one of the
+    /// hub's watcher slots. The returned watch is `'static` so it can block
+    /// on a worker thread.
+    pub fn watch(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+    ) -> ServiceResult<PendingWatch> {
+        let storage = self.authorize(user)?;
+        let dir = self
+            .listing_dir(user, relative)?
+            .ok_or(ServiceError::NotFound)?;
+        let slot = self.watchers.acquire(storage.hub_id())?;
+        Ok(PendingWatch::new(dir, slot))
+    }
+
+    /// Count the entries of a folder matching `filter` without building DTOs.
+    pub fn count_entries(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        filter: &EntryFilter,
+    ) -> ServiceResult<EntryCountsDto> {
+        let mut counts = EntryCountsDto::default();
+        let Some(target_path) = self.listing_dir(user, relative)? else {
+            return Ok(counts);
+        };
+
+        for (entry, _) in self.read_entries(&target_path)? {
+            if !filter.matches(&entry) {
+                continue;
+            }
+            if entry.is_directory() {
+                counts.directories += 1;
+            } else {
+                counts.files += 1;
+            }
+            if entry.is_image() {
+                counts.images += 1;
+            }
+        }
+
+        Ok(counts)
+    }
+
+    /// Count the files of a folder by lowercased extension. Directories are
+    /// grouped under `"dir"` and extensionless files under `""`.
+    pub fn extension_counts(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+    ) -> ServiceResult<BTreeMap<String, usize>> {
+        let mut counts = BTreeMap::new();
+        let Some(target_path) = self.listing_dir(user, relative)? else {
+            return Ok(counts);
+        };
+
+        for (entry, _) in self.read_entries(&target_path)? {
+            let key = if entry.is_directory() {
+                "dir".to_string()
+            } else {
+                entry.name().extension().unwrap_or_default()
+            };
+            *counts.entry(key).or_insert(0) += 1;
+        }
+
+        Ok(counts)
+    }
+
+    /// Sum file sizes under the hub root grouped by file category. Symlinks
+    /// are not followed.
+    pub fn storage_breakdown(
+        &self,
+        user: &AuthenticatedUser,
+        depth: Option<i64>,
+    ) -> ServiceResult<StorageBreakdownDto> {
+        let depth = self.effective_depth(depth)?;
+        let storage = self.authorize(user)?;
+        self.ensure_hub_root(&storage)?;
+
+        let mut breakdown = StorageBreakdownDto::default();
+        for entry in WalkDir::new(storage.hub_root())
+            .follow_links(false)
+            .max_depth(depth.saturating_add(1))
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let Ok(name) = FileName::try_from_str(&entry.file_name().to_string_lossy()) else {
+                continue;
+            };
+            let bytes = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            breakdown.record(self.category_of(&name), bytes);
+        }
+
+        Ok(breakdown)
+    }
+
+    /// Turn a client-requested traversal depth into the one actually used:
+    /// negative values are rejected and anything beyond `max_tree_depth` is
+    /// clamped to it. `None` means the configured maximum.
+    pub fn effective_depth(&self, requested: Option<i64>) -> ServiceResult<usize> {
+        match requested {
+            None => Ok(self.max_tree_depth),
+            Some(depth) if depth < 0 => Err(ServiceError::Validation(
+                "Глубина не может быть отрицательной".into(),
+            )),
+            Some(depth) => Ok(usize::try_from(depth)
+                .unwrap_or(usize::MAX)
+                .min(self.max_tree_depth)),
+        }
+    }
+
+    /// Compute the deepest directory nesting below `relative`, stopping once
+    /// the requested depth (clamped to the configured cap) is exceeded.
+    pub fn folder_depth(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        depth: Option<i64>,
+    ) -> ServiceResult<FolderDepthDto> {
+        let cap = self.effective_depth(depth)?;
+        let storage = self.authorize(user)?;
+        let relative = Self::sanitize_path_param(relative)?;
+        self.ensure_hub_root(&storage)?;
+
+        let target_path = storage.resolve_dir(&relative);
+        Self::ensure_within_hub(&storage, &target_path)?;
+        if !target_path.exists() {
+            return Ok(FolderDepthDto::default());
+        }
+        if !target_path.is_dir() {
+            return Err(ServiceError::InvalidPath);
+        }
+
+        let mut result = FolderDepthDto::default();
+        for entry in WalkDir::new(&target_path)
+            .follow_links(false)
+            .min_depth(1)
+            .max_depth(cap.saturating_add(1))
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_dir())
+        {
+            if entry.depth() > cap {
+                result.capped = true;
+                break;
+            }
+            result.depth = result.depth.max(entry.depth());
+        }
+
+        Ok(result)
+    }
+
+    /// Nested folders and files of the whole hub for the sidebar, down to
+    /// `max_depth` levels (clamped to `max_tree_depth`). Symlinks are never
+    /// followed, so links cannot make the walk loop, and the tree is built
+    /// without recursion. Hidden and ignored entries are left out.
+    pub fn list_tree(
+        &self,
+        user: &AuthenticatedUser,
+        max_depth: Option<i64>,
+    ) -> ServiceResult<TreeNodeDto> {
+        let cap = self.effective_depth(max_depth)?;
+        let storage = self.authorize(user)?;
+        self.ensure_hub_root(&storage)?;
+
+        let walker = WalkDir::new(storage.hub_root())
+            .follow_links(false)
+            .max_depth(cap.saturating_add(1))
+            .sort_by(|a, b| {
+                b.file_type()
+                    .is_dir()
+                    .cmp(&a.file_type().is_dir())
+                    .then_with(|| a.file_name().cmp(b.file_name()))
+            })
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.depth() == 0
+                    || (!entry.path_is_symlink()
+                        && self
+                            .visible_name(entry.file_name())
+                            .is_some_and(|name| !name.is_hidden()))
+            });
+
+        // Folders still open, one per level from the hub root down.
+        let mut open = vec![TreeNodeDto {
+            is_directory: true,
+            ..TreeNodeDto::default()
+        }];
+        for entry in walker.filter_map(|e| e.ok()).skip(1) {
+            Self::close_tree_levels(&mut open, entry.depth());
+            let Some(parent) = open.last_mut() else {
+                break;
+            };
+            if entry.depth() > cap {
+                parent.truncated = true;
+                continue;
+            }
+            let node = TreeNodeDto {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_directory: entry.file_type().is_dir(),
+                ..TreeNodeDto::default()
+            };
+            if node.is_directory {
+                open.push(node);
+            } else {
+                parent.children.push(node);
+            }
+        }
+        Self::close_tree_levels(&mut open, 1);
+
+        Ok(open.pop().unwrap_or_default())
+    }
+
+    /// Attach the open folders deeper than `depth` to their parents, keeping
+    /// at least the hub root open.
+    fn close_tree_levels(open: &mut Vec<TreeNodeDto>, depth: usize) {
+        while open.len() > depth.max(1) {
+            let Some(done) = open.pop() else {
+                break;
+            };
+            if let Some(parent) = open.last_mut() {
+                parent.children.push(done);
+            }
+        }
+    }
+
+    /// Create a folder (and parents) within the hub storage. Returns whether
+    /// this call created it; when it already exists the result depends on
+    /// `form.exist_ok`. The final directory is created atomically, so of
+    /// several racing callers exactly one sees it as created.
+    pub async fn create_folder(
+        &self,
+        user: &AuthenticatedUser,
+        current_path: Option<&str>,
+        form: &CreateFolderForm,
+    ) -> ServiceResult<bool> {
+        form.validate()
+            .map_err(|e| ServiceError::Validation(e.to_string()))?;
+
+        let storage = self.authorize(user)?;
+        self.ensure_hub_root(&storage)?;
+
+        let current_path = Self::sanitize_path_param(current_path)?;
+        let new_path = RelativePath::try_from_str(&form.name)
+            .map_err(|_| ServiceError::Validation("Недопустимое имя папки".into()))?;
+        let charset_ok = new_path.as_path().components().all(|c| {
+            self.folder_name_charset
+                .allows(&c.as_os_str().to_string_lossy())
+        });
+        if !charset_ok {
+            return Err(ServiceError::Validation(
+                "Имя папки содержит недопустимые символы".into(),
+            ));
+        }
+        let combined = current_path.join(&new_path);
+        Self::ensure_writable(&combined)?;
+
+        let path = storage.resolve_dir(&combined);
+        Self::ensure_within_hub(&storage, &path)?;
+        let backend = Arc::clone(&self.backend);
+        let key = storage.dir_key(&combined);
+        let created = tokio::task::spawn_blocking(move || backend.create_dir(&key))
+            .await
+            .map_err(|err| ServiceError::CreateFolder(std::io::Error::other(err)))?;
+        match created {
+            Ok(true) => {}
+            Ok(false) if form.exist_ok => return Ok(false),
+            Ok(false) => return Err(ServiceError::FileExists),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(ServiceError::FileExists);
+            }
+            Err(err) => return Err(ServiceError::CreateFolder(err)),
+        }
+
+        metrics::record_folder_create();
+        self.audit(user, AuditOperation::CreateFolder, combined.as_path());
+        self.replay_on_mirror(MirrorOp::CreateDir {
+            hub_id: storage.hub_id().clone(),
+            relative: combined,
+        });
+        Ok(true)
+    }
+
+    /// Create an empty file in the existing folder `current_path`. Names
+    /// already taken by a file or folder are refused.
+    pub fn create_file(
+        &self,
+        user: &AuthenticatedUser,
+        current_path: Option<&str>,
+        raw_file_name: &str,
+    ) -> ServiceResult<FileEntryDto> {
+        let storage = self.authorize(user)?;
+        self.ensure_hub_root(&storage)?;
+        let relative = Self::sanitize_path_param(current_path)?;
+        Self::ensure_writable(&relative)?;
+        let file_name = Self::sanitize_file_name(Some(raw_file_name.to_string()))?;
+        if self.is_reserved_sidecar(&file_name) {
+            return Err(ServiceError::InvalidFileName);
+        }
+        if self.is_spoofed_extension(&file_name) {
+            return Err(ServiceError::DisallowedExtension);
+        }
+        if !self.is_allowed_extension(&file_name) {
+            return Err(ServiceError::ForbiddenExtension);
+        }
+
+        let parent = storage.resolve_dir(&relative);
+        let path = storage.resolve_file(&relative, &file_name);
+        Self::ensure_within_hub(&storage, &parent)?;
+        Self::ensure_within_hub(&storage, &path)?;
+        if !parent.is_dir() {
+            return Err(ServiceError::NotFound);
+        }
+        if path.symlink_metadata().is_ok_and(|meta| meta.is_dir()) {
+            return Err(ServiceError::Validation(
+                "Папка с таким именем уже существует".into(),
+            ));
+        }
+        // `create_new` makes the existence check and the creation one step,
+        // so a concurrent writer is never truncated.
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::AlreadyExists => ServiceError::FileExists,
+                _ => ServiceError::SaveFile(err),
+            })?;
+
+        self.audit(
+            user,
+            AuditOperation::CreateFile,
+            &relative.as_path().join(file_name.as_str()),
+        );
+        self.replay_on_mirror(MirrorOp::WriteFile {
+            hub_id: storage.hub_id().clone(),
+            relative,
+            name: file_name.clone(),
+        });
+        let kind = EntryKind::File {
+            is_image: self.is_image(&file_name),
+        };
+        Ok(self.entry_dto(StorageEntry::new(file_name, kind)))
+    }
+
+    /// Delete a single file of the current folder. Directories are refused;
+    /// symlinks are removed without touching their target.
+    pub fn delete_file(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: &str,
+    ) -> ServiceResult<()> {
+        let storage = self.authorize(user)?;
+        let relative = Self::sanitize_path_param(relative)?;
+        Self::ensure_writable(&relative)?;
+        let file_name = Self::sanitize_file_name(Some(raw_file_name.to_string()))?;
+
+        let path = storage.resolve_file(&relative, &file_name);
+        Self::ensure_within_hub(&storage, &path)?;
+        match fs::symlink_metadata(&path) {
+            Ok(meta) if meta.is_dir() => {
+                return Err(ServiceError::Validation("Папки удаляются отдельно".into()));
+            }
+            Ok(_) => {}
+            Err(_) => return Err(ServiceError::NotFound),
+        }
+        if self.soft_delete {
+            let entry = RelativePath::try_new(file_name.to_path_buf())
+                .map_err(|_| ServiceError::InvalidFileName)?;
+            Self::move_to_trash(&storage, &path, &relative.join(&entry))?;
+        } else {
+            self.backend
+                .delete(&storage.file_key(&relative, &file_name))
+                .map_err(ServiceError::DeleteEntry)?;
+        }
+
+        self.audit(
+            user,
+            AuditOperation::DeleteFile,
+            &relative.as_path().join(file_name.as_str()),
+        );
+        self.forget_downloads(&storage.file_key(&relative, &file_name));
+        self.replay_on_mirror(MirrorOp::DeleteFile {
+            hub_id: storage.hub_id().clone(),
+            relative: relative.clone(),
+            name: file_name,
+        });
+        self.prune_empty_parents(&storage, &storage.resolve_dir(&relative));
+        Ok(())
+    }
+
+    /// Delete several files of the current folder, one [`DeleteResultDto`]
+    /// per name in the given order. A failing name does not stop the others;
+    /// only a missing role or an invalid `relative` fails the whole call.
+    pub fn delete_many(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        names: Vec<String>,
+    ) -> ServiceResult<Vec<DeleteResultDto>> {
+        self.authorize(user)?;
+        let folder = Self::sanitize_path_param(relative)?;
+        Self::ensure_writable(&folder)?;
+        if names.is_empty() {
+            return Err(ServiceError::Validation("Не выбраны файлы".into()));
+        }
+
+        Ok(names
+            .into_iter()
+            .map(|name| {
+                let error = match self.delete_file(user, relative, &name) {
+                    Ok(()) => None,
+                    Err(ServiceError::NotFound) => Some("Файл не найден".into()),
+                    Err(ServiceError::Validation(msg)) => Some(msg),
+                    Err(ServiceError::InvalidFileName) | Err(ServiceError::InvalidPath) => {
+                        Some("Некорректное имя файла".into())
+                    }
+                    Err(e) => {
+                        log::error!("Failed to delete {name:?}: {e:?}");
+                        Some("Не удалось удалить файл".into())
+                    }
+                };
+                DeleteResultDto {
+                    name,
+                    deleted: error.is_none(),
+                    error,
+                }
+            })
+            .collect())
+    }
+
+    /// Delete a folder of the current folder. Without `recursive` only empty
+    /// folders are removed and [`ServiceError::FolderNotEmpty`] is returned
+    /// otherwise. The hub root and reserved directories are never removed.
+    pub fn delete_folder(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_name: &str,
+        recursive: bool,
+    ) -> ServiceResult<()> {
+        let storage = self.authorize(user)?;
+        let relative = Self::sanitize_path_param(relative)?;
+        let name = FileName::try_from_str(raw_name).map_err(|_| ServiceError::InvalidFileName)?;
+        let folder = relative.join(
+            &RelativePath::try_from_str(name.as_str()).map_err(|_| ServiceError::InvalidPath)?,
+        );
+        Self::ensure_writable(&folder)?;
+
+        let path = storage.resolve_dir(&folder);
+        Self::ensure_within_hub(&storage, &path)?;
+        if path == storage.hub_root() {
+            return Err(ServiceError::InvalidPath);
+        }
+        match fs::symlink_metadata(&path) {
+            Ok(meta) if meta.is_dir() => {}
+            Ok(_) => {
+                return Err(ServiceError::Validation("Файлы удаляются отдельно".into()));
+            }
+            Err(_) => return Err(ServiceError::NotFound),
+        }
+
+        if self.soft_delete {
+            let has_entries = fs::read_dir(&path)
+                .map_err(ServiceError::DeleteEntry)?
+                .next()
+                .is_some();
+            if has_entries && !recursive {
+                return Err(ServiceError::FolderNotEmpty);
+            }
+            Self::move_to_trash(&storage, &path, &folder)?;
+        } else {
+            let removed = if recursive {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_dir(&path)
+            };
+            removed.map_err(|err| {
+                if err.kind() == std::io::ErrorKind::DirectoryNotEmpty {
+                    ServiceError::FolderNotEmpty
+                } else {
+                    ServiceError::DeleteEntry(err)
+                }
+            })?;
+        }
+
+        self.audit(user, AuditOperation::DeleteFolder, folder.as_path());
+        self.forget_downloads(&storage.dir_key(&folder));
+        self.replay_on_mirror(MirrorOp::DeleteDir {
+            hub_id: storage.hub_id().clone(),
+            relative: folder,
+        });
+        self.prune_empty_parents(&storage, &storage.resolve_dir(&relative));
+        Ok(())
+    }
+
+    /// Move `path`, the hub entry at `entry`, below a fresh
+    /// `.trash/<milliseconds since epoch>` folder, keeping its location.
+    /// When that spot is already taken, e.g. by the same path deleted within
+    /// the same millisecond, the folder gets a `-1`, `-2`, ... suffix.
+    fn move_to_trash(storage: &HubStorage, path: &Path, entry: &RelativePath) -> ServiceResult<()> {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let trash = storage.hub_root().join(TRASH_DIR);
+        let target = (0..=MAX_NAME_SUFFIX)
+            .map(|n| match n {
+                0 => stamp.to_string(),
+                n => format!("{stamp}-{n}"),
+            })
+            .map(|folder| trash.join(folder).join(entry.as_path()))
+            .find(|target| target.symlink_metadata().is_err())
+            .ok_or(ServiceError::FileExists)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(ServiceError::DeleteEntry)?;
+        }
+        fs::rename(path, &target).map_err(ServiceError::DeleteEntry)
+    }
+
+    /// Move a soft-deleted entry back to where it was deleted from.
+    /// `trashed` is its path inside [`TRASH_DIR`], starting with the
+    /// timestamp folder (e.g. `1700000000000/docs/report.pdf`). Existing
+    /// entries are never replaced.
+    pub fn restore(&self, user: &AuthenticatedUser, trashed: &str) -> ServiceResult<()> {
+        let storage = self.authorize(user)?;
+        let trashed = RelativePath::try_from_str(trashed).map_err(|_| ServiceError::InvalidPath)?;
+        let mut components = trashed.as_path().components();
+        components.next();
+        let original = RelativePath::try_new(components.as_path().to_path_buf())
+            .map_err(|_| ServiceError::InvalidPath)?;
+        if original.as_path().as_os_str().is_empty() {
+            return Err(ServiceError::InvalidPath);
+        }
+        Self::ensure_writable(&original)?;
+
+        let trash = storage.hub_root().join(TRASH_DIR);
+        let source = trash.join(trashed.as_path());
+        let target = storage.hub_root().join(original.as_path());
+        Self::ensure_within_hub(&storage, &source)?;
+        Self::ensure_within_hub(&storage, &target)?;
+        if source.symlink_metadata().is_err() {
+            return Err(ServiceError::NotFound);
+        }
+        if target.symlink_metadata().is_ok() {
+            return Err(ServiceError::FileExists);
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(ServiceError::MoveEntry)?;
+        }
+        fs::rename(&source, &target).map_err(ServiceError::MoveEntry)?;
+        self.audit(user, AuditOperation::Restore, original.as_path());
+
+        // Drop the folders of the trash the restore left empty.
+        for ancestor in source.ancestors().skip(1) {
+            if ancestor == trash || fs::remove_dir(ancestor).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Permanently remove everything in the hub's trash.
+    pub fn empty_trash(&self, user: &AuthenticatedUser) -> ServiceResult<()> {
+        let storage = self.authorize(user)?;
+        self.ensure_hub_root(&storage)?;
+        let trash = storage.hub_root().join(TRASH_DIR);
+        Self::ensure_within_hub(&storage, &trash)?;
+        match fs::remove_dir_all(&trash) {
+            Ok(()) => {
+                self.audit(user, AuditOperation::EmptyTrash, Path::new(TRASH_DIR));
+                Ok(())
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(ServiceError::DeleteEntry(err)),
+        }
+    }
+
+    /// Persist an uploaded file into the hub storage at the provided path.
+    /// `overwrite` replaces an existing file whatever the [`ConflictPolicy`];
+    /// otherwise the policy decides, so the stored name may differ from the
+    /// uploaded one.
+    ///
+    /// The checks and the write run on tokio's blocking pool since they may
+    /// decode images and walk quota folders.
+    pub async fn persist_upload(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: Option<String>,
+        temp_file: TempFile,
+        overwrite: bool,
+    ) -> ServiceResult<FileInfoDto> {
+        self.persist_upload_file(user, relative, raw_file_name, temp_file.file, overwrite)
+            .await
+    }
+
+    /// [`FileService::persist_upload`] for a file received outside a
+    /// multipart form, such as one field of a streamed batch.
+    pub async fn persist_upload_file(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: Option<String>,
+        file: NamedTempFile,
+        overwrite: bool,
+    ) -> ServiceResult<FileInfoDto> {
+        let storage = self.authorize(user)?;
+        let folder = Self::sanitize_path_param(relative)?;
+        let service = self.clone();
+        let target = relative.map(str::to_string);
+        let entry = tokio::task::spawn_blocking(move || {
+            service.store_file(&storage, target.as_deref(), raw_file_name, file, overwrite)
+        })
+        .await
+        .map_err(|e| ServiceError::SaveFile(std::io::Error::other(e)))??;
+        self.audit(
+            user,
+            AuditOperation::Upload,
+            &folder.as_path().join(entry.name().as_str()),
+        );
+        let info = self.file_info(user, relative, entry.name().as_str())?;
+        metrics::record_upload(info.size);
+        Ok(info)
+    }
+
+    /// Whether an uploaded file name asks for [`FileService::extract_upload`].
+    pub fn is_zip_name(name: &str) -> bool {
+        FileName::try_from_str(name).is_ok_and(|name| name.extension().as_deref() == Some("zip"))
+    }
+
+    /// Unpack an uploaded zip archive into `relative`, keeping its folder
+    /// structure. The whole archive is refused before anything is written
+    /// when an entry is absolute or climbs out with `..`; every file is then
+    /// stored like a regular upload, so the hub's limits apply to each one.
+    pub async fn extract_upload(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        temp_file: TempFile,
+        overwrite: bool,
+    ) -> ServiceResult<ExtractResultDto> {
+        let storage = self.authorize(user)?;
+        let folder = Self::sanitize_path_param(relative)?;
+        let service = self.clone();
+        let target = relative.map(str::to_string);
+        let files = tokio::task::spawn_blocking(move || {
+            service.extract_archive(&storage, target.as_deref(), temp_file.file, overwrite)
+        })
+        .await
+        .map_err(|e| ServiceError::SaveFile(std::io::Error::other(e)))??;
+        for file in &files {
+            self.audit(user, AuditOperation::Upload, &folder.as_path().join(file));
+        }
+        Ok(ExtractResultDto { files })
+    }
+
+    fn extract_archive(
+        &self,
+        storage: &HubStorage,
+        relative: Option<&str>,
+        file: NamedTempFile,
+        overwrite: bool,
+    ) -> ServiceResult<Vec<String>> {
+        let base = Self::sanitize_path_param(relative)?;
+        Self::ensure_writable(&base)?;
+        let invalid =
+            |e: zip::result::ZipError| ServiceError::Validation(format!("Некорректный архив: {e}"));
+        let mut archive = zip::ZipArchive::new(file.as_file()).map_err(invalid)?;
+
+        let mut entries = Vec::with_capacity(archive.len());
+        for index in 0..archive.len() {
+            let entry = archive.by_index_raw(index).map_err(invalid)?;
+            entries.push((
+                index,
+                Self::archive_entry_path(entry.name())?,
+                entry.is_dir(),
+            ));
+        }
+
+        self.ensure_hub_root(storage)?;
+        let limit = self.upload_limit_for(storage.hub_id());
+        let mut stored = Vec::new();
+        for (index, path, is_dir) in entries {
+            if is_dir {
+                let folder = base.join(&path);
+                Self::ensure_writable(&folder)?;
+                let dir = storage.resolve_dir(&folder);
+                Self::ensure_within_hub(storage, &dir)?;
+                fs::create_dir_all(&dir).map_err(ServiceError::CreateFolder)?;
+                continue;
+            }
+            let Some(name) = path.as_path().file_name() else {
+                return Err(ServiceError::InvalidFileName);
+            };
+            let parent = path.as_path().parent().unwrap_or(Path::new(""));
+            let folder = base.join(
+                &RelativePath::try_new(parent.to_path_buf())
+                    .map_err(|_| ServiceError::InvalidPath)?,
+            );
+
+            // Read one byte past the limit so oversized entries are refused
+            // by `store_file` without unpacking all of them.
+            let mut entry = archive.by_index(index).map_err(invalid)?;
+            let mut temp = NamedTempFile::new().map_err(ServiceError::SaveFile)?;
+            std::io::copy(
+                &mut entry.by_ref().take(limit.saturating_add(1)),
+                temp.as_file_mut(),
+            )
+            .map_err(ServiceError::SaveFile)?;
+            let size = temp
+                .as_file()
+                .metadata()
+                .map_err(ServiceError::SaveFile)?
+                .len();
+
+            let written = self.store_file(
+                storage,
+                Some(&folder.as_path().to_string_lossy()),
+                Some(name.to_string_lossy().into_owned()),
+                temp,
+                overwrite,
+            )?;
+            metrics::record_upload(size);
+            stored.push(
+                parent
+                    .join(written.name().as_str())
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
+        Ok(stored)
+    }
+
+    /// Clean relative path of an archive entry. Either separator is accepted;
+    /// absolute names, drive prefixes and `..` are refused.
+    fn archive_entry_path(raw: &str) -> ServiceResult<RelativePath> {
+        let segments = raw.split(['/', '\\']);
+        let drive = segments
+            .clone()
+            .next()
+            .is_some_and(|first| first.contains(':'));
+        if raw.starts_with(['/', '\\']) || drive {
+            return Err(ServiceError::InvalidPath);
+        }
+
+        let mut path = PathBuf::new();
+        for segment in segments.filter(|s| !s.is_empty() && *s != ".") {
+            if segment == ".." {
+                return Err(ServiceError::InvalidPath);
+            }
+            let name =
+                FileName::try_from_str(segment).map_err(|_| ServiceError::InvalidFileName)?;
+            path.push(name.as_str());
+        }
+        RelativePath::try_new(path).map_err(|_| ServiceError::InvalidPath)
+    }
+
+    /// Store part `index` of `total` of the chunked upload `upload_id`, a
+    /// client chosen UUID. Parts may arrive in any order and may be resent;
+    /// together they must stay within the hub's upload limit. Unfinished
+    /// uploads of the hub older than the configured TTL are removed first.
+    pub async fn store_chunk(
+        &self,
+        user: &AuthenticatedUser,
+        upload_id: &str,
+        index: u32,
+        total: u32,
+        chunk: NamedTempFile,
+    ) -> ServiceResult<()> {
+        let storage = self.authorize(user)?;
+        self.ensure_hub_root(&storage)?;
+        if total == 0 || total > MAX_UPLOAD_CHUNKS || index >= total {
+            return Err(ServiceError::Validation("Некорректный номер части".into()));
+        }
+        let dir = Self::chunk_dir(&storage, upload_id)?;
+        let service = self.clone();
+        tokio::task::spawn_blocking(move || {
+            service.remove_stale_uploads(&storage);
+            service.write_chunk(&storage, &dir, index, total, chunk)
+        })
+        .await
+        .map_err(|e| ServiceError::SaveFile(std::io::Error::other(e)))?
+    }
+
+    fn write_chunk(
+        &self,
+        storage: &HubStorage,
+        dir: &Path,
+        index: u32,
+        total: u32,
+        chunk: NamedTempFile,
+    ) -> ServiceResult<()> {
+        fs::create_dir_all(dir).map_err(ServiceError::SaveFile)?;
+        let total_file = dir.join("total");
+        match fs::read_to_string(&total_file) {
+            Ok(recorded) if recorded.trim() != total.to_string() => {
+                return Err(ServiceError::Validation(
+                    "Число частей не совпадает с начатой загрузкой".into(),
+                ));
+            }
+            Ok(_) => {}
+            Err(_) => fs::write(&total_file, total.to_string()).map_err(ServiceError::SaveFile)?,
+        }
+
+        let part = dir.join(format!("{index}.part"));
+        let stored: u64 = fs::read_dir(dir)
+            .map_err(ServiceError::SaveFile)?
+            .filter_map(|e| e.ok())
+            .filter(|entry| {
+                entry.path() != part && entry.path().extension() == Some("part".as_ref())
+            })
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|meta| meta.len())
+            .sum();
+        let size = chunk
+            .as_file()
+            .metadata()
+            .map_err(ServiceError::SaveFile)?
+            .len();
+        let limit = self.upload_limit_for(storage.hub_id());
+        if stored.saturating_add(size) > limit {
+            return Err(ServiceError::FileTooLarge { limit });
+        }
+        persist_file(chunk, &part, true).map_err(ServiceError::SaveFile)?;
+        Ok(())
+    }
+
+    /// Join the parts of a chunked upload and store the result under
+    /// `raw_file_name` like a regular upload. The parts are removed once the
+    /// file is stored; on failure they are kept so the upload can be retried.
+    pub async fn complete_chunked_upload(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        upload_id: &str,
+        raw_file_name: &str,
+        overwrite: bool,
+    ) -> ServiceResult<FileInfoDto> {
+        let storage = self.authorize(user)?;
+        let dir = Self::chunk_dir(&storage, upload_id)?;
+        let parts = dir.clone();
+        let joined = tokio::task::spawn_blocking(move || Self::join_chunks(&parts))
+            .await
+            .map_err(|e| ServiceError::SaveFile(std::io::Error::other(e)))??;
+        let size = joined
+            .as_file()
+            .metadata()
+            .map_err(ServiceError::SaveFile)?
+            .len();
+        let temp_file = TempFile {
+            file: joined,
+            content_type: None,
+            file_name: Some(raw_file_name.to_string()),
+            size: usize::try_from(size).unwrap_or(usize::MAX),
+        };
+
+        let info = self
+            .persist_upload(
+                user,
+                relative,
+                Some(raw_file_name.to_string()),
+                temp_file,
+                overwrite,
+            )
+            .await?;
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            log::warn!("Failed to remove parts of chunked upload {upload_id}: {e}");
+        }
+        Ok(info)
+    }
+
+    /// Concatenate every part of a chunked upload, in order, into a temporary
+    /// file next to them.
+    fn join_chunks(dir: &Path) -> ServiceResult<NamedTempFile> {
+        let total: u32 = fs::read_to_string(dir.join("total"))
+            .ok()
+            .and_then(|raw| raw.trim().parse().ok())
+            .ok_or(ServiceError::NotFound)?;
+        let mut joined = NamedTempFile::new_in(dir).map_err(ServiceError::SaveFile)?;
+        for index in 0..total {
+            let mut part = fs::File::open(dir.join(format!("{index}.part")))
+                .map_err(|_| ServiceError::Validation(format!("Часть {index} ещё не загружена")))?;
+            std::io::copy(&mut part, joined.as_file_mut()).map_err(ServiceError::SaveFile)?;
+        }
+        Ok(joined)
+    }
+
+    /// Folder holding the parts of `upload_id` inside the hub.
+    fn chunk_dir(storage: &HubStorage, upload_id: &str) -> ServiceResult<PathBuf> {
+        let id = Uuid::parse_str(upload_id)
+            .map_err(|_| ServiceError::Validation("Некорректный идентификатор загрузки".into()))?;
+        let dir = storage
+            .hub_root()
+            .join(CHUNKED_UPLOAD_DIR)
+            .join(id.hyphenated().to_string());
+        Self::ensure_within_hub(storage, &dir)?;
+        Ok(dir)
+    }
+
+    /// Remove unfinished chunked uploads of the hub that saw no part for
+    /// longer than the configured TTL.
+    fn remove_stale_uploads(&self, storage: &HubStorage) {
+        let Ok(uploads) = fs::read_dir(storage.hub_root().join(CHUNKED_UPLOAD_DIR)) else {
+            return;
+        };
+        for upload in uploads.filter_map(|e| e.ok()) {
+            let stale = upload
+                .metadata()
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > self.chunked_upload_ttl);
+            if stale {
+                if let Err(e) = fs::remove_dir_all(upload.path()) {
+                    log::warn!(
+                        "Failed to remove stale chunked upload {:?}: {e}",
+                        upload.path()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Collect the metadata of a single file into one object.
+    pub fn file_info(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: &str,
+    ) -> ServiceResult<FileInfoDto> {
+        let path = self.existing_file(user, relative, raw_file_name)?;
+        let file_name =
+            FileName::try_from_str(raw_file_name).map_err(|_| ServiceError::InvalidFileName)?;
+        let metadata = fs::metadata(&path).map_err(ServiceError::ReadFile)?;
+        let unix_secs = |time: std::io::Result<SystemTime>| {
+            time.ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+        };
+
+        Ok(FileInfoDto {
+            name: file_name.as_str().to_string(),
+            size: metadata.len(),
+            is_image: self.is_image(&file_name),
+            extension: file_name.extension(),
+            content_type: self.content_type_for(&file_name).to_string(),
+            modified: unix_secs(metadata.modified()),
+            created: unix_secs(metadata.created()),
+        })
+    }
+
+    /// Details of a single file or folder. Missing entries are reported as
+    /// [`ServiceError::NotFound`], unlike paths leaving the hub.
+    pub fn stat(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_name: &str,
+    ) -> ServiceResult<EntryStatDto> {
+        let storage = self.authorize(user)?;
+        let relative = Self::sanitize_path_param(relative)?;
+        let name = FileName::try_from_str(raw_name).map_err(|_| ServiceError::InvalidFileName)?;
+        if Self::is_quota_sidecar(&name) {
+            return Err(ServiceError::NotFound);
+        }
+        let path = storage.resolve_file(&relative, &name);
+        Self::ensure_within_hub(&storage, &path)?;
+        let metadata = fs::metadata(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ServiceError::NotFound,
+            _ => ServiceError::ReadFile(e),
+        })?;
+
+        let is_directory = metadata.is_dir();
+        let (entry, stats) = self.listed_entry(name, is_directory, EntryStats::from(&metadata));
+        let dimensions = if entry.is_image() {
+            Self::image_dimensions(&path)
+        } else {
+            None
+        };
+        Ok(EntryStatDto {
+            entry: self.entry_dto(entry),
+            size: (!is_directory).then_some(stats.size),
+            modified: stats
+                .modified
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            width: dimensions.map(|(width, _)| width),
+            height: dimensions.map(|(_, height)| height),
+        })
+    }
+
+    /// Probe the container metadata of a video file. Anything that is not a
+    /// recognized video is rejected with [`ServiceError::UnsupportedMedia`].
+    pub fn media_info(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: &str,
+    ) -> ServiceResult<MediaInfoDto> {
+        let path = self.existing_file(user, relative, raw_file_name)?;
+        let file_name =
+            FileName::try_from_str(raw_file_name).map_err(|_| ServiceError::InvalidFileName)?;
+        if self.category_of(&file_name) != FileCategory::Video {
+            return Err(ServiceError::UnsupportedMedia);
+        }
+        media::probe(&path)
+    }
+
+    /// Split a file into fixed-size chunks and hash each one, so clients can
+    /// fetch them through range requests on `/files/download` and retry
+    /// individually.
+    pub fn chunk_manifest(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: &str,
+        chunk_size: Option<u64>,
+    ) -> ServiceResult<ChunkManifestDto> {
+        let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+        if chunk_size == 0 {
+            return Err(ServiceError::Validation(
+                "Размер фрагмента должен быть больше нуля".into(),
+            ));
+        }
+        let path = self.existing_file(user, relative, raw_file_name)?;
+        let mut file = fs::File::open(&path).map_err(ServiceError::ReadFile)?;
+        let size = file.metadata().map_err(ServiceError::ReadFile)?.len();
+        if size.div_ceil(chunk_size) > MAX_MANIFEST_CHUNKS {
+            return Err(ServiceError::Validation(format!(
+                "Слишком много фрагментов, максимум {MAX_MANIFEST_CHUNKS}"
+            )));
+        }
+
+        let mut chunks = Vec::new();
+        let mut offset = 0;
+        let mut buffer = Vec::new();
+        while offset < size {
+            buffer.clear();
+            let length = (&mut file)
+                .take(chunk_size)
+                .read_to_end(&mut buffer)
+                .map_err(ServiceError::ReadFile)? as u64;
+            if length == 0 {
+                break;
+            }
+            chunks.push(ChunkDto {
+                index: chunks.len(),
+                offset,
+                length,
+                sha256: format!("{:x}", Sha256::digest(&buffer)),
+            });
+            offset += length;
+        }
+
+        Ok(ChunkManifestDto {
+            size: offset,
+            chunk_size,
+            chunks,
+        })
+    }
+
+    /// Read up to `bytes` leading bytes of a file, capped by the configured
+    /// maximum. Shorter files are returned whole.
+    pub fn read_head(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: &str,
+        bytes: Option<u64>,
+    ) -> ServiceResult<Vec<u8>> {
+        let limit = bytes
+            .unwrap_or(self.max_head_bytes)
+            .min(self.max_head_bytes);
+        let key = self.existing_file_key(user, relative, raw_file_name)?;
+        let file = self.backend.read(&key).map_err(ServiceError::ReadFile)?;
+
+        let mut head = Vec::new();
+        file.take(limit)
+            .read_to_end(&mut head)
+            .map_err(ServiceError::ReadFile)?;
+        Ok(head)
+    }
+
+    /// Read a whole text file of at most `max_bytes`. Invalid UTF-8 is
+    /// replaced; files containing NUL bytes are treated as binary and refused
+    /// with [`ServiceError::NotText`], larger files with
+    /// [`ServiceError::TooLargeToView`].
+    pub fn read_text(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: &str,
+        max_bytes: u64,
+    ) -> ServiceResult<String> {
+        let path = self.existing_file(user, relative, raw_file_name)?;
+        let size = fs::metadata(&path).map_err(ServiceError::ReadFile)?.len();
+        if size > max_bytes {
+            return Err(ServiceError::TooLargeToView { limit: max_bytes });
+        }
+
+        let key = self.existing_file_key(user, relative, raw_file_name)?;
+        let mut content = Vec::new();
+        self.backend
+            .read(&key)
+            .map_err(ServiceError::ReadFile)?
+            .take(max_bytes.saturating_add(1))
+            .read_to_end(&mut content)
+            .map_err(ServiceError::ReadFile)?;
+        if content.len() as u64 > max_bytes {
+            return Err(ServiceError::TooLargeToView { limit: max_bytes });
+        }
+        if content.contains(&0) {
+            return Err(ServiceError::NotText);
+        }
+        Ok(String::from_utf8_lossy(&content).into_owned())
+    }
+
+    /// Save UTF-8 text content as a file, returning the stored entry.
+    pub fn save_text(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: &str,
+        content: &str,
+        overwrite: bool,
+    ) -> ServiceResult<FileEntryDto> {
+        // Saving edits a file in place; only `Reject` protects the old text.
+        let overwrite = overwrite || self.conflict_policy != ConflictPolicy::Reject;
+        self.store_text(user, relative, raw_file_name, content, overwrite)
+    }
+
+    /// Replace the content of a text file, or create it, from the in-browser
+    /// editor. Content over `max_bytes` is refused.
+    pub fn write_text(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: &str,
+        contents: &str,
+        max_bytes: u64,
+    ) -> ServiceResult<FileEntryDto> {
+        if contents.len() as u64 > max_bytes {
+            return Err(ServiceError::FileTooLarge { limit: max_bytes });
+        }
+        self.store_text(user, relative, raw_file_name, contents, true)
+    }
+
+    /// Store text as a file; folders cannot be written. The text goes to a
+    /// temporary file in the target folder first and is renamed into place,
+    /// so the rename never crosses file systems and readers never see a
+    /// half-written file.
+    fn store_text(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        raw_file_name: &str,
+        contents: &str,
+        overwrite: bool,
+    ) -> ServiceResult<FileEntryDto> {
+        let storage = self.authorize(user)?;
+        let sanitized = Self::sanitize_path_param(relative)?;
+        Self::ensure_writable(&sanitized)?;
+        let file_name = Self::sanitize_file_name(Some(raw_file_name.to_string()))?;
+        let path = storage.resolve_file(&sanitized, &file_name);
+        Self::ensure_within_hub(&storage, &path)?;
+        if path.is_dir() {
+            return Err(ServiceError::Validation(
+                "Нельзя записать текст в папку".into(),
+            ));
+        }
+
+        self.ensure_hub_root(&storage)?;
+        let target_dir = storage.resolve_dir(&sanitized);
+        Self::ensure_within_hub(&storage, &target_dir)?;
+        fs::create_dir_all(&target_dir).map_err(ServiceError::SaveFile)?;
+        let mut file = NamedTempFile::new_in(&target_dir).map_err(ServiceError::SaveFile)?;
+        file.write_all(contents.as_bytes())
+            .map_err(ServiceError::SaveFile)?;
+        let entry = self.store_file(
+            &storage,
+            relative,
+            Some(file_name.as_str().to_string()),
+            file,
+            overwrite,
+        )?;
+        self.audit(
+            user,
+            AuditOperation::Save,
+            &sanitized.as_path().join(entry.name().as_str()),
+        );
+        Ok(self.entry_dto(entry))
+    }
+
+    /// Rename a single file or folder of the current folder. The new name
+    /// must be free; existing entries are never replaced.
+    pub fn rename(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        from: &str,
+        to: &str,
+    ) -> ServiceResult<()> {
+        let storage = self.authorize(user)?;
+        let relative = Self::sanitize_path_param(relative)?;
+        let from = FileName::try_from_str(from).map_err(|_| ServiceError::InvalidFileName)?;
+        let to = FileName::try_from_str(to).map_err(|_| ServiceError::InvalidFileName)?;
+        if self.is_reserved_sidecar(&to) {
+            return Err(ServiceError::InvalidFileName);
+        }
+        let entry_path = |name: &FileName| {
+            RelativePath::try_from_str(name.as_str())
+                .map(|name| relative.join(&name))
+                .map_err(|_| ServiceError::InvalidFileName)
+        };
+        let (from_entry, to_entry) = (entry_path(&from)?, entry_path(&to)?);
+        Self::ensure_writable(&from_entry)?;
+        Self::ensure_writable(&to_entry)?;
+
+        let source = storage.resolve_file(&relative, &from);
+        let target = storage.resolve_file(&relative, &to);
+        Self::ensure_within_hub(&storage, &source)?;
+        Self::ensure_within_hub(&storage, &target)?;
+        let file_type = fs::symlink_metadata(&source)
+            .map_err(|_| ServiceError::NotFound)?
+            .file_type();
+        if !file_type.is_file() && !file_type.is_dir() {
+            return Err(ServiceError::Validation(
+                "Переименовать можно только файл или папку".into(),
+            ));
+        }
+        // Renaming to a different case of the same name is allowed on
+        // case-insensitive file systems, where the target "exists" already
+        // as the source itself.
+        let same_entry = is_same_entry(&source, &target);
+        if from == to || (!same_entry && target.symlink_metadata().is_ok()) {
+            return Err(ServiceError::FileExists);
+        }
+        fs::rename(&source, &target).map_err(ServiceError::MoveEntry)?;
+
+        self.relocate_artifacts(&storage, &from_entry, &to_entry);
+        self.audit_rename(
+            user,
+            storage.hub_id(),
+            from_entry.as_path(),
+            to_entry.as_path(),
+        );
+        self.replay_on_mirror(MirrorOp::Rename {
+            hub_id: storage.hub_id().clone(),
+            from: from_entry,
+            to: to_entry,
+        });
+        Ok(())
+    }
+
+    /// Move a file or folder from `from_path` into the existing folder
+    /// `to_path` of the same hub, keeping its name.
+    pub fn move_entry(
+        &self,
+        user: &AuthenticatedUser,
+        from_path: Option<&str>,
+        to_path: Option<&str>,
+        raw_name: &str,
+    ) -> ServiceResult<()> {
+        let storage = self.authorize(user)?;
+        let from = Self::sanitize_path_param(from_path)?;
+        let to = Self::sanitize_path_param(to_path)?;
+        let name = FileName::try_from_str(raw_name).map_err(|_| ServiceError::InvalidFileName)?;
+        let entry =
+            RelativePath::try_from_str(name.as_str()).map_err(|_| ServiceError::InvalidFileName)?;
+        let (from_entry, to_entry) = (from.join(&entry), to.join(&entry));
+        Self::ensure_writable(&from_entry)?;
+        Self::ensure_writable(&to_entry)?;
+        if from == to {
+            return Err(ServiceError::Validation(
+                "Элемент уже находится в этой папке".into(),
+            ));
+        }
+        if to.as_path().starts_with(from_entry.as_path()) {
+            return Err(ServiceError::Validation(
+                "Нельзя переместить папку внутрь самой себя".into(),
+            ));
+        }
+
+        let source = storage.resolve_file(&from, &name);
+        let target_dir = storage.resolve_dir(&to);
+        let target = storage.resolve_file(&to, &name);
+        for path in [&source, &target_dir, &target] {
+            Self::ensure_within_hub(&storage, path)?;
+        }
+        if !target_dir.is_dir() {
+            return Err(ServiceError::InvalidPath);
+        }
+        fs::symlink_metadata(&source).map_err(|_| ServiceError::NotFound)?;
+        if target.symlink_metadata().is_ok() {
+            return Err(ServiceError::FileExists);
+        }
+        fs::rename(&source, &target).map_err(ServiceError::MoveEntry)?;
+
+        self.relocate_artifacts(&storage, &from_entry, &to_entry);
+        self.replay_on_mirror(MirrorOp::Rename {
+            hub_id: storage.hub_id().clone(),
+            from: from_entry,
+            to: to_entry,
+        });
+        self.prune_empty_parents(&storage, &storage.resolve_dir(&from));
+        Ok(())
+    }
+
+    /// Copy a file or folder from `from_path` into the existing folder
+    /// `to_path` of the same hub, keeping its name. A folder copied onto an
+    /// existing folder is merged into it, unless that would overwrite a file;
+    /// then nothing is copied and the clashing paths are reported through
+    /// [`ServiceError::CopyConflict`]. Symlinks below the source are skipped.
+    pub fn copy_entry(
+        &self,
+        user: &AuthenticatedUser,
+        from_path: Option<&str>,
+        to_path: Option<&str>,
+        raw_name: &str,
+    ) -> ServiceResult<()> {
+        let storage = self.authorize(user)?;
+        let from = Self::sanitize_path_param(from_path)?;
+        let to = Self::sanitize_path_param(to_path)?;
+        let name = FileName::try_from_str(raw_name).map_err(|_| ServiceError::InvalidFileName)?;
+        let entry =
+            RelativePath::try_from_str(name.as_str()).map_err(|_| ServiceError::InvalidFileName)?;
+        let (from_entry, to_entry) = (from.join(&entry), to.join(&entry));
+        Self::ensure_writable(&from_entry)?;
+        Self::ensure_writable(&to_entry)?;
+        if to.as_path().starts_with(from_entry.as_path()) {
+            return Err(ServiceError::Validation(
+                "Нельзя скопировать папку внутрь самой себя".into(),
+            ));
+        }
+
+        let source = storage.resolve_file(&from, &name);
+        let target_dir = storage.resolve_dir(&to);
+        let target = storage.resolve_file(&to, &name);
+        for path in [&source, &target_dir, &target] {
+            Self::ensure_within_hub(&storage, path)?;
+        }
+        if !target_dir.is_dir() {
+            return Err(ServiceError::InvalidPath);
+        }
+        let source_meta = fs::symlink_metadata(&source).map_err(|_| ServiceError::NotFound)?;
+        if source_meta.file_type().is_symlink() {
+            return Err(ServiceError::InvalidPath);
+        }
+        if let Ok(target_meta) = target.symlink_metadata() {
+            if !(source_meta.is_dir() && target_meta.is_dir()) {
+                return Err(ServiceError::FileExists);
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        let mut incoming = 0u64;
+        let copy_depth = self.max_tree_depth.saturating_add(1);
+        let walker = WalkDir::new(&source)
+            .follow_links(false)
+            .max_depth(copy_depth.saturating_add(1));
+        for item in walker.into_iter().filter_map(|e| e.ok()) {
+            // Refuse rather than silently leave out what lies below the cap.
+            if item.depth() > copy_depth {
+                return Err(ServiceError::Validation(
+                    "Папка слишком глубокая для копирования".into(),
+                ));
+            }
+            let file_type = item.file_type();
+            if file_type.is_symlink() {
+                continue;
+            }
+            let Ok(inner) = item.path().strip_prefix(&source) else {
+                continue;
+            };
+            if file_type.is_file() {
+                incoming += item.metadata().map(|meta| meta.len()).unwrap_or(0);
+            }
+            let clashes = target
+                .join(inner)
+                .symlink_metadata()
+                .is_ok_and(|existing| !(file_type.is_dir() && existing.is_dir()));
+            if clashes {
+                conflicts.push(entry.as_path().join(inner).to_string_lossy().to_string());
+            }
+        }
+        if !conflicts.is_empty() {
+            conflicts.sort();
+            return Err(ServiceError::CopyConflict(conflicts));
+        }
+        Self::check_folder_quota(&storage, &to, 0, incoming)?;
+        self.check_hub_quota(&storage, 0, incoming)?;
+
+        copy_tree(&source, &target, copy_depth).map_err(ServiceError::CopyEntry)?;
+
+        self.replay_on_mirror(MirrorOp::Copy {
+            hub_id: storage.hub_id().clone(),
+            relative: to_entry,
+        });
+        Ok(())
+    }
+
+    /// Rename every file in a folder whose name matches `find`, substituting
+    /// `replace`. Each resulting name is validated and existing targets are
+    /// handled according to the conflict policy; per-file outcomes are returned.
+    pub fn rename_by_pattern(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        find: &str,
+        replace: &str,
+    ) -> ServiceResult<Vec<RenameResultDto>> {
+        let pattern = Regex::new(find)
+            .map_err(|e| ServiceError::Validation(format!("Некорректное выражение: {e}")))?;
+        let Some(dir) = self.listing_dir(user, relative)? else {
+            return Ok(vec![]);
+        };
+        let storage = self.authorize(user)?;
+        let relative = Self::sanitize_path_param(relative)?;
+        Self::ensure_writable(&relative)?;
+
+        let mut names: Vec<FileName> = self
+            .read_entries(&dir)?
+            .into_iter()
+            .filter(|(entry, _)| !entry.is_directory())
+            .map(|(entry, _)| entry.into_name())
+            .filter(|name| pattern.is_match(name.as_str()))
+            .collect();
+        names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+        let mut results = Vec::with_capacity(names.len());
+        // Targets created by this call, which must not be replaced by a later
+        // file of the same call even when overwriting is allowed.
+        let mut renamed_to = HashSet::new();
+        for name in names {
+            let to = pattern.replace_all(name.as_str(), replace).into_owned();
+            if to == name.as_str() {
+                continue;
+            }
+            let mut result = RenameResultDto {
+                from: name.as_str().to_string(),
+                to: to.clone(),
+                renamed: false,
+                error: None,
+            };
+
+            let mut target = match FileName::try_from_str(&to) {
+                Ok(target) if !to.contains(['/', '\\']) && !self.is_reserved_sidecar(&target) => {
+                    target
+                }
+                _ => {
+                    result.error = Some("Недопустимое имя файла".into());
+                    results.push(result);
+                    continue;
+                }
+            };
+            let source_path = storage.resolve_file(&relative, &name);
+            let mut target_path = storage.resolve_file(&relative, &target);
+            if Self::ensure_within_hub(&storage, &source_path).is_err()
+                || Self::ensure_within_hub(&storage, &target_path).is_err()
+            {
+                result.error = Some("Недопустимый путь".into());
+                results.push(result);
+                continue;
+            }
+            // A different case of the same name is the source itself on
+            // case-insensitive file systems.
+            let same_entry = is_same_entry(&source_path, &target_path);
+            let existing = target_path.symlink_metadata().ok().filter(|_| !same_entry);
+            let conflict = match existing {
+                Some(meta) if meta.is_dir() => Some("Папка с таким именем уже существует"),
+                Some(_) if renamed_to.contains(target.as_str()) => {
+                    Some("Файл с таким именем уже переименован")
+                }
+                Some(_) if self.conflict_policy == ConflictPolicy::Reject => {
+                    Some("Файл с таким именем уже существует")
+                }
+                Some(_) if self.conflict_policy == ConflictPolicy::Rename => {
+                    match Self::free_file_name(&storage, &relative, target.clone()) {
+                        Ok(free) => {
+                            target_path = storage.resolve_file(&relative, &free);
+                            target = free;
+                            None
+                        }
+                        Err(_) => Some("Не удалось подобрать свободное имя"),
+                    }
+                }
+                _ => None,
+            };
+            if let Some(conflict) = conflict {
+                result.error = Some(conflict.into());
+                results.push(result);
+                continue;
+            }
+
+            result.to = target.as_str().to_string();
+            match fs::rename(&source_path, &target_path) {
+                Ok(()) => {
+                    result.renamed = true;
+                    renamed_to.insert(target.as_str().to_string());
+                    let entry = |name: &FileName| {
+                        RelativePath::try_from_str(name.as_str()).map(|name| relative.join(&name))
+                    };
+                    if let (Ok(from), Ok(to)) = (entry(&name), entry(&target)) {
+                        self.relocate_artifacts(&storage, &from, &to);
+                        self.audit_rename(user, storage.hub_id(), from.as_path(), to.as_path());
+                        self.replay_on_mirror(MirrorOp::Rename {
+                            hub_id: storage.hub_id().clone(),
+                            from,
+                            to,
+                        });
+                    }
+                }
+                Err(err) => {
+                    log::error!("Failed to rename {name:?} to {target:?}: {err}");
+                    result.error = Some("Не удалось переименовать файл".into());
+                }
+            }
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    /// Move the files of `from_path` matching the glob and/or age filter of
+    /// the form into `to_path`. Existing targets follow the conflict policy;
+    /// every matching file gets its own result.
+    pub fn move_matching(
+        &self,
+        user: &AuthenticatedUser,
+        form: &MoveMatchingForm,
+    ) -> ServiceResult<Vec<MoveResultDto>> {
+        if form.glob.is_none() && form.older_than.is_none() {
+            return Err(ServiceError::Validation(
+                "Нужно указать glob или older_than".into(),
+            ));
+        }
+        let matcher = form
+            .glob
+            .as_deref()
+            .map(|glob| Glob::new(glob).map(|glob| glob.compile_matcher()))
+            .transpose()
+            .map_err(|e| ServiceError::Validation(format!("Некорректный шаблон: {e}")))?;
+        let cutoff = form
+            .older_than
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+
+        let storage = self.authorize(user)?;
+        let from = Self::sanitize_path_param(form.from_path.as_deref())?;
+        let to = Self::sanitize_path_param(form.to_path.as_deref())?;
+        Self::ensure_writable(&from)?;
+        Self::ensure_writable(&to)?;
+        if from == to {
+            return Err(ServiceError::Validation(
+                "Папки источника и назначения совпадают".into(),
+            ));
+        }
+        let Some(source_dir) = self.listing_dir(user, form.from_path.as_deref())? else {
+            return Ok(vec![]);
+        };
+
+        let mut names: Vec<FileName> = self
+            .read_entries(&source_dir)?
+            .into_iter()
+            .filter(|(entry, _)| !entry.is_directory())
+            .map(|(entry, _)| entry.into_name())
+            .filter(|name| {
+                matcher
+                    .as_ref()
+                    .is_none_or(|matcher| matcher.is_match(name.as_str()))
+            })
+            .filter(|name| {
+                cutoff.is_none_or(|cutoff| {
+                    fs::metadata(source_dir.join(name.as_str()))
+                        .and_then(|meta| meta.modified())
+                        .is_ok_and(|modified| modified < cutoff)
+                })
+            })
+            .collect();
+        if names.is_empty() {
+            return Ok(vec![]);
+        }
+        names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+        let target_dir = storage.resolve_dir(&to);
+        Self::ensure_within_hub(&storage, &target_dir)?;
+        if target_dir.exists() && !target_dir.is_dir() {
+            return Err(ServiceError::InvalidPath);
+        }
+        fs::create_dir_all(&target_dir).map_err(ServiceError::CreateFolder)?;
+        self.replay_on_mirror(MirrorOp::CreateDir {
+            hub_id: storage.hub_id().clone(),
+            relative: to.clone(),
+        });
+
+        let mut results = Vec::with_capacity(names.len());
+        for name in names {
+            let mut result = MoveResultDto {
+                name: name.as_str().to_string(),
+                to: name.as_str().to_string(),
+                moved: false,
+                error: None,
+            };
+            let source_path = storage.resolve_file(&from, &name);
+            let mut target = name.clone();
+            let mut target_path = storage.resolve_file(&to, &target);
+            if !storage.contains(&source_path) || !storage.contains(&target_path) {
+                result.error = Some("Недопустимый путь".into());
+                results.push(result);
+                continue;
+            }
+            let existing = target_path.symlink_metadata().ok();
+            let conflict = match existing {
+                Some(meta) if meta.is_dir() => Some("В папке назначения есть папка с таким именем"),
+                Some(_) if self.conflict_policy == ConflictPolicy::Reject => {
+                    Some("Файл с таким именем уже существует")
+                }
+                Some(_) if self.conflict_policy == ConflictPolicy::Rename => {
+                    match Self::free_file_name(&storage, &to, name.clone()) {
+                        Ok(free) => {
+                            target_path = storage.resolve_file(&to, &free);
+                            target = free;
+                            None
+                        }
+                        Err(_) => Some("Не удалось подобрать свободное имя"),
+                    }
+                }
+                _ => None,
+            };
+            if let Some(conflict) = conflict {
+                result.error = Some(conflict.into());
+                results.push(result);
+                continue;
+            }
+
+            result.to = target.as_str().to_string();
+            match fs::rename(&source_path, &target_path) {
+                Ok(()) => {
+                    result.moved = true;
+                    if let (Ok(from_name), Ok(to_name)) = (
+                        RelativePath::try_from_str(name.as_str()),
+                        RelativePath::try_from_str(target.as_str()),
+                    ) {
+                        let (from_entry, to_entry) = (from.join(&from_name), to.join(&to_name));
+                        self.relocate_artifacts(&storage, &from_entry, &to_entry);
+                        self.audit_rename(
+                            user,
+                            storage.hub_id(),
+                            from_entry.as_path(),
+                            to_entry.as_path(),
+                        );
+                        self.replay_on_mirror(MirrorOp::Rename {
+                            hub_id: storage.hub_id().clone(),
+                            from: from_entry,
+                            to: to_entry,
+                        });
+                    }
+                }
+                Err(err) => {
+                    log::error!("Failed to move {source_path:?} to {target_path:?}: {err}");
+                    result.error = Some("Не удалось переместить файл".into());
+                }
+            }
+            results.push(result);
+        }
+        if results.iter().any(|result| result.moved) {
+            self.prune_empty_parents(&storage, &source_dir);
+        }
+
+        Ok(results)
+    }
+
+    /// Remove every empty folder of a hub, deepest first, so chains of empty
+    /// folders disappear in one sweep. Reserved directories and the hub root
+    /// are kept. Returns the removed folders relative to the hub root.
+    pub fn prune_empty(
+        &self,
+        user: &AuthenticatedUser,
+        hub_id: HubId,
+    ) -> ServiceResult<Vec<String>> {
+        let storage = self.authorize_admin(user, hub_id)?;
+        let hub_root = storage.hub_root();
+        if !hub_root.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut removed = Vec::new();
+        let walker = WalkDir::new(&hub_root)
+            .follow_links(false)
+            .min_depth(1)
+            .max_depth(self.max_tree_depth.saturating_add(1))
+            .contents_first(true);
+        for entry in walker.into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_dir() {
+                continue;
+            }
+            let Ok(relative) = entry.path().strip_prefix(&hub_root) else {
+                continue;
+            };
+            let Ok(within) = RelativePath::try_new(relative.to_path_buf()) else {
+                continue;
+            };
+            if within.is_reserved() {
+                continue;
+            }
+            if fs::remove_dir(entry.path()).is_ok() {
+                removed.push(relative.to_string_lossy().to_string());
+            }
+        }
+        if !removed.is_empty() {
+            log::info!(
+                "Pruned {} empty folders in hub {}",
+                removed.len(),
+                storage.hub_id()
+            );
+        }
+
+        Ok(removed)
+    }
+
+    /// Rename every entry of a hub whose name does not survive
+    /// [`FileName::sanitize`] unchanged, e.g. files dropped in over WebDAV or
+    /// rsync. With `dry_run` the planned renames are only reported. Reserved
+    /// directories are left alone and existing targets are never replaced.
+    pub fn sanitize_names(
+        &self,
+        user: &AuthenticatedUser,
+        hub_id: HubId,
+        dry_run: bool,
+    ) -> ServiceResult<Vec<RenameResultDto>> {
+        let storage = self.authorize_admin(user, hub_id)?;
+        let hub_root = storage.hub_root();
+        if !hub_root.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut results = Vec::new();
+        let walker = WalkDir::new(&hub_root)
+            .follow_links(false)
+            .min_depth(1)
+            .max_depth(self.max_tree_depth.saturating_add(1))
+            .contents_first(true);
+        for entry in walker.into_iter().filter_map(|e| e.ok()) {
+            let Ok(relative) = entry.path().strip_prefix(&hub_root) else {
+                continue;
+            };
+            let Ok(within) = RelativePath::try_new(relative.to_path_buf()) else {
+                continue;
+            };
+            if within.is_reserved() {
+                continue;
+            }
+            let raw = entry.file_name().to_string_lossy().to_string();
+            let sanitized = FileName::sanitize(&raw);
+            if entry.file_name().to_str() == Some(sanitized.as_str()) {
+                continue;
+            }
+
+            let target = entry.path().with_file_name(sanitized.as_str());
+            let mut result = RenameResultDto {
+                from: relative.to_string_lossy().to_string(),
+                to: target
+                    .strip_prefix(&hub_root)
+                    .unwrap_or(&target)
+                    .to_string_lossy()
+                    .to_string(),
+                renamed: false,
+                error: None,
+            };
+            if target.symlink_metadata().is_ok() {
+                result.error = Some("Файл с таким именем уже существует".into());
+            } else if !dry_run {
+                match fs::rename(entry.path(), &target) {
+                    Ok(()) => {
+                        log::info!(
+                            "Sanitized name in hub {}: {} -> {}",
+                            storage.hub_id(),
+                            result.from,
+                            result.to
+                        );
+                        result.renamed = true;
+                        if let (Ok(from), Ok(to)) = (
+                            RelativePath::try_from_str(&result.from),
+                            RelativePath::try_from_str(&result.to),
+                        ) {
+                            self.relocate_artifacts(&storage, &from, &to);
+                        }
+                        self.audit_rename(
+                            user,
+                            storage.hub_id(),
+                            Path::new(&result.from),
+                            Path::new(&result.to),
+                        );
+                    }
+                    Err(err) => result.error = Some(err.to_string()),
+                }
+            }
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    /// Pack a folder of the hub into a zip archive, keeping paths relative to
+    /// that folder. Hidden entries are left out and empty folders still
+    /// produce a valid (empty) archive.
+    pub fn zip_folder(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+    ) -> ServiceResult<Vec<u8>> {
+        let root = self
+            .listing_dir(user, relative)?
+            .ok_or(ServiceError::NotFound)?;
+        if !root.is_dir() {
+            return Err(ServiceError::NotFound);
+        }
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        let walker = WalkDir::new(&root)
+            .follow_links(false)
+            .min_depth(1)
+            .max_depth(self.max_tree_depth.saturating_add(1))
+            .into_iter()
+            .filter_entry(|entry| {
+                // Dotfiles cover the reserved directories and the quota
+                // sidecar as well as the member's own hidden files.
+                FileName::from_disk(entry.file_name()).is_ok_and(|name| {
+                    !name.is_hidden()
+                        && !self.is_reserved_sidecar(&name)
+                        && !self.ignore_patterns.is_match(name.as_str())
+                })
+            });
+        for entry in walker {
+            let entry = entry.map_err(|e| ServiceError::ReadFile(e.into()))?;
+            let Ok(path) = entry.path().strip_prefix(&root) else {
+                continue;
+            };
+            let name = path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if entry.file_type().is_dir() {
+                writer
+                    .add_directory(name, options)
+                    .map_err(ServiceError::BuildArchive)?;
+            } else if entry.file_type().is_file() {
+                writer
+                    .start_file(name, options)
+                    .map_err(ServiceError::BuildArchive)?;
+                let mut file = fs::File::open(entry.path()).map_err(ServiceError::ReadFile)?;
+                std::io::copy(&mut file, &mut writer).map_err(ServiceError::ReadFile)?;
+            }
+        }
+
+        let cursor = writer.finish().map_err(ServiceError::BuildArchive)?;
+        Ok(cursor.into_inner())
+    }
+
+    /// Report what an uploaded zip archive contains without extracting it:
+    /// entry sizes, the overall compression ratio and which entries would
+    /// escape the hub or break the naming rules. Nothing is rejected.
+    pub fn inspect_archive(
+        &self,
+        user: &AuthenticatedUser,
+        temp_file: TempFile,
+    ) -> ServiceResult<ArchiveReportDto> {
+        self.authorize(user)?;
+        let mut archive = zip::ZipArchive::new(temp_file.file.as_file())
+            .map_err(|e| ServiceError::Validation(format!("Некорректный архив: {e}")))?;
+
+        let mut entries = Vec::with_capacity(archive.len());
+        for index in 0..archive.len() {
+            let entry = archive
+                .by_index_raw(index)
+                .map_err(|e| ServiceError::Validation(format!("Некорректный архив: {e}")))?;
+            let problem = match entry.enclosed_name() {
+                None => Some("Путь выходит за пределы хаба".to_string()),
+                Some(path) => Self::archive_path_problem(&path),
+            };
+            entries.push(ArchiveEntryDto {
+                name: entry.name().to_string(),
+                size: entry.size(),
+                compressed_size: entry.compressed_size(),
+                is_directory: entry.is_dir(),
+                problem,
+            });
+        }
+
+        let total_size = entries.iter().map(|e| e.size).sum::<u64>();
+        let total_compressed_size = entries.iter().map(|e| e.compressed_size).sum::<u64>();
+        let compression_ratio = if total_compressed_size == 0 {
+            0.0
+        } else {
+            total_size as f64 / total_compressed_size as f64
+        };
+
+        Ok(ArchiveReportDto {
+            entries,
+            total_size,
+            total_compressed_size,
+            compression_ratio,
+            possible_zip_bomb: compression_ratio > ZIP_BOMB_RATIO,
+        })
+    }
+
+    /// Why an archive entry at `path` could not be extracted as is.
+    fn archive_path_problem(path: &Path) -> Option<String> {
+        let Ok(relative) = RelativePath::try_new(path.to_path_buf()) else {
+            return Some("Путь выходит за пределы хаба".into());
+        };
+        if relative.is_reserved() {
+            return Some("Служебная папка".into());
+        }
+        let unsafe_name = path.components().any(|c| {
+            let name = c.as_os_str().to_string_lossy();
+            FileName::sanitize(&name).as_str() != name
+        });
+        unsafe_name.then(|| "Недопустимое имя файла".into())
+    }
+
+    /// Exchange the contents of two files in the same folder. Both names stay
+    /// present throughout: each file is hard-linked to a temporary name first
+    /// and the links are then renamed over the other name.
+    pub fn swap_entries(
+        &self,
+        user: &AuthenticatedUser,
+        relative: Option<&str>,
+        name_a: &str,
+        name_b: &str,
+    ) -> ServiceResult<()> {
+        let storage = self.authorize(user)?;
+        let relative = Self::sanitize_path_param(relative)?;
+        Self::ensure_writable(&relative)?;
+        let name_a = FileName::try_from_str(name_a).map_err(|_| ServiceError::InvalidFileName)?;
+        let name_b = FileName::try_from_str(name_b).map_err(|_| ServiceError::InvalidFileName)?;
+        if name_a == name_b {
+            return Err(ServiceError::Validation(
+                "Нужно указать два разных файла".into(),
+            ));
+        }
+        self.ensure_hub_root(&storage)?;
+
+        let path_a = storage.resolve_file(&relative, &name_a);
+        let path_b = storage.resolve_file(&relative, &name_b);
+        for path in [&path_a, &path_b] {
+            Self::ensure_within_hub(&storage, path)?;
+            match fs::symlink_metadata(path) {
+                Ok(meta) if meta.is_file() => {}
+                Ok(_) => {
+                    return Err(ServiceError::Validation(
+                        "Поменять местами можно только файлы".into(),
+                    ));
+                }
+                Err(_) => return Err(ServiceError::NotFound),
+            }
+        }
+
+        let dir = storage.resolve_dir(&relative);
+        let temp_a = dir.join(format!(".swap-{}", Uuid::new_v4()));
+        let temp_b = dir.join(format!(".swap-{}", Uuid::new_v4()));
+        fs::hard_link(&path_a, &temp_a).map_err(ServiceError::MoveEntry)?;
+        if let Err(err) = fs::hard_link(&path_b, &temp_b) {
+            let _ = fs::remove_file(&temp_a);
+            return Err(ServiceError::MoveEntry(err));
+        }
+        if let Err(err) = fs::rename(&temp_b, &path_a) {
+            let _ = fs::remove_file(&temp_b);
+            let _ = fs::remove_file(&temp_a);
+            return Err(ServiceError::MoveEntry(err));
+        }
+        if let Err(err) = fs::rename(&temp_a, &path_b) {
+            // `path_a` already holds the content of `b`; undo in reverse
+            // order by putting the original `a` back from its link.
+            if let Err(restore) = fs::rename(&temp_a, &path_a) {
+                log::error!("Failed to restore {path_a:?} from {temp_a:?}: {restore}");
+            }
+            return Err(ServiceError::MoveEntry(err));
+        }
+
+        for name in [name_a, name_b] {
+            self.replay_on_mirror(MirrorOp::WriteFile {
+                hub_id: storage.hub_id().clone(),
+                relative: relative.clone(),
+                name,
+            });
+        }
+        Ok(())
+    }
+
+    /// Make a staging folder live: the current `target` is renamed to
+    /// `{target}.backup-{unix_secs}` and `staging` is renamed to `target`.
+    /// Each step is a single rename, so readers see either the old or the new
+    /// folder; if the second step fails the backup is put back.
+    pub fn publish(
+        &self,
+        user: &AuthenticatedUser,
+        form: &PublishForm,
+    ) -> ServiceResult<PublishResultDto> {
+        let storage = self.authorize(user)?;
+        let relative = Self::sanitize_path_param(form.path.as_deref())?;
+        let staging =
+            FileName::try_from_str(&form.staging).map_err(|_| ServiceError::InvalidFileName)?;
+        let target =
+            FileName::try_from_str(&form.target).map_err(|_| ServiceError::InvalidFileName)?;
+        if staging == target {
+            return Err(ServiceError::Validation(
+                "Папки публикации должны различаться".into(),
+            ));
+        }
+        let entry_path = |name: &str| {
+            RelativePath::try_from_str(name)
+                .map(|name| relative.join(&name))
+                .map_err(|_| ServiceError::InvalidFileName)
+        };
+        let staging_entry = entry_path(staging.as_str())?;
+        let target_entry = entry_path(target.as_str())?;
+        for entry in [&staging_entry, &target_entry] {
+            Self::ensure_writable(entry)?;
+        }
+
+        let staging_path = storage.resolve_file(&relative, &staging);
+        let target_path = storage.resolve_file(&relative, &target);
+        for path in [&staging_path, &target_path] {
+            Self::ensure_within_hub(&storage, path)?;
+        }
+        match fs::symlink_metadata(&staging_path) {
+            Ok(meta) if meta.is_dir() => {}
+            Ok(_) => return Err(ServiceError::InvalidPath),
+            Err(_) => return Err(ServiceError::NotFound),
+        }
+
+        let backup = match fs::symlink_metadata(&target_path) {
+            Ok(meta) if meta.is_dir() => {
+                let secs = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+                let name = format!("{}.backup-{secs}", target.as_str());
+                let backup_path = target_path.with_file_name(&name);
+                if backup_path.symlink_metadata().is_ok() {
+                    return Err(ServiceError::FileExists);
+                }
+                fs::rename(&target_path, &backup_path).map_err(ServiceError::MoveEntry)?;
+                Some((name, backup_path))
+            }
+            Ok(_) => return Err(ServiceError::InvalidPath),
+            Err(_) => None,
+        };
+
+        if let Err(err) = fs::rename(&staging_path, &target_path) {
+            if let Some((_, backup_path)) = &backup {
+                let _ = fs::rename(backup_path, &target_path);
+            }
+            return Err(ServiceError::MoveEntry(err));
+        }
+        log::info!(
+            "Published {} over {} in hub {}",
+            staging,
+            target,
+            storage.hub_id()
+        );
+
+        let backup_entry = match &backup {
+            Some((name, _)) => Some(entry_path(name.as_str())?),
+            None => None,
+        };
+        if let Some(backup_entry) = &backup_entry {
+            self.relocate_artifacts(&storage, &target_entry, backup_entry);
+            self.audit_rename(
+                user,
+                storage.hub_id(),
+                target_entry.as_path(),
+                backup_entry.as_path(),
+            );
+        }
+        self.relocate_artifacts(&storage, &staging_entry, &target_entry);
+        self.audit_rename(
+            user,
+            storage.hub_id(),
+            staging_entry.as_path(),
+            target_entry.as_path(),
+        );
+        self.replay_on_mirror(MirrorOp::Publish {
+            hub_id: storage.hub_id().clone(),
+            staging: staging_entry,
+            target: target_entry,
+            backup: backup_entry,
+        });
+
+        Ok(PublishResultDto {
+            backup: backup.map(|(name, _)| name),
+        })
+    }
+
+    /// Move a prepared temporary file into the hub storage. Shared by every
+    /// write path so validation and storage rules stay in one place.
+    fn store_file(
+        &self,
+        storage: &HubStorage,
+        relative: Option<&str>,
+        raw_file_name: Option<String>,
+        file: NamedTempFile,
+        overwrite: bool,
+    ) -> ServiceResult<StorageEntry> {
+        let relative = Self::sanitize_path_param(relative)?;
+        Self::ensure_writable(&relative)?;
+        let mut file_name = Self::sanitize_file_name(raw_file_name)?;
+        if self.is_reserved_sidecar(&file_name) {
+            return Err(ServiceError::InvalidFileName);
+        }
+        if self.is_spoofed_extension(&file_name) {
+            return Err(ServiceError::DisallowedExtension);
+        }
+        if !self.is_allowed_extension(&file_name) {
+            return Err(ServiceError::ForbiddenExtension);
+        }
+        self.ensure_hub_root(storage)?;
+
+        let target_dir = storage.resolve_dir(&relative);
+        Self::ensure_within_hub(storage, &target_dir)?;
+        fs::create_dir_all(&target_dir).map_err(ServiceError::SaveFile)?;
+
+        let mut filepath = storage.resolve_file(&relative, &file_name);
+        if !overwrite && self.conflict_policy == ConflictPolicy::Rename {
+            file_name = Self::free_file_name(storage, &relative, file_name)?;
+            filepath = storage.resolve_file(&relative, &file_name);
+        }
+        Self::ensure_within_hub(storage, &filepath)?;
+        let limit = self.upload_limit_for(storage.hub_id());
+        let size = file
+            .as_file()
+            .metadata()
+            .map_err(ServiceError::SaveFile)?
+            .len();
+        if size > limit {
+            return Err(ServiceError::FileTooLarge { limit });
+        }
+        let replaced = fs::metadata(&filepath).map(|meta| meta.len()).unwrap_or(0);
+        Self::check_folder_quota(storage, &relative, replaced, size)?;
+        self.check_hub_quota(storage, replaced, size)?;
+        if self.strict_mime {
+            Self::check_sniffed_type(&file_name, file.path())?;
+        }
+        if self.is_image(&file_name) {
+            self.check_aspect_ratio(file.path())?;
+        }
+        let replace = overwrite || self.conflict_policy == ConflictPolicy::Overwrite;
+        self.backend
+            .persist(file, &storage.file_key(&relative, &file_name), replace)
+            .map_err(|err| {
+                if err.kind() == std::io::ErrorKind::AlreadyExists {
+                    ServiceError::FileExists
+                } else {
+                    ServiceError::SaveFile(err)
+                }
+            })?;
+
+        self.replay_on_mirror(MirrorOp::WriteFile {
+            hub_id: storage.hub_id().clone(),
+            relative,
+            name: file_name.clone(),
+        });
+
+        let kind = EntryKind::File {
+            is_image: self.is_image(&file_name),
+        };
+        Ok(StorageEntry::new(file_name, kind))
+    }
+
+    /// Bring the `.thumbnails` cache of a hub back in line with its files.
+    /// Thumbnails whose source is gone or no longer an image are deleted,
+    /// those older than their source are regenerated and current ones are
+    /// left alone. A stale thumbnail that cannot be regenerated is deleted.
+    pub fn repair_thumbnails(
+        &self,
+        user: &AuthenticatedUser,
+        hub_id: HubId,
+    ) -> ServiceResult<ThumbnailRepairDto> {
+        let storage = self.authorize_admin(user, hub_id)?;
+        let hub_root = storage.hub_root();
+        let thumbnails = hub_root.join(THUMBNAIL_DIR);
+        let mut report = ThumbnailRepairDto::default();
+        if !thumbnails.is_dir() {
+            return Ok(report);
+        }
+        Self::ensure_within_hub(&storage, &thumbnails)?;
+
+        let walker = WalkDir::new(&thumbnails)
+            .follow_links(false)
+            .min_depth(1)
+            .max_depth(self.max_tree_depth.saturating_add(1))
+            .contents_first(true);
+        for entry in walker.into_iter().filter_map(|e| e.ok()) {
+            if entry.file_type().is_dir() {
+                // Folders left empty by deleted thumbnails; others stay.
+                let _ = fs::remove_dir(entry.path());
+                continue;
+            }
+            let Ok(relative) = entry.path().strip_prefix(&thumbnails) else {
+                continue;
+            };
+            let source = hub_root.join(relative);
+            let is_source = entry.file_type().is_file()
+                && source.symlink_metadata().is_ok_and(|meta| meta.is_file())
+                && FileName::from_disk(entry.file_name()).is_ok_and(|name| self.is_image(&name));
+            if !is_source {
+                match fs::remove_file(entry.path()) {
+                    Ok(()) => report.deleted += 1,
+                    Err(err) => log::warn!("Failed to delete thumbnail {:?}: {err}", entry.path()),
+                }
+                continue;
+            }
+
+            let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+            let (Some(source_modified), Some(thumbnail_modified)) =
+                (modified(&source), modified(entry.path()))
+            else {
+                continue;
+            };
+            if source_modified <= thumbnail_modified {
+                continue;
+            }
+            let regenerated = image::ImageReader::open(&source)
+                .and_then(|reader| reader.with_guessed_format())
+                .map_err(image::ImageError::IoError)
+                .and_then(|reader| reader.decode())
+                .and_then(|image| {
+                    image
+                        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+                        .save(entry.path())
+                });
+            match regenerated {
+                Ok(()) => report.regenerated += 1,
+                Err(err) => {
+                    log::warn!("Failed to regenerate thumbnail of {source:?}: {err}");
+                    if fs::remove_file(entry.path()).is_ok() {
+                        report.deleted += 1;
+                    }
+                }
+            }
+        }
+        log::info!(
+            "Repaired thumbnails of hub {}: {} deleted, {} regenerated",
+            storage.hub_id(),
+            report.deleted,
+            report.regenerated
+        );
+
+        Ok(report)
+    }
+
+    /// First of `name`, `stem (1).ext`, `stem (2).ext`, ... not taken in the
+    /// folder yet.
+    fn free_file_name(
+        storage: &HubStorage,
+        relative: &RelativePath,
+        name: FileName,
+    ) -> ServiceResult<FileName> {
+        let taken = |name: &FileName| {
+            storage
+                .resolve_file(relative, name)
+                .symlink_metadata()
+                .is_ok()
+        };
+        if !taken(&name) {
+            return Ok(name);
+        }
+
+        let path = Path::new(name.as_str());
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        (1..=MAX_NAME_SUFFIX)
+            .filter_map(|n| FileName::try_from_str(&format!("{stem} ({n}){extension}")).ok())
+            .find(|candidate| !taken(candidate))
+            .ok_or(ServiceError::FileExists)
+    }
+
+    /// Enforce the [`QUOTA_SIDECAR`] of the nearest ancestor folder that
+    /// declares one. The `replaced` bytes of an overwritten file no longer
+    /// count towards the total.
+    fn check_folder_quota(
+        storage: &HubStorage,
+        relative: &RelativePath,
+        replaced: u64,
+        incoming: u64,
+    ) -> ServiceResult<()> {
+        let quota = relative.as_path().ancestors().find_map(|ancestor| {
+            let dir = storage.hub_root().join(ancestor);
+            fs::read_to_string(dir.join(QUOTA_SIDECAR))
+                .ok()
+                .and_then(|raw| raw.trim().parse::<u64>().ok())
+                .map(|limit| (ancestor.to_path_buf(), dir, limit))
+        });
+        let Some((folder, dir, limit)) = quota else {
+            return Ok(());
+        };
+
+        let used = Self::used_bytes(storage, &dir);
+        if used.saturating_sub(replaced).saturating_add(incoming) > limit {
+            return Err(ServiceError::QuotaExceeded {
+                folder: format!("/{}", folder.display()),
+                limit,
+            });
+        }
+        Ok(())
+    }
+
+    /// Enforce `quota_bytes` on the whole hub, reported as a quota of `/`.
+    fn check_hub_quota(
+        &self,
+        storage: &HubStorage,
+        replaced: u64,
+        incoming: u64,
+    ) -> ServiceResult<()> {
+        let Some(limit) = self.quota_bytes else {
+            return Ok(());
+        };
+        let used = Self::used_bytes(storage, &storage.hub_root());
+        if used.saturating_sub(replaced).saturating_add(incoming) > limit {
+            return Err(ServiceError::QuotaExceeded {
+                folder: "/".into(),
+                limit,
+            });
+        }
+        Ok(())
+    }
+
+    /// Bytes of the regular files below `dir`, not counting quota sidecars or
+    /// the parts of unfinished chunked uploads.
+    fn used_bytes(storage: &HubStorage, dir: &Path) -> u64 {
+        let pending = storage.hub_root().join(CHUNKED_UPLOAD_DIR);
+        WalkDir::new(dir)
+            .into_iter()
+            .filter_entry(|entry| entry.path() != pending)
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file() && entry.file_name() != QUOTA_SIDECAR)
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|meta| meta.len())
+            .sum()
+    }
+
+    /// Compare the type sniffed from the file's magic bytes with the one its
+    /// extension claims. Content nothing is detected for (e.g. plain text)
+    /// only passes under extensions whose types cannot be detected either.
+    fn check_sniffed_type(name: &FileName, path: &Path) -> ServiceResult<()> {
+        let Some(extension) = name.extension() else {
+            return Ok(());
+        };
+        let detected = infer::get_from_path(path).map_err(ServiceError::ReadFile)?;
+        let matches = match detected {
+            Some(kind) => {
+                kind.extension() == extension
+                    || mime_guess::from_ext(&extension)
+                        .iter()
+                        .any(|claimed| claimed.essence_str() == kind.mime_type())
+            }
+            None => !infer::is_supported(&extension),
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(ServiceError::ContentTypeMismatch)
+        }
+    }
+
+    /// Width and height of an image, read from its header only.
+    fn image_dimensions(path: &Path) -> Option<(u32, u32)> {
+        image::ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok())
+    }
+
+    /// Enforce `max_aspect_ratio` on an image about to be stored. Only the
+    /// header is read; formats without known dimensions (e.g. SVG) pass.
+    fn check_aspect_ratio(&self, path: &Path) -> ServiceResult<()> {
+        let Some(max_ratio) = self.max_aspect_ratio else {
+            return Ok(());
+        };
+        let Some((width, height)) = Self::image_dimensions(path) else {
+            return Ok(());
+        };
+
+        let (long, short) = (width.max(height), width.min(height).max(1));
+        if f64::from(long) / f64::from(short) > max_ratio {
+            return Err(ServiceError::AspectRatioExceeded);
+        }
+        Ok(())
+    }
+}
+
+/// `/files/download` URL showing the file `name` of the sub-folder `folder`
+/// of `relative` inline, as used for folder previews.
+fn preview_url(relative: &RelativePath, folder: &str, name: &str) -> String {
+    let path = relative.as_path().join(folder);
+    format!(
+        "/files/download?path={}&name={}&inline=true",
+        utf8_percent_encode(&path.to_string_lossy(), QUERY_VALUE),
+        utf8_percent_encode(name, QUERY_VALUE),
+    )
+}
+
+/// Whether `bytes` look like text: valid UTF-8 without NUL bytes. A code
+/// point cut off at the end (as happens when reading a prefix) is allowed.
+pub fn looks_textual(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::*;
+    use pushkind_common::domain::auth::AuthenticatedUser;
+    use tempfile::tempdir;
+
+    fn build_service(root: PathBuf) -> FileService {
+        FileService::new(UploadRoot::from(root))
+    }
+
+    #[actix_web::test]
+    async fn list_entries_sorted_and_typed() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("42");
+        fs::create_dir_all(&hub_root).unwrap();
+        fs::create_dir(hub_root.join("b_folder")).unwrap();
+        fs::create_dir(hub_root.join("a_folder")).unwrap();
+        fs::write(hub_root.join("a_file.txt"), b"hello").unwrap();
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        fs::write(hub_root.join("c_image.png"), b"fakepng").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 42,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let entries = service
+            .list_entries(&user, None, &EntryFilter::All, ListingSort::Default, false)
+            .await
+            .unwrap();
+
+        let names: Vec<String> = entries.iter().map(|e| e.name.clone()).collect();
+        assert!(entries[0].is_directory);
+        assert!(entries[1].is_directory);
+        assert!(!entries[2].is_directory);
+        assert!(entries.iter().any(|entry| entry.is_image));
+
+        let created_a = fs::metadata(hub_root.join("a_file.txt"))
+            .ok()
+            .and_then(|m| m.created().ok());
+        let created_c = fs::metadata(hub_root.join("c_image.png"))
+            .ok()
+            .and_then(|m| m.created().ok());
+        // When creation timestamps are unavailable or identical, we fall back to name ordering.
+        let expected_files = match (created_a, created_c) {
+            (Some(a_time), Some(c_time)) if c_time > a_time => {
+                vec!["c_image.png".to_string(), "a_file.txt".to_string()]
+            }
+            (Some(a_time), Some(c_time)) if a_time > c_time => {
+                vec!["a_file.txt".to_string(), "c_image.png".to_string()]
+            }
+            _ => vec!["a_file.txt".to_string(), "c_image.png".to_string()],
+        };
+
+        assert_eq!(
+            names,
+            [
+                &["a_folder".to_string(), "b_folder".to_string()][..],
+                &expected_files
+            ]
+            .concat()
+        );
+    }
+
+    #[actix_web::test]
+    async fn folder_previews_pick_first_image() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("12");
+        fs::create_dir_all(hub_root.join("gallery/Summer 2024")).unwrap();
+        fs::create_dir_all(hub_root.join("gallery/docs")).unwrap();
+        fs::write(hub_root.join("gallery/Summer 2024/b.jpg"), b"img").unwrap();
+        fs::write(hub_root.join("gallery/Summer 2024/a.png"), b"img").unwrap();
+        fs::write(hub_root.join("gallery/docs/notes.txt"), b"text").unwrap();
+
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 12,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let service = build_service(dir.path().to_path_buf()).with_folder_previews(true);
+        let entries = service
+            .list_entries(
+                &user,
+                Some("gallery"),
+                &EntryFilter::All,
+                ListingSort::Default,
+                false,
+            )
+            .await
+            .unwrap();
+        let preview = |name: &str| {
+            entries
+                .iter()
+                .find(|e| e.name == name)
+                .unwrap()
+                .folder_preview_image
+                .clone()
+        };
+        assert_eq!(
+            preview("Summer 2024").as_deref(),
+            Some("/files/download?path=gallery%2FSummer%202024&name=a.png&inline=true")
+        );
+        assert_eq!(preview("docs"), None);
+
+        let disabled = build_service(dir.path().to_path_buf());
+        let entries = disabled
+            .list_entries(
+                &user,
+                Some("gallery"),
+                &EntryFilter::All,
+                ListingSort::Default,
+                false,
+            )
+            .await
+            .unwrap();
+        assert!(entries.iter().all(|e| e.folder_preview_image.is_none()));
+    }
+
+    #[actix_web::test]
+    async fn list_entries_skips_ignored_patterns() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("12");
+        fs::create_dir_all(&hub_root).unwrap();
+        fs::write(hub_root.join(".DS_Store"), b"junk").unwrap();
+        fs::write(hub_root.join("draft.tmp"), b"junk").unwrap();
+        fs::write(hub_root.join("report.pdf"), b"pdf").unwrap();
+
+        let service = build_service(dir.path().to_path_buf())
+            .with_ignore_patterns(&[".DS_Store".to_string(), "*.tmp".to_string()])
+            .unwrap();
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 12,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let entries = service
+            .list_entries(&user, None, &EntryFilter::All, ListingSort::Default, false)
+            .await
+            .unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["report.pdf"]);
+    }
+
+    #[actix_web::test]
+    async fn watch_reports_changes_and_timeouts() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("6");
+        fs::create_dir_all(hub_root.join("inbox")).unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 6,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let idle = service
+            .watch(&user, Some("inbox"))
+            .unwrap()
+            .wait(Duration::from_millis(300))
+            .unwrap();
+        assert!(!idle);
+
+        let inbox = hub_root.join("inbox");
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            fs::write(inbox.join("new.txt"), b"hi").unwrap();
+        });
+        let changed = service
+            .watch(&user, Some("inbox"))
+            .unwrap()
+            .wait(Duration::from_secs(10))
+            .unwrap();
+        writer.join().unwrap();
+        assert!(changed);
+
+        let entries = service
+            .list_entries(
+                &user,
+                Some("inbox"),
+                &EntryFilter::All,
+                ListingSort::Default,
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(entries[0].name, "new.txt");
+    }
+
+    #[test]
+    fn extension_counts_group_dirs_and_extensionless() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("34");
+        fs::create_dir_all(hub_root.join("alpha")).unwrap();
+        fs::create_dir_all(hub_root.join("beta.d")).unwrap();
+        for name in ["a.pdf", "b.PDF", "c.png", "Makefile", "LICENSE"] {
+            fs::write(hub_root.join(name), name).unwrap();
+        }
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 34,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let counts = service.extension_counts(&user, None).unwrap();
+        let expected: BTreeMap<String, usize> = [("dir", 2), ("pdf", 2), ("png", 1), ("", 2)]
+            .into_iter()
+            .map(|(key, count)| (key.to_string(), count))
+            .collect();
+        assert_eq!(counts, expected);
+        assert!(
+            service
+                .extension_counts(&user, Some("missing"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[actix_web::test]
+    async fn count_entries_matches_listing() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("24");
+        fs::create_dir_all(hub_root.join("alpha")).unwrap();
+        fs::create_dir_all(hub_root.join("beta")).unwrap();
+        fs::write(hub_root.join("cat.png"), b"png").unwrap();
+        fs::write(hub_root.join("notes.txt"), b"txt").unwrap();
+        fs::write(hub_root.join("report.pdf"), b"pdf").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 24,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let listing = service
+            .list_entries(&user, None, &EntryFilter::All, ListingSort::Default, false)
+            .await
+            .unwrap();
+        let counts = service
+            .count_entries(&user, None, &EntryFilter::All)
+            .unwrap();
+        assert_eq!(
+            counts,
+            EntryCountsDto {
+                files: listing.iter().filter(|e| !e.is_directory).count(),
+                directories: listing.iter().filter(|e| e.is_directory).count(),
+                images: listing.iter().filter(|e| e.is_image).count(),
+            }
+        );
+        assert_eq!((counts.files, counts.directories, counts.images), (3, 2, 1));
+
+        let images = service
+            .count_entries(&user, None, &EntryFilter::Images)
+            .unwrap();
+        assert_eq!((images.files, images.directories, images.images), (1, 0, 1));
+    }
+
+    #[test]
+    fn hub_ids_skip_non_numeric_dirs() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("12")).unwrap();
+        fs::create_dir_all(dir.path().join("3")).unwrap();
+        fs::create_dir_all(dir.path().join("lost+found")).unwrap();
+        fs::write(dir.path().join("99"), b"not a dir").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let hubs = service.hub_ids().unwrap();
+        assert_eq!(hubs, vec![HubId::new(3), HubId::new(12)]);
+    }
+
+    #[actix_web::test]
+    async fn list_entries_missing_dir_returns_empty() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 99,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let entries = service
+            .list_entries(
+                &user,
+                Some("nope"),
+                &EntryFilter::All,
+                ListingSort::Default,
+                false,
+            )
+            .await
+            .unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn list_entries_follow_out_of_band_changes() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("5");
+        fs::create_dir_all(&hub_root).unwrap();
+        fs::write(hub_root.join("a.pdf"), b"%PDF").unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 5,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let (service, user) = (&service, &user);
+        let names = move || async move {
+            service
+                .list_entries(user, None, &EntryFilter::All, ListingSort::Default, false)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names().await, ["a.pdf"]);
+
+        // Another instance sharing the upload root changes the folder.
+        fs::write(hub_root.join("b.pdf"), b"%PDF").unwrap();
+        fs::remove_file(hub_root.join("a.pdf")).unwrap();
+
+        assert_eq!(names().await, ["b.pdf"]);
+    }
+
+    #[actix_web::test]
+    async fn list_entries_rejects_parent_paths() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 1,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let err = service
+            .list_entries(
+                &user,
+                Some("../etc"),
+                &EntryFilter::All,
+                ListingSort::Default,
+                false,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::InvalidPath));
+    }
+
+    #[actix_web::test]
+    async fn create_folder_builds_nested_structure() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 5,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let form = CreateFolderForm {
+            name: "beta".to_string(),
+            exist_ok: true,
+        };
+
+        service
+            .create_folder(&user, Some("alpha"), &form)
+            .await
+            .unwrap();
+
+        let storage = service.storage_for_hub(HubId::new(5));
+        assert!(
+            storage
+                .resolve_dir(
+                    &RelativePath::try_new(PathBuf::from("alpha"))
+                        .unwrap()
+                        .join(&RelativePath::try_new(PathBuf::from("beta")).unwrap())
+                )
+                .exists()
+        );
+    }
+
+    #[actix_web::test]
+    async fn hub_roots_move_a_hub_off_the_upload_root() {
+        let dir = tempdir().unwrap();
+        let upload = dir.path().join("upload");
+        let mount = dir.path().join("mount/hub5");
+        fs::create_dir_all(&mount).unwrap();
+        fs::create_dir_all(upload.join("3")).unwrap();
+        fs::create_dir_all(upload.join("5")).unwrap();
+        let service =
+            build_service(upload.clone()).with_hub_roots(HashMap::from([(5, mount.clone())]));
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 5,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let form = CreateFolderForm {
+            name: "beta".to_string(),
+            exist_ok: true,
+        };
+
+        service.create_folder(&user, None, &form).await.unwrap();
+        assert!(mount.join("beta").is_dir());
+        assert!(!upload.join("5/beta").exists());
+
+        let entries = service
+            .list_entries(&user, None, &EntryFilter::All, ListingSort::Default, false)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(matches!(
+            service
+                .list_entries(
+                    &user,
+                    Some("../.."),
+                    &EntryFilter::All,
+                    ListingSort::Default,
+                    false
+                )
+                .await,
+            Err(ServiceError::InvalidPath)
+        ));
+        assert_eq!(
+            service.hub_ids().unwrap(),
+            vec![HubId::new(3), HubId::new(5)]
+        );
+    }
+
+    #[actix_web::test]
+    async fn concurrent_create_folder_reports_single_creation() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 4,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let form = CreateFolderForm {
+            name: "race/target".into(),
+            exist_ok: false,
+        };
+
+        // Both creations interleave on tokio's blocking pool.
+        let (first, second) = futures_util::join!(
+            service.create_folder(&user, None, &form),
+            service.create_folder(&user, None, &form)
+        );
+        let results = [first, second];
+
+        let created = results.iter().filter(|r| matches!(r, Ok(true))).count();
+        let conflicts = results
+            .iter()
+            .filter(|r| matches!(r, Err(ServiceError::FileExists)))
+            .count();
+        assert_eq!((created, conflicts), (1, 1));
+        assert!(dir.path().join("4/race/target").is_dir());
+    }
+
+    #[actix_web::test]
+    async fn create_folder_enforces_charset() {
+        let dir = tempdir().unwrap();
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 4,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let form = |name: &str| CreateFolderForm {
+            name: name.to_string(),
+            exist_ok: true,
+        };
+
+        let strict = build_service(dir.path().to_path_buf())
+            .with_folder_name_charset(FolderNameCharset::AlphanumericDash);
+        strict
+            .create_folder(&user, None, &form("my-folder_1"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            strict.create_folder(&user, None, &form("my folder!")).await,
+            Err(ServiceError::Validation(_))
+        ));
+        assert!(!dir.path().join("4/my folder!").exists());
+
+        let any = build_service(dir.path().to_path_buf());
+        any.create_folder(&user, None, &form("my-folder_2"))
+            .await
+            .unwrap();
+        any.create_folder(&user, None, &form("my folder!"))
+            .await
+            .unwrap();
+        assert!(dir.path().join("4/my folder!").is_dir());
+    }
+
+    #[actix_web::test]
+    async fn writes_into_reserved_dirs_are_rejected() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 9,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let mut temp = NamedTempFile::new().unwrap();
+        writeln!(temp, "content").unwrap();
+        let temp_file = TempFile {
+            file: temp,
+            content_type: None,
+            file_name: Some("note.txt".to_string()),
+            size: 0,
+        };
+        assert!(matches!(
+            service
+                .persist_upload(
+                    &user,
+                    Some(".trash"),
+                    Some("note.txt".to_string()),
+                    temp_file,
+                    false
+                )
+                .await,
+            Err(ServiceError::InvalidPath)
+        ));
+        assert!(matches!(
+            service
+                .create_folder(
+                    &user,
+                    None,
+                    &CreateFolderForm {
+                        name: ".versions".into(),
+                        exist_ok: true
+                    }
+                )
+                .await,
+            Err(ServiceError::InvalidPath)
+        ));
+        assert!(!dir.path().join("9/.trash").exists());
+        assert!(!dir.path().join("9/.versions").exists());
+
+        service
+            .create_folder(
+                &user,
+                None,
+                &CreateFolderForm {
+                    name: ".config".into(),
+                    exist_ok: true,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(dir.path().join("9/.config").is_dir());
+    }
+
+    #[actix_web::test]
+    async fn hub_upload_limits_override_global_limit() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf())
+            .with_hub_upload_limits(HashMap::from([(1, 2 * DEFAULT_MAX_UPLOAD_BYTES)]));
+        let user = |hub_id| AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let upload = async |hub_id| {
+            let temp = NamedTempFile::new().unwrap();
+            temp.as_file()
+                .set_len(DEFAULT_MAX_UPLOAD_BYTES + 1)
+                .unwrap();
+            let temp_file = TempFile {
+                file: temp,
+                content_type: None,
+                file_name: Some("design.psd".to_string()),
+                size: 0,
+            };
+            service
+                .persist_upload(
+                    &user(hub_id),
+                    None,
+                    Some("design.psd".to_string()),
+                    temp_file,
+                    false,
+                )
+                .await
+        };
+
+        upload(1).await.unwrap();
+        assert!(matches!(
+            upload(2).await,
+            Err(ServiceError::FileTooLarge { limit }) if limit == DEFAULT_MAX_UPLOAD_BYTES
+        ));
+
+        assert_eq!(
+            service.upload_limits(&user(1)).unwrap().max_upload_bytes,
+            2 * DEFAULT_MAX_UPLOAD_BYTES
+        );
+        assert_eq!(
+            service.upload_limits(&user(2)).unwrap().max_upload_bytes,
+            DEFAULT_MAX_UPLOAD_BYTES
+        );
+        assert_eq!(service.largest_upload_limit(), 2 * DEFAULT_MAX_UPLOAD_BYTES);
+    }
+
+    #[actix_web::test]
+    async fn persist_upload_writes_file() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 9,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let rel = RelativePath::try_new(PathBuf::from("uploads")).unwrap();
+        let file_name = FileName::try_new("note.txt".to_string()).unwrap();
+
+        let mut temp = NamedTempFile::new().unwrap();
+        writeln!(temp, "content").unwrap();
+        let temp_file = TempFile {
+            file: temp,
+            content_type: None,
+            file_name: Some("note.txt".to_string()),
+            size: 0,
+        };
+
+        let info = service
+            .persist_upload(
+                &user,
+                Some("uploads"),
+                Some("note.txt".to_string()),
+                temp_file,
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(info.name, "note.txt");
+        assert_eq!(info.content_type, "text/plain");
+
+        let storage = service.storage_for_hub(HubId::new(9));
+        let saved = storage.resolve_file(&rel, &file_name);
+        assert!(saved.exists());
+        let data = fs::read_to_string(saved).unwrap();
+        assert!(data.contains("content"));
+    }
+
+    #[actix_web::test]
+    async fn folder_quota_caps_uploads_below_it() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("29");
+        fs::create_dir_all(hub_root.join("public/press")).unwrap();
+        fs::create_dir_all(hub_root.join("private")).unwrap();
+        fs::write(hub_root.join("public").join(QUOTA_SIDECAR), "10\n").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 29,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let upload = async |relative: &str, name: &str| {
+            let mut temp = NamedTempFile::new().unwrap();
+            temp.write_all(b"12345678").unwrap();
+            let temp_file = TempFile {
+                file: temp,
+                content_type: None,
+                file_name: Some(name.to_string()),
+                size: 0,
+            };
+            service
+                .persist_upload(
+                    &user,
+                    Some(relative),
+                    Some(name.to_string()),
+                    temp_file,
+                    false,
+                )
+                .await
+        };
+
+        upload("public", "a.bin").await.unwrap();
+        match upload("public/press", "b.bin").await {
+            Err(ServiceError::QuotaExceeded { folder, limit }) => {
+                assert_eq!(folder, "/public");
+                assert_eq!(limit, 10);
+            }
+            other => panic!("expected quota error, got {other:?}"),
+        }
+        assert!(!hub_root.join("public/press/b.bin").exists());
+        upload("private", "b.bin").await.unwrap();
+
+        let listed = service
+            .list_entries(
+                &user,
+                Some("public"),
+                &EntryFilter::All,
+                ListingSort::Default,
+                false,
+            )
+            .await
+            .unwrap();
+        assert!(listed.iter().all(|entry| entry.name != QUOTA_SIDECAR));
+        assert!(matches!(
+            upload("private", QUOTA_SIDECAR).await,
+            Err(ServiceError::InvalidFileName)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn double_extension_executables_are_rejected() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf())
+            .with_double_extension_blocklist(&["exe".to_string(), ".SCR".to_string()]);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 35,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let upload = async |name: &str| {
+            let temp_file = TempFile {
+                file: NamedTempFile::new().unwrap(),
+                content_type: None,
+                file_name: Some(name.to_string()),
+                size: 0,
+            };
+            service
+                .persist_upload(&user, None, Some(name.to_string()), temp_file, false)
+                .await
+        };
+
+        assert!(matches!(
+            upload("invoice.pdf.exe").await,
+            Err(ServiceError::DisallowedExtension)
+        ));
+        assert!(matches!(
+            upload("photo.jpg.Scr").await,
+            Err(ServiceError::DisallowedExtension)
+        ));
+        upload("archive.tar.gz").await.unwrap();
+        upload("report.pdf").await.unwrap();
+        assert!(!dir.path().join("35/invoice.pdf.exe").exists());
+    }
+
+    #[actix_web::test]
+    async fn allowed_extensions_restrict_uploads_case_insensitively() {
+        let dir = tempdir().unwrap();
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 45,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let upload = async |service: &FileService, name: &str| {
+            let temp_file = TempFile {
+                file: NamedTempFile::new().unwrap(),
+                content_type: None,
+                file_name: Some(name.to_string()),
+                size: 0,
+            };
+            service
+                .persist_upload(&user, None, Some(name.to_string()), temp_file, true)
+                .await
+        };
+
+        let service = build_service(dir.path().to_path_buf())
+            .with_allowed_extensions(Some(&["pdf".to_string(), ".PNG".to_string()]));
+        upload(&service, "report.PDF").await.unwrap();
+        upload(&service, "logo.png").await.unwrap();
+        assert!(matches!(
+            upload(&service, "setup.exe").await,
+            Err(ServiceError::ForbiddenExtension)
+        ));
+        assert!(matches!(
+            upload(&service, "Makefile").await,
+            Err(ServiceError::ForbiddenExtension)
+        ));
+        assert!(!dir.path().join("45/setup.exe").exists());
+
+        let open = build_service(dir.path().to_path_buf()).with_allowed_extensions(None);
+        upload(&open, "setup.exe").await.unwrap();
+        upload(&open, "Makefile").await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn hub_quota_counts_the_whole_hub_and_blocks_uploads_beyond_it() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("46");
+        fs::create_dir_all(hub_root.join("a/b")).unwrap();
+        fs::write(hub_root.join("top.txt"), vec![0u8; 40]).unwrap();
+        fs::write(hub_root.join("a/b/deep.txt"), vec![0u8; 50]).unwrap();
+        fs::create_dir_all(dir.path().join("47")).unwrap();
+        fs::write(dir.path().join("47/other.txt"), vec![0u8; 500]).unwrap();
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 46,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let upload = async |service: &FileService, name: &str, size: usize| {
+            let mut file = NamedTempFile::new().unwrap();
+            file.write_all(&vec![0u8; size]).unwrap();
+            let temp_file = TempFile {
+                file,
+                content_type: None,
+                file_name: Some(name.to_string()),
+                size,
+            };
+            service
+                .persist_upload(&user, Some("a"), Some(name.to_string()), temp_file, true)
+                .await
+        };
+
+        let service = build_service(dir.path().to_path_buf()).with_quota_bytes(Some(100));
+        assert_eq!(service.hub_usage(&user).unwrap(), 90);
+
+        assert!(matches!(
+            upload(&service, "big.bin", 11).await,
+            Err(ServiceError::QuotaExceeded { ref folder, limit: 100 }) if folder == "/"
+        ));
+        assert!(!hub_root.join("a/big.bin").exists());
+        upload(&service, "fits.bin", 10).await.unwrap();
+        assert_eq!(service.hub_usage(&user).unwrap(), 100);
+
+        let unlimited = build_service(dir.path().to_path_buf());
+        upload(&unlimited, "big.bin", 11).await.unwrap();
+    }
+
+    #[test]
+    fn read_text_refuses_binary_and_oversized_files() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("48");
+        fs::create_dir_all(&hub_root).unwrap();
+        fs::write(hub_root.join("notes.txt"), "привет").unwrap();
+        fs::write(hub_root.join("latin1.txt"), b"caf\xe9").unwrap();
+        fs::write(hub_root.join("image.png"), b"\x89PNG\r\n\x1a\n\x00\x00").unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 48,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        assert_eq!(
+            service.read_text(&user, None, "notes.txt", 64).unwrap(),
+            "привет"
+        );
+        assert_eq!(
+            service.read_text(&user, None, "latin1.txt", 64).unwrap(),
+            "caf\u{fffd}"
+        );
+        assert!(matches!(
+            service.read_text(&user, None, "notes.txt", 4),
+            Err(ServiceError::TooLargeToView { limit: 4 })
+        ));
+        assert!(matches!(
+            service.read_text(&user, None, "image.png", 64),
+            Err(ServiceError::NotText)
+        ));
+        assert!(matches!(
+            service.read_text(&user, None, "missing.txt", 64),
+            Err(ServiceError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn copy_entry_duplicates_files_and_merges_folders() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("50");
+        fs::create_dir_all(hub_root.join("src/project/nested")).unwrap();
+        fs::create_dir_all(hub_root.join("dst/project")).unwrap();
+        fs::write(hub_root.join("src/project/a.txt"), b"a").unwrap();
+        fs::write(hub_root.join("src/project/nested/b.txt"), b"bb").unwrap();
+        fs::write(hub_root.join("src/notes.txt"), b"notes").unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 50,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        service
+            .copy_entry(&user, Some("src"), Some("dst"), "notes.txt")
+            .unwrap();
+        assert_eq!(fs::read(hub_root.join("dst/notes.txt")).unwrap(), b"notes");
+        assert!(hub_root.join("src/notes.txt").is_file());
+        assert!(matches!(
+            service.copy_entry(&user, Some("src"), Some("dst"), "notes.txt"),
+            Err(ServiceError::FileExists)
+        ));
+
+        service
+            .copy_entry(&user, Some("src"), Some("dst"), "project")
+            .unwrap();
+        assert_eq!(fs::read(hub_root.join("dst/project/a.txt")).unwrap(), b"a");
+        assert_eq!(
+            fs::read(hub_root.join("dst/project/nested/b.txt")).unwrap(),
+            b"bb"
+        );
+
+        fs::write(hub_root.join("src/project/c.txt"), b"c").unwrap();
+        match service.copy_entry(&user, Some("src"), Some("dst"), "project") {
+            Err(ServiceError::CopyConflict(paths)) => {
+                assert_eq!(paths, ["project/a.txt", "project/nested/b.txt"]);
+            }
+            other => panic!("expected a copy conflict, got {other:?}"),
+        }
+        assert!(!hub_root.join("dst/project/c.txt").exists());
+
+        assert!(matches!(
+            service.copy_entry(&user, None, Some("src/project/nested"), "src"),
+            Err(ServiceError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn copy_entry_refuses_folders_deeper_than_the_cap() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("50");
+        fs::create_dir_all(hub_root.join("src/project/a/b")).unwrap();
+        fs::create_dir_all(hub_root.join("dst")).unwrap();
+        fs::write(hub_root.join("src/project/a/b/deep.txt"), b"deep").unwrap();
+        let service = build_service(dir.path().to_path_buf()).with_max_tree_depth(1);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 50,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        assert!(matches!(
+            service.copy_entry(&user, Some("src"), Some("dst"), "project"),
+            Err(ServiceError::Validation(_))
+        ));
+        assert!(!hub_root.join("dst/project").exists());
+
+        let service = service.with_max_tree_depth(2);
+        service
+            .copy_entry(&user, Some("src"), Some("dst"), "project")
+            .unwrap();
+        assert_eq!(
+            fs::read(hub_root.join("dst/project/a/b/deep.txt")).unwrap(),
+            b"deep"
+        );
+    }
+
+    #[actix_web::test]
+    async fn chunked_uploads_join_parts_in_order_and_expire() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("52");
+        fs::create_dir_all(&hub_root).unwrap();
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 52,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let part = |content: &[u8]| {
+            let mut file = NamedTempFile::new().unwrap();
+            file.write_all(content).unwrap();
+            file
+        };
+        let service = build_service(dir.path().to_path_buf());
+        let id = Uuid::new_v4().to_string();
+
+        service
+            .store_chunk(&user, &id, 2, 3, part(b"!"))
+            .await
+            .unwrap();
+        service
+            .store_chunk(&user, &id, 0, 3, part(b"hello"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            service
+                .complete_chunked_upload(&user, None, &id, "hello.txt", false)
+                .await,
+            Err(ServiceError::Validation(_))
+        ));
+        assert!(matches!(
+            service.store_chunk(&user, &id, 1, 4, part(b" ")).await,
+            Err(ServiceError::Validation(_))
+        ));
+        service
+            .store_chunk(&user, &id, 1, 3, part(b", world"))
+            .await
+            .unwrap();
+
+        let info = service
+            .complete_chunked_upload(&user, None, &id, "hello.txt", false)
+            .await
+            .unwrap();
+        assert_eq!(info.size, 13);
+        assert_eq!(
+            fs::read(hub_root.join("hello.txt")).unwrap(),
+            b"hello, world!"
+        );
+        assert!(!hub_root.join(CHUNKED_UPLOAD_DIR).join(&id).exists());
+        assert!(matches!(
+            service
+                .store_chunk(&user, "not-a-uuid", 0, 1, part(b"x"))
+                .await,
+            Err(ServiceError::Validation(_))
+        ));
+
+        let abandoned = Uuid::new_v4().to_string();
+        service
+            .store_chunk(&user, &abandoned, 0, 2, part(b"x"))
+            .await
+            .unwrap();
+        let expiring =
+            build_service(dir.path().to_path_buf()).with_chunked_upload_ttl(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(20));
+        expiring
+            .store_chunk(&user, &Uuid::new_v4().to_string(), 0, 1, part(b"y"))
+            .await
+            .unwrap();
+        assert!(!hub_root.join(CHUNKED_UPLOAD_DIR).join(&abandoned).exists());
+    }
+
+    #[actix_web::test]
+    async fn reserved_sidecar_names_cannot_be_uploaded_or_renamed_to() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("40");
+        fs::create_dir_all(&hub_root).unwrap();
+        fs::write(hub_root.join("acl.txt"), b"acl").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 40,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let upload = async |service: &FileService, name: &str| {
+            let temp_file = TempFile {
+                file: NamedTempFile::new().unwrap(),
+                content_type: None,
+                file_name: Some(name.to_string()),
+                size: 0,
+            };
+            service
+                .persist_upload(&user, None, Some(name.to_string()), temp_file, false)
+                .await
+        };
+
+        for name in [".meta.json", ".acl.json", ".META.json"] {
+            assert!(matches!(
+                upload(&service, name).await,
+                Err(ServiceError::InvalidFileName)
+            ));
+        }
+        upload(&service, "meta.json").await.unwrap();
+
+        let renamed = service
+            .rename_by_pattern(&user, None, "^acl\\.txt$", ".acl.json")
+            .unwrap();
+        assert!(!renamed[0].renamed);
+        assert!(hub_root.join("acl.txt").exists());
+
+        let custom = build_service(dir.path().to_path_buf())
+            .with_reserved_sidecars(&[".tags.json".to_string()]);
+        assert!(upload(&custom, ".tags.json").await.is_err());
+        assert!(upload(&custom, QUOTA_SIDECAR).await.is_err());
+        upload(&custom, ".acl.json").await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn persist_upload_enforces_aspect_ratio() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf()).with_max_aspect_ratio(3.0);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 9,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let upload = async |name: &str, width: u32, height: u32| {
+            let temp = NamedTempFile::new().unwrap();
+            image::RgbImage::new(width, height)
+                .save_with_format(temp.path(), image::ImageFormat::Png)
+                .unwrap();
+            let temp_file = TempFile {
+                file: temp,
+                content_type: None,
+                file_name: Some(name.to_string()),
+                size: 0,
+            };
+            service
+                .persist_upload(&user, None, Some(name.to_string()), temp_file, false)
+                .await
+        };
+
+        assert!(matches!(
+            upload("panorama.png", 1000, 100).await,
+            Err(ServiceError::AspectRatioExceeded)
+        ));
+        upload("photo.png", 400, 300).await.unwrap();
+
+        let hub_root = dir.path().join("9");
+        assert!(!hub_root.join("panorama.png").exists());
+        assert!(hub_root.join("photo.png").exists());
+    }
+
+    #[actix_web::test]
+    async fn strict_mime_rejects_content_not_matching_the_extension() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf()).with_strict_mime(true);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 9,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let upload = async |name: &str, temp: NamedTempFile| {
+            let temp_file = TempFile {
+                file: temp,
+                content_type: None,
+                file_name: Some(name.to_string()),
+                size: 0,
+            };
+            service
+                .persist_upload(&user, None, Some(name.to_string()), temp_file, false)
+                .await
+        };
+        let png = || {
+            let temp = NamedTempFile::new().unwrap();
+            image::RgbImage::new(4, 4)
+                .save_with_format(temp.path(), image::ImageFormat::Png)
+                .unwrap();
+            temp
+        };
+        let text = || {
+            let mut temp = NamedTempFile::new().unwrap();
+            writeln!(temp, "just some notes").unwrap();
+            temp
+        };
+
+        upload("photo.png", png()).await.unwrap();
+        upload("notes.txt", text()).await.unwrap();
+        assert!(matches!(
+            upload("script.png", text()).await,
+            Err(ServiceError::ContentTypeMismatch)
+        ));
+        assert!(matches!(
+            upload("photo.jpg", png()).await,
+            Err(ServiceError::ContentTypeMismatch)
+        ));
+
+        let hub_root = dir.path().join("9");
+        assert!(hub_root.join("photo.png").exists());
+        assert!(!hub_root.join("script.png").exists());
+        assert!(!hub_root.join("photo.jpg").exists());
+    }
+
+    #[actix_web::test]
+    async fn chunk_manifest_matches_range_requests() {
+        use actix_web::{App, http::header, test};
+
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("5");
+        fs::create_dir_all(hub_root.join("big")).unwrap();
+        let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        fs::write(hub_root.join("big/blob.bin"), &data).unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 5,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let manifest = service
+            .chunk_manifest(&user, Some("big"), "blob.bin", Some(1000))
+            .unwrap();
+        assert_eq!(manifest.size, 2500);
+        assert_eq!(manifest.chunks.len(), 3);
+        assert_eq!(manifest.chunks[2].length, 500);
+
+        let app =
+            test::init_service(App::new().service(actix_files::Files::new("/upload", dir.path())))
+                .await;
+        for chunk in &manifest.chunks {
+            let range = format!("bytes={}-{}", chunk.offset, chunk.offset + chunk.length - 1);
+            let request = test::TestRequest::get()
+                .uri("/upload/5/big/blob.bin")
+                .insert_header((header::RANGE, range))
+                .to_request();
+            let body = test::call_and_read_body(&app, request).await;
+            assert_eq!(body.len() as u64, chunk.length);
+            assert_eq!(format!("{:x}", Sha256::digest(&body)), chunk.sha256);
+        }
+
+        assert!(matches!(
+            service.chunk_manifest(&user, Some("big"), "blob.bin", Some(0)),
+            Err(ServiceError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn download_file_stays_inside_the_users_hub() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("6/docs")).unwrap();
+        fs::create_dir_all(dir.path().join("7")).unwrap();
+        fs::write(dir.path().join("6/docs/report.pdf"), b"pdf").unwrap();
+        fs::write(dir.path().join("6/docs/.quota"), b"10").unwrap();
+        fs::write(dir.path().join("7/secret.txt"), b"other hub").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 6,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let path = service
+            .download_file(&user, Some("docs"), "report.pdf")
+            .unwrap();
+        assert_eq!(path, dir.path().join("6/docs/report.pdf"));
+        assert!(matches!(
+            service.download_file(&user, None, "docs"),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(matches!(
+            service.download_file(&user, Some("docs"), ".quota"),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(
+            service
+                .download_file(&user, Some("../7"), "secret.txt")
+                .is_err()
+        );
+        assert!(
+            service
+                .download_file(&user, None, "../7/secret.txt")
+                .is_err()
+        );
+
+        let outsider = AuthenticatedUser {
+            roles: vec![],
+            ..user
+        };
+        assert!(matches!(
+            service.download_file(&outsider, Some("docs"), "report.pdf"),
+            Err(ServiceError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn file_info_aggregates_present_metadata() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("5");
+        fs::create_dir_all(hub_root.join("docs")).unwrap();
+        fs::write(hub_root.join("docs/Report.PDF"), b"%PDF-1.7").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 5,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let info = service
+            .file_info(&user, Some("docs"), "Report.PDF")
+            .unwrap();
+        assert_eq!(info.size, 8);
+        assert_eq!(info.extension.as_deref(), Some("pdf"));
+        assert_eq!(info.content_type, "application/pdf");
+        assert!(info.modified.is_some());
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["name"], "Report.PDF");
+        assert!(json.get("download_count").is_none());
+        assert!(json.get("tags").is_none());
+
+        assert!(matches!(
+            service.file_info(&user, Some("docs"), "missing.pdf"),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(matches!(
+            service.file_info(&user, Some("../6"), "Report.PDF"),
+            Err(ServiceError::InvalidPath)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn listings_report_download_counts() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("5");
+        fs::create_dir_all(hub_root.join("docs")).unwrap();
+        fs::write(hub_root.join("docs/a.pdf"), b"%PDF").unwrap();
+        fs::write(hub_root.join("docs/b.pdf"), b"%PDF").unwrap();
+        let service = build_service(dir.path().to_path_buf()).with_download_counts(
+            DownloadCounts::load(dir.path().join(".download_counts.json")),
+        );
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 5,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        service.record_download(&user, Some("docs"), "a.pdf");
+        service.record_download(&user, Some("docs"), "a.pdf");
+        service.record_download(&user, Some("docs"), "missing.pdf");
+
+        let entries = service
+            .list_entries(
+                &user,
+                Some("docs"),
+                &EntryFilter::All,
+                ListingSort::Default,
+                false,
+            )
+            .await
+            .unwrap();
+        let downloads: Vec<(&str, u64)> = entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.downloads))
+            .collect();
+        assert_eq!(downloads, vec![("a.pdf", 2), ("b.pdf", 0)]);
+    }
+
+    #[actix_web::test]
+    async fn shared_storage_listings_follow_out_of_band_changes() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("5");
+        fs::create_dir_all(&hub_root).unwrap();
+        fs::write(hub_root.join("a.pdf"), b"%PDF").unwrap();
+        let sidecar = dir.path().join(".download_counts.json");
+        let service = build_service(dir.path().to_path_buf())
+            .with_download_counts(DownloadCounts::load(sidecar.clone()).with_shared_storage(true));
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 5,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let (service, user) = (&service, &user);
+        let listing = move || async move {
+            service
+                .list_entries(user, None, &EntryFilter::All, ListingSort::Default, false)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|entry| (entry.name, entry.downloads))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(listing().await, [("a.pdf".to_string(), 0)]);
+
+        // Another instance stores a file and serves a download.
+        fs::write(hub_root.join("b.pdf"), b"%PDF").unwrap();
+        let other = DownloadCounts::load(sidecar).with_shared_storage(true);
+        other.record(&service.storage_for_hub(HubId::new(5)).file_key(
+            &RelativePath::root(),
+            &FileName::try_from_str("a.pdf").unwrap(),
+        ));
+
+        assert_eq!(
+            listing().await,
+            [("a.pdf".to_string(), 1), ("b.pdf".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn stat_reports_image_dimensions_and_folders() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("5");
+        fs::create_dir_all(hub_root.join("docs")).unwrap();
+        image::RgbImage::new(6, 4)
+            .save_with_format(hub_root.join("docs/photo.png"), image::ImageFormat::Png)
+            .unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 5,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let photo = service.stat(&user, Some("docs"), "photo.png").unwrap();
+        assert!(photo.entry.is_image);
+        assert_eq!(photo.entry.content_type.as_deref(), Some("image/png"));
+        assert_eq!((photo.width, photo.height), (Some(6), Some(4)));
+        assert!(photo.size.is_some_and(|size| size > 0));
+        assert!(photo.modified.is_some());
+
+        let folder = service.stat(&user, None, "docs").unwrap();
+        assert!(folder.entry.is_directory);
+        assert_eq!(folder.size, None);
+        let json = serde_json::to_value(&folder).unwrap();
+        assert_eq!(json["name"], "docs");
+        assert!(json.get("width").is_none());
+
+        assert!(matches!(
+            service.stat(&user, Some("docs"), "missing.png"),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(matches!(
+            service.stat(&user, Some("../6"), "photo.png"),
+            Err(ServiceError::InvalidPath)
+        ));
+    }
+
+    #[test]
+    fn content_type_defaults_for_extensionless_files() {
+        let service = build_service(PathBuf::from("/unused"));
+        let plain = FileName::try_from_str("LICENSE").unwrap();
+        let image = FileName::try_from_str("cat.png").unwrap();
+        assert_eq!(
+            service.content_type_for(&plain),
+            mime::APPLICATION_OCTET_STREAM
+        );
+        assert_eq!(service.content_type_for(&image), mime::IMAGE_PNG);
+
+        let configured = build_service(PathBuf::from("/unused"))
+            .with_default_mime_extensionless(mime::TEXT_PLAIN);
+        assert_eq!(configured.content_type_for(&plain), mime::TEXT_PLAIN);
+    }
+
+    #[cfg(feature = "media-info")]
+    #[test]
+    fn media_info_reports_video_duration_and_rejects_images() {
+        use mp4::{AvcConfig, MediaConfig, Mp4Config, Mp4Sample, Mp4Writer, TrackConfig};
+
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("12");
+        fs::create_dir_all(&hub_root).unwrap();
+        fs::write(hub_root.join("cat.png"), b"\x89PNG").unwrap();
+
+        let video = fs::File::create(hub_root.join("clip.mp4")).unwrap();
+        let config = Mp4Config {
+            major_brand: "isom".parse().unwrap(),
+            minor_version: 512,
+            compatible_brands: vec!["isom".parse().unwrap(), "avc1".parse().unwrap()],
+            timescale: 1000,
+        };
+        let mut writer = Mp4Writer::write_start(video, &config).unwrap();
+        writer
+            .add_track(&TrackConfig {
+                track_type: mp4::TrackType::Video,
+                timescale: 1000,
+                language: "und".into(),
+                media_conf: MediaConfig::AvcConfig(AvcConfig {
+                    width: 320,
+                    height: 240,
+                    seq_param_set: vec![0x67, 0x64, 0x00, 0x1f],
+                    pic_param_set: vec![0x68, 0xeb],
+                }),
+            })
+            .unwrap();
+        for index in 0..2 {
+            writer
+                .write_sample(
+                    1,
+                    &Mp4Sample {
+                        start_time: index * 1000,
+                        duration: 1000,
+                        rendering_offset: 0,
+                        is_sync: true,
+                        bytes: mp4::Bytes::from_static(&[0, 0, 0, 1, 0x65]),
+                    },
+                )
+                .unwrap();
+        }
+        writer.write_end().unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 12,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let info = service.media_info(&user, None, "clip.mp4").unwrap();
+        assert_eq!(info.container, "isom");
+        assert!((info.duration_secs - 2.0).abs() < 0.01);
+        assert_eq!(info.tracks.len(), 1);
+        assert_eq!(info.tracks[0].width, Some(320));
+        assert!(matches!(
+            service.media_info(&user, None, "cat.png"),
+            Err(ServiceError::UnsupportedMedia)
+        ));
+    }
+
+    #[test]
+    fn read_head_returns_prefix_or_whole_file() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("5");
+        fs::create_dir_all(&hub_root).unwrap();
+        let log: String = (0..100).map(|i| format!("line {i}\n")).collect();
+        fs::write(hub_root.join("app.log"), &log).unwrap();
+        fs::write(hub_root.join("short.csv"), "a,b\n").unwrap();
+
+        let service = build_service(dir.path().to_path_buf()).with_max_head_bytes(200);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 5,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let head = service
+            .read_head(&user, None, "app.log", Some(100))
+            .unwrap();
+        assert_eq!(head, &log.as_bytes()[..100]);
+        assert!(looks_textual(&head));
+
+        let capped = service
+            .read_head(&user, None, "app.log", Some(10_000))
+            .unwrap();
+        assert_eq!(capped.len(), 200);
+
+        let short = service
+            .read_head(&user, None, "short.csv", Some(100))
+            .unwrap();
+        assert_eq!(short, b"a,b\n");
+
+        assert!(matches!(
+            service.read_head(&user, None, "missing.txt", Some(100)),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(!looks_textual(&[0x89, b'P', b'N', b'G', 0, 0]));
+        assert!(looks_textual("привет".as_bytes().split_last().unwrap().1));
+    }
+
+    #[test]
+    fn save_text_writes_exact_content() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 11,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let content = "первая строка\nsecond line\n";
+
+        let entry = service
+            .save_text(&user, Some("docs"), "notes.md", content, false)
+            .unwrap();
+
+        assert_eq!(entry.name, "notes.md");
+        assert!(!entry.is_directory);
+        let saved = dir.path().join("11/docs/notes.md");
+        assert_eq!(fs::read_to_string(saved).unwrap(), content);
+        // The temporary file is created next to the target and renamed away.
+        let names = fs::read_dir(dir.path().join("11/docs"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["notes.md"]);
+    }
+
+    #[test]
+    fn create_file_adds_an_empty_file_once() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("11/docs/drafts")).unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 11,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let entry = service
+            .create_file(&user, Some("docs"), "todo.txt")
+            .unwrap();
+        assert_eq!(entry.name, "todo.txt");
+        assert_eq!(fs::read(dir.path().join("11/docs/todo.txt")).unwrap(), b"");
+
+        let err = service
+            .create_file(&user, Some("docs"), "todo.txt")
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::FileExists));
+        let err = service
+            .create_file(&user, Some("docs"), "drafts")
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::Validation(_)));
+        let err = service
+            .create_file(&user, Some("missing"), "todo.txt")
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::NotFound));
+        let err = service
+            .create_file(&user, Some("docs"), QUOTA_SIDECAR)
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::InvalidFileName));
+    }
+
+    #[test]
+    fn write_text_replaces_content_within_the_cap() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("11/docs/drafts")).unwrap();
+        fs::write(dir.path().join("11/docs/notes.md"), b"old").unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 11,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        service
+            .write_text(&user, Some("docs"), "notes.md", "новый текст", 64)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("11/docs/notes.md")).unwrap(),
+            "новый текст"
+        );
+
+        let err = service
+            .write_text(&user, Some("docs"), "notes.md", "too long", 4)
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::FileTooLarge { limit: 4 }));
+        let err = service
+            .write_text(&user, Some("docs"), "drafts", "text", 64)
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::Validation(_)));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("11/docs/notes.md")).unwrap(),
+            "новый текст"
+        );
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingAuditLog(std::sync::Mutex<Vec<AuditRecord>>);
+
+    impl AuditLog for RecordingAuditLog {
+        fn record(&self, record: &AuditRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    #[actix_web::test]
+    async fn changes_are_recorded_in_the_audit_log() {
+        let dir = tempdir().unwrap();
+        let audit_log = Arc::new(RecordingAuditLog::default());
+        let service = build_service(dir.path().to_path_buf()).with_audit_log(audit_log.clone());
+        let user = AuthenticatedUser {
+            sub: "auditor".into(),
+            email: "user@example.com".into(),
+            hub_id: 11,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let form = CreateFolderForm {
+            name: "docs".into(),
+            exist_ok: false,
+        };
+
+        service.create_folder(&user, None, &form).await.unwrap();
+        service
+            .create_file(&user, Some("docs"), "draft.txt")
+            .unwrap();
+        service
+            .rename(&user, Some("docs"), "draft.txt", "final.txt")
+            .unwrap();
+        service
+            .delete_file(&user, Some("docs"), "final.txt")
+            .unwrap();
+
+        let records = audit_log.0.lock().unwrap();
+        let summary = records
+            .iter()
+            .map(|record| {
+                (
+                    record.operation,
+                    record.path.as_str(),
+                    record.target.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (AuditOperation::CreateFolder, "docs", None),
+                (AuditOperation::CreateFile, "docs/draft.txt", None),
+                (
+                    AuditOperation::Rename,
+                    "docs/draft.txt",
+                    Some("docs/final.txt")
+                ),
+                (AuditOperation::DeleteFile, "docs/final.txt", None),
+            ]
+        );
+        assert!(
+            records
+                .iter()
+                .all(|record| record.user == "auditor" && record.hub_id == 11)
+        );
+    }
+
+    #[test]
+    fn editor_and_bulk_changes_are_recorded_in_the_audit_log() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("11/docs")).unwrap();
+        let audit_log = Arc::new(RecordingAuditLog::default());
+        let service = build_service(dir.path().to_path_buf())
+            .with_audit_log(audit_log.clone())
+            .with_soft_delete(true);
+        let user = AuthenticatedUser {
+            sub: "auditor".into(),
+            email: "user@example.com".into(),
+            hub_id: 11,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        for name in ["a_1.txt", "b_1.txt"] {
+            service
+                .write_text(&user, Some("docs"), name, "text", 64)
+                .unwrap();
+        }
+        service
+            .rename_by_pattern(&user, Some("docs"), "^[a-z]_", "")
+            .unwrap();
+        service.delete_file(&user, Some("docs"), "1.txt").unwrap();
+        let archive = MoveMatchingForm {
+            from_path: Some("docs".into()),
+            to_path: Some("archive".into()),
+            glob: Some("*.txt".into()),
+            older_than: None,
+        };
+        service.move_matching(&user, &archive).unwrap();
+        service.empty_trash(&user).unwrap();
+
+        let records = audit_log.0.lock().unwrap();
+        let summary = records
+            .iter()
+            .map(|record| {
+                (
+                    record.operation,
+                    record.path.as_str(),
+                    record.target.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (AuditOperation::Save, "docs/a_1.txt", None),
+                (AuditOperation::Save, "docs/b_1.txt", None),
+                (AuditOperation::Rename, "docs/a_1.txt", Some("docs/1.txt")),
+                (
+                    AuditOperation::Rename,
+                    "docs/b_1.txt",
+                    Some("docs/1 (1).txt")
+                ),
+                (AuditOperation::DeleteFile, "docs/1.txt", None),
+                (
+                    AuditOperation::Rename,
+                    "docs/1 (1).txt",
+                    Some("archive/1 (1).txt")
+                ),
+                (AuditOperation::EmptyTrash, TRASH_DIR, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn save_text_rejects_invalid_name() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 11,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let err = service
+            .save_text(&user, None, "../escape.txt", "data", false)
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::InvalidFileName));
+    }
+
+    #[test]
+    fn delete_file_removes_files_only() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("36");
+        fs::create_dir_all(hub_root.join("docs/folder")).unwrap();
+        fs::write(hub_root.join("docs/mistake.txt"), b"oops").unwrap();
+        fs::create_dir_all(dir.path().join("37")).unwrap();
+        fs::write(dir.path().join("37/secret.txt"), b"secret").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 36,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        service
+            .delete_file(&user, Some("docs"), "mistake.txt")
+            .unwrap();
+        assert!(!hub_root.join("docs/mistake.txt").exists());
+        assert!(matches!(
+            service.delete_file(&user, Some("docs"), "mistake.txt"),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(matches!(
+            service.delete_file(&user, Some("docs"), "folder"),
+            Err(ServiceError::Validation(_))
+        ));
+        assert!(hub_root.join("docs/folder").is_dir());
+        assert!(
+            service
+                .delete_file(&user, Some("../37"), "secret.txt")
+                .is_err()
+        );
+        assert!(dir.path().join("37/secret.txt").exists());
+    }
+
+    #[actix_web::test]
+    async fn soft_delete_moves_entries_to_trash_and_restores_them() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("54");
+        fs::create_dir_all(hub_root.join("docs/old")).unwrap();
+        fs::write(hub_root.join("docs/report.pdf"), b"pdf").unwrap();
+        fs::write(hub_root.join("docs/old/draft.txt"), b"draft").unwrap();
+
+        let service = build_service(dir.path().to_path_buf()).with_soft_delete(true);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 54,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        service
+            .delete_file(&user, Some("docs"), "report.pdf")
+            .unwrap();
+        assert!(!hub_root.join("docs/report.pdf").exists());
+        assert!(matches!(
+            service.delete_folder(&user, Some("docs"), "old", false),
+            Err(ServiceError::FolderNotEmpty)
+        ));
+        service
+            .delete_folder(&user, Some("docs"), "old", true)
+            .unwrap();
+        assert!(!hub_root.join("docs/old").exists());
+
+        let trashed = |suffix: &str| {
+            fs::read_dir(hub_root.join(TRASH_DIR))
+                .unwrap()
+                .filter_map(|e| e.ok())
+                .map(|e| format!("{}/{suffix}", e.file_name().to_string_lossy()))
+                .find(|path| hub_root.join(TRASH_DIR).join(path).exists())
+                .unwrap()
+        };
+        let report = trashed("docs/report.pdf");
+        let folder = trashed("docs/old");
+        assert_eq!(
+            fs::read(hub_root.join(TRASH_DIR).join(&folder).join("draft.txt")).unwrap(),
+            b"draft"
+        );
+
+        let names =
+            |entries: Vec<FileEntryDto>| entries.into_iter().map(|e| e.name).collect::<Vec<_>>();
+        let root = service
+            .list_entries(&user, None, &EntryFilter::All, ListingSort::Default, false)
+            .await
+            .unwrap();
+        assert_eq!(names(root), ["docs"]);
+
+        service.restore(&user, &report).unwrap();
+        assert_eq!(fs::read(hub_root.join("docs/report.pdf")).unwrap(), b"pdf");
+        assert!(matches!(
+            service.restore(&user, &report),
+            Err(ServiceError::NotFound)
+        ));
+        fs::create_dir_all(hub_root.join("docs/old")).unwrap();
+        assert!(matches!(
+            service.restore(&user, &folder),
+            Err(ServiceError::FileExists)
+        ));
+        assert!(matches!(
+            service.restore(&user, "../../55/secret.txt"),
+            Err(ServiceError::InvalidPath)
+        ));
+
+        service.empty_trash(&user).unwrap();
+        assert!(!hub_root.join(TRASH_DIR).exists());
+        service.empty_trash(&user).unwrap();
+    }
+
+    #[test]
+    fn soft_delete_keeps_every_trashed_copy_of_a_path() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("54");
+        fs::create_dir_all(hub_root.join("docs")).unwrap();
+        let service = build_service(dir.path().to_path_buf()).with_soft_delete(true);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 54,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        for round in 0..5 {
+            fs::write(hub_root.join("docs/draft.txt"), round.to_string()).unwrap();
+            service
+                .delete_file(&user, Some("docs"), "draft.txt")
+                .unwrap();
+        }
+
+        let mut trashed: Vec<String> = fs::read_dir(hub_root.join(TRASH_DIR))
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .map(|e| fs::read_to_string(e.path().join("docs/draft.txt")).unwrap())
+            .collect();
+        trashed.sort();
+        assert_eq!(trashed, ["0", "1", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn delete_many_reports_each_name() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("56");
+        fs::create_dir_all(hub_root.join("docs/folder")).unwrap();
+        fs::write(hub_root.join("docs/a.txt"), b"a").unwrap();
+        fs::write(hub_root.join("docs/b.txt"), b"b").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 56,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let names = ["a.txt", "missing.txt", "folder", "../b.txt", "b.txt"]
+            .map(String::from)
+            .to_vec();
+        let results = service.delete_many(&user, Some("docs"), names).unwrap();
+        let deleted: Vec<(&str, bool)> = results
+            .iter()
+            .map(|r| (r.name.as_str(), r.deleted))
+            .collect();
+        assert_eq!(
+            deleted,
+            [
+                ("a.txt", true),
+                ("missing.txt", false),
+                ("folder", false),
+                ("../b.txt", false),
+                ("b.txt", true),
+            ]
+        );
+        assert!(results[1].error.is_some());
+        assert!(!hub_root.join("docs/a.txt").exists());
+        assert!(!hub_root.join("docs/b.txt").exists());
+        assert!(hub_root.join("docs/folder").is_dir());
+
+        assert!(matches!(
+            service.delete_many(&user, Some("docs"), vec![]),
+            Err(ServiceError::Validation(_))
+        ));
+        assert!(matches!(
+            service.delete_many(&user, Some("../57"), vec!["a.txt".into()]),
+            Err(ServiceError::InvalidPath)
+        ));
+    }
+
+    #[test]
+    fn delete_folder_requires_recursive_for_contents() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("41");
+        fs::create_dir_all(hub_root.join("empty")).unwrap();
+        fs::create_dir_all(hub_root.join("full/nested")).unwrap();
+        fs::create_dir_all(hub_root.join(".trash")).unwrap();
+        fs::write(hub_root.join("full/nested/file.txt"), b"data").unwrap();
+        fs::write(hub_root.join("note.txt"), b"data").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 41,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        service.delete_folder(&user, None, "empty", false).unwrap();
+        assert!(!hub_root.join("empty").exists());
+
+        assert!(matches!(
+            service.delete_folder(&user, None, "full", false),
+            Err(ServiceError::FolderNotEmpty)
+        ));
+        assert!(hub_root.join("full/nested/file.txt").exists());
+        service.delete_folder(&user, None, "full", true).unwrap();
+        assert!(!hub_root.join("full").exists());
+
+        for name in [".", "..", ""] {
+            assert!(service.delete_folder(&user, None, name, true).is_err());
+        }
+        assert!(matches!(
+            service.delete_folder(&user, None, ".trash", true),
+            Err(ServiceError::InvalidPath)
+        ));
+        assert!(matches!(
+            service.delete_folder(&user, None, "note.txt", true),
+            Err(ServiceError::Validation(_))
+        ));
+        assert!(hub_root.is_dir());
+    }
+
+    #[test]
+    fn deleting_last_nested_file_prunes_empty_chain() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("38");
+        fs::create_dir_all(hub_root.join("a/b")).unwrap();
+        fs::write(hub_root.join("a/b/last.txt"), b"last").unwrap();
+        fs::write(hub_root.join("root.txt"), b"root").unwrap();
+
+        let service = build_service(dir.path().to_path_buf()).with_prune_empty_on_delete(true);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 38,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        service.delete_file(&user, Some("a/b"), "last.txt").unwrap();
+        assert!(!hub_root.join("a").exists());
+        service.delete_file(&user, None, "root.txt").unwrap();
+        assert!(hub_root.is_dir());
+    }
+
+    #[test]
+    fn publish_swaps_staging_live_and_keeps_backup() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("39");
+        fs::create_dir_all(hub_root.join("site/staging")).unwrap();
+        fs::create_dir_all(hub_root.join("site/public")).unwrap();
+        fs::write(hub_root.join("site/staging/index.html"), b"new").unwrap();
+        fs::write(hub_root.join("site/public/index.html"), b"old").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 39,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let form = |staging: &str, target: &str| PublishForm {
+            path: Some("site".into()),
+            staging: staging.into(),
+            target: target.into(),
+        };
+
+        let result = service.publish(&user, &form("staging", "public")).unwrap();
+        let backup = result.backup.unwrap();
+        assert!(backup.starts_with("public.backup-"));
+        assert_eq!(
+            fs::read_to_string(hub_root.join("site/public/index.html")).unwrap(),
+            "new"
+        );
+        assert_eq!(
+            fs::read_to_string(hub_root.join("site").join(&backup).join("index.html")).unwrap(),
+            "old"
+        );
+        assert!(!hub_root.join("site/staging").exists());
+
+        assert!(matches!(
+            service.publish(&user, &form("staging", "public")),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(matches!(
+            service.publish(&user, &form("../x", "public")),
+            Err(ServiceError::InvalidFileName)
+        ));
+    }
+
+    #[test]
+    fn publish_is_mirrored_and_audited() {
+        let dir = tempdir().unwrap();
+        let mirror = tempdir().unwrap();
+        for root in [dir.path(), mirror.path()] {
+            fs::create_dir_all(root.join("39/site/staging")).unwrap();
+            fs::create_dir_all(root.join("39/site/public")).unwrap();
+            fs::write(root.join("39/site/staging/index.html"), b"new").unwrap();
+            fs::write(root.join("39/site/public/index.html"), b"old").unwrap();
+        }
+
+        let audit_log = Arc::new(RecordingAuditLog::default());
+        let service = build_service(dir.path().to_path_buf())
+            .with_mirror(UploadRoot::from(mirror.path().to_path_buf()))
+            .with_audit_log(audit_log.clone());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 39,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let form = PublishForm {
+            path: Some("site".into()),
+            staging: "staging".into(),
+            target: "public".into(),
+        };
+
+        let backup = service.publish(&user, &form).unwrap().backup.unwrap();
+
+        let records = audit_log.0.lock().unwrap();
+        let renames = records
+            .iter()
+            .map(|record| (record.path.clone(), record.target.clone().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            renames,
+            [
+                ("site/public".to_string(), format!("site/{backup}")),
+                ("site/staging".to_string(), "site/public".to_string()),
+            ]
+        );
+
+        // Mirroring runs in the background; give it a moment to land.
+        let mirrored = mirror.path().join("39/site");
+        for _ in 0..50 {
+            if !mirrored.join("staging").exists() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert_eq!(
+            fs::read_to_string(mirrored.join("public/index.html")).unwrap(),
+            "new"
+        );
+        assert_eq!(
+            fs::read_to_string(mirrored.join(&backup).join("index.html")).unwrap(),
+            "old"
+        );
+    }
+
+    #[actix_web::test]
+    async fn persist_upload_replays_on_mirror() {
+        let dir = tempdir().unwrap();
+        let mirror = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf())
+            .with_mirror(UploadRoot::from(mirror.path().to_path_buf()));
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 9,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let mut temp = NamedTempFile::new().unwrap();
+        writeln!(temp, "mirrored").unwrap();
+        let temp_file = TempFile {
+            file: temp,
+            content_type: None,
+            file_name: Some("note.txt".to_string()),
+            size: 0,
+        };
+        service
+            .persist_upload(
+                &user,
+                Some("docs"),
+                Some("note.txt".to_string()),
+                temp_file,
+                false,
+            )
+            .await
+            .unwrap();
+
+        let primary = dir.path().join("9/docs/note.txt");
+        let mirrored = mirror.path().join("9/docs/note.txt");
+        assert!(primary.exists());
+        // Mirroring runs in the background; give it a moment to land.
+        for _ in 0..50 {
+            if fs::read_to_string(&mirrored).is_ok_and(|data| data.contains("mirrored")) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert_eq!(
+            fs::read_to_string(&mirrored).unwrap(),
+            fs::read_to_string(&primary).unwrap()
+        );
+    }
+
+    #[test]
+    fn storage_breakdown_groups_by_category() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("21");
+        fs::create_dir_all(hub_root.join("nested/deep")).unwrap();
+        fs::write(hub_root.join("a.png"), b"abc").unwrap();
+        fs::write(hub_root.join("nested/b.JPG"), b"abcd").unwrap();
+        fs::write(hub_root.join("nested/doc.pdf"), b"12345").unwrap();
+        fs::write(hub_root.join("nested/deep/movie.mp4"), b"1234567").unwrap();
+        fs::write(hub_root.join("backup.zip"), b"12").unwrap();
+        fs::write(hub_root.join("data.bin"), b"1").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 21,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let breakdown = service.storage_breakdown(&user, None).unwrap();
+        assert_eq!((breakdown.image.bytes, breakdown.image.count), (7, 2));
+        assert_eq!((breakdown.document.bytes, breakdown.document.count), (5, 1));
+        assert_eq!((breakdown.video.bytes, breakdown.video.count), (7, 1));
+        assert_eq!((breakdown.archive.bytes, breakdown.archive.count), (2, 1));
+        assert_eq!((breakdown.other.bytes, breakdown.other.count), (1, 1));
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn list_entries_follows_symlinked_root_when_allowed() {
+        let dir = tempdir().unwrap();
+        let volume = tempdir().unwrap();
+        fs::write(volume.path().join("inside.txt"), b"data").unwrap();
+        std::os::unix::fs::symlink(volume.path(), dir.path().join("4")).unwrap();
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 4,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let strict = build_service(dir.path().to_path_buf());
+        let err = strict
+            .list_entries(&user, None, &EntryFilter::All, ListingSort::Default, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::InvalidPath));
+
+        let relaxed = build_service(dir.path().to_path_buf()).with_symlinked_roots(true);
+        let entries = relaxed
+            .list_entries(&user, None, &EntryFilter::All, ListingSort::Default, false)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "inside.txt");
+    }
+
+    #[test]
+    fn folder_depth_reports_nesting_and_cap() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("22");
+        fs::create_dir_all(hub_root.join("flat")).unwrap();
+        fs::write(hub_root.join("flat/file.txt"), b"data").unwrap();
+        fs::create_dir_all(hub_root.join("nested/a/b/c")).unwrap();
+        fs::create_dir_all(hub_root.join("nested/x")).unwrap();
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 22,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let service = build_service(dir.path().to_path_buf());
+        let flat = service.folder_depth(&user, Some("flat"), None).unwrap();
+        assert_eq!((flat.depth, flat.capped), (0, false));
+        let nested = service.folder_depth(&user, Some("nested"), None).unwrap();
+        assert_eq!((nested.depth, nested.capped), (3, false));
+        let shallow = service
+            .folder_depth(&user, Some("nested"), Some(1))
+            .unwrap();
+        assert_eq!((shallow.depth, shallow.capped), (1, true));
+
+        let capped = build_service(dir.path().to_path_buf())
+            .with_max_tree_depth(2)
+            .folder_depth(&user, Some("nested"), None)
+            .unwrap();
+        assert_eq!((capped.depth, capped.capped), (2, true));
+    }
+
+    #[test]
+    fn list_tree_nests_entries_and_stops_at_the_cap() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("22");
+        fs::create_dir_all(hub_root.join("docs/a/b")).unwrap();
+        fs::create_dir_all(hub_root.join(".trash")).unwrap();
+        fs::write(hub_root.join("docs/a/b/deep.txt"), b"data").unwrap();
+        fs::write(hub_root.join("docs/readme.md"), b"data").unwrap();
+        fs::write(hub_root.join("top.txt"), b"data").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&hub_root, hub_root.join("docs/loop")).unwrap();
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 22,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let service = build_service(dir.path().to_path_buf());
+        let names = |node: &TreeNodeDto| -> Vec<String> {
+            node.children.iter().map(|c| c.name.clone()).collect()
+        };
+
+        let tree = service.list_tree(&user, None).unwrap();
+        assert_eq!(names(&tree), vec!["docs", "top.txt"]);
+        let docs = &tree.children[0];
+        assert_eq!(names(docs), vec!["a", "readme.md"]);
+        assert_eq!(names(&docs.children[0].children[0]), vec!["deep.txt"]);
+        assert!(!docs.truncated);
+
+        let shallow = service.list_tree(&user, Some(2)).unwrap();
+        let a = &shallow.children[0].children[0];
+        assert!(a.children.is_empty());
+        assert!(a.truncated);
+
+        assert!(matches!(
+            service.list_tree(&user, Some(-1)),
+            Err(ServiceError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn requested_depth_is_clamped_and_validated() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("22");
+        fs::create_dir_all(hub_root.join("nested/a/b/c")).unwrap();
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 22,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let service = build_service(dir.path().to_path_buf()).with_max_tree_depth(2);
+
+        assert_eq!(service.effective_depth(Some(1_000_000)).unwrap(), 2);
+        let clamped = service
+            .folder_depth(&user, Some("nested"), Some(1_000_000))
+            .unwrap();
+        assert_eq!((clamped.depth, clamped.capped), (2, true));
+
+        assert!(matches!(
+            service.folder_depth(&user, Some("nested"), Some(-1)),
+            Err(ServiceError::Validation(_))
+        ));
+        assert!(matches!(
+            service.storage_breakdown(&user, Some(-5)),
+            Err(ServiceError::Validation(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn reject_policy_requires_confirmed_overwrite() {
+        let dir = tempdir().unwrap();
+        let service =
+            build_service(dir.path().to_path_buf()).with_conflict_policy(ConflictPolicy::Reject);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 23,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let upload = |content: &str| {
+            let mut temp = NamedTempFile::new().unwrap();
+            write!(temp, "{content}").unwrap();
+            TempFile {
+                file: temp,
+                content_type: None,
+                file_name: Some("report.pdf".to_string()),
+                size: 0,
+            }
+        };
+        let saved = dir.path().join("23/report.pdf");
+
+        service
+            .persist_upload(&user, None, Some("report.pdf".into()), upload("v1"), false)
+            .await
+            .unwrap();
+        let err = service
+            .persist_upload(&user, None, Some("report.pdf".into()), upload("v2"), false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::FileExists));
+        assert_eq!(fs::read_to_string(&saved).unwrap(), "v1");
+
+        service
+            .persist_upload(&user, None, Some("report.pdf".into()), upload("v2"), true)
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(&saved).unwrap(), "v2");
+    }
+
+    #[actix_web::test]
+    async fn duplicate_uploads_are_numbered_unless_overwriting() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 46,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let upload = async |name: &str, content: &str, overwrite: bool| {
+            let mut temp = NamedTempFile::new().unwrap();
+            write!(temp, "{content}").unwrap();
+            let temp_file = TempFile {
+                file: temp,
+                content_type: None,
+                file_name: Some(name.to_string()),
+                size: 0,
+            };
+            service
+                .persist_upload(&user, None, Some(name.to_string()), temp_file, overwrite)
+                .await
+                .map(|info| info.name)
+        };
+        let hub_root = dir.path().join("46");
+
+        assert_eq!(
+            upload("report.pdf", "v1", false).await.unwrap(),
+            "report.pdf"
+        );
+        assert_eq!(
+            upload("report.pdf", "v2", false).await.unwrap(),
+            "report (1).pdf"
+        );
+        assert_eq!(
+            upload("report.pdf", "v3", false).await.unwrap(),
+            "report (2).pdf"
+        );
+        assert_eq!(
+            fs::read_to_string(hub_root.join("report.pdf")).unwrap(),
+            "v1"
+        );
+        assert_eq!(
+            fs::read_to_string(hub_root.join("report (1).pdf")).unwrap(),
+            "v2"
+        );
+        assert_eq!(
+            upload("archive.tar.gz", "a", false).await.unwrap(),
+            "archive.tar.gz"
+        );
+        assert_eq!(
+            upload("archive.tar.gz", "b", false).await.unwrap(),
+            "archive.tar (1).gz"
+        );
+        assert_eq!(upload("Makefile", "a", false).await.unwrap(), "Makefile");
+        assert_eq!(
+            upload("Makefile", "b", false).await.unwrap(),
+            "Makefile (1)"
+        );
+
+        assert_eq!(
+            upload("report.pdf", "v4", true).await.unwrap(),
+            "report.pdf"
+        );
+        assert_eq!(
+            fs::read_to_string(hub_root.join("report.pdf")).unwrap(),
+            "v4"
+        );
+
+        let overwriting =
+            build_service(dir.path().to_path_buf()).with_conflict_policy(ConflictPolicy::Overwrite);
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(temp, "v5").unwrap();
+        let temp_file = TempFile {
+            file: temp,
+            content_type: None,
+            file_name: Some("report.pdf".to_string()),
+            size: 0,
+        };
+        overwriting
+            .persist_upload(&user, None, Some("report.pdf".into()), temp_file, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(hub_root.join("report.pdf")).unwrap(),
+            "v5"
+        );
+        assert!(!hub_root.join("report (3).pdf").exists());
+    }
+
+    #[actix_web::test]
+    async fn listed_files_carry_guessed_content_types() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("49");
+        fs::create_dir_all(hub_root.join("docs")).unwrap();
+        fs::write(hub_root.join("report.pdf"), b"%PDF").unwrap();
+        fs::write(hub_root.join("README"), b"text").unwrap();
+        let service = build_service(dir.path().to_path_buf())
+            .with_default_mime_extensionless(mime::TEXT_PLAIN);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 49,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let entries = service
+            .list_entries(&user, None, &EntryFilter::All, ListingSort::Default, false)
+            .await
+            .unwrap();
+        let content_type = |name: &str| {
+            entries
+                .iter()
+                .find(|e| e.name == name)
+                .unwrap()
+                .content_type
+                .clone()
+        };
+        assert_eq!(content_type("docs"), None);
+        assert_eq!(
+            content_type("report.pdf").as_deref(),
+            Some("application/pdf")
+        );
+        assert_eq!(content_type("README").as_deref(), Some("text/plain"));
+    }
+
+    #[actix_web::test]
+    async fn configured_image_extensions_replace_the_builtin_set() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("51");
+        fs::create_dir_all(&hub_root).unwrap();
+        fs::write(hub_root.join("photo.HEIC"), b"heic").unwrap();
+        fs::write(hub_root.join("logo.png"), b"png").unwrap();
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 51,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let images = async |service: &FileService| {
+            let mut names: Vec<String> = service
+                .list_entries(
+                    &user,
+                    None,
+                    &EntryFilter::Images,
+                    ListingSort::Default,
+                    false,
+                )
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|e| e.is_image)
+                .map(|e| e.name)
+                .collect();
+            names.sort();
+            names
+        };
+
+        let builtin = build_service(dir.path().to_path_buf());
+        assert_eq!(images(&builtin).await, ["logo.png"]);
+
+        let configured = build_service(dir.path().to_path_buf())
+            .with_image_extensions(Some(&[".avif".to_string(), "heic".to_string()]));
+        assert_eq!(images(&configured).await, ["photo.HEIC"]);
+        let category = |name: &str| configured.category_of(&FileName::try_from_str(name).unwrap());
+        assert_eq!(category("photo.heic"), FileCategory::Image);
+        assert_eq!(category("logo.png"), FileCategory::Other);
+        assert_eq!(category("clip.mp4"), FileCategory::Video);
+    }
+
+    #[actix_web::test]
+    async fn list_entries_hides_dotfiles_unless_asked() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("55");
+        fs::create_dir_all(hub_root.join(".config")).unwrap();
+        fs::create_dir_all(hub_root.join(TRASH_DIR)).unwrap();
+        fs::write(hub_root.join(".DS_Store"), b"junk").unwrap();
+        fs::write(hub_root.join("report.pdf"), b"pdf").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 55,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let names = async |show_hidden: bool| {
+            service
+                .list_entries(
+                    &user,
+                    None,
+                    &EntryFilter::All,
+                    ListingSort::parse(Some("name"), None),
+                    show_hidden,
+                )
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(false).await, ["report.pdf"]);
+        assert_eq!(names(true).await, [".config", ".DS_Store", "report.pdf"]);
+        let streamed = service
+            .stream_entries(&user, None, EntryFilter::All, false)
+            .unwrap()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>();
+        assert_eq!(streamed, ["report.pdf"]);
+    }
+
+    #[actix_web::test]
+    async fn list_entries_applies_entry_filter() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("50");
+        fs::create_dir_all(hub_root.join("photos")).unwrap();
+        fs::write(hub_root.join("cat.PNG"), b"png").unwrap();
+        fs::write(hub_root.join("report.pdf"), b"pdf").unwrap();
+        fs::write(hub_root.join("notes.txt"), b"txt").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 50,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let names = async |filter: &str| {
+            service
+                .list_entries(
+                    &user,
+                    None,
+                    &EntryFilter::parse(filter),
+                    ListingSort::parse(Some("name"), None),
+                    false,
+                )
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("images").await, ["cat.PNG"]);
+        assert_eq!(names("dirs").await, ["photos"]);
+        assert_eq!(names("ext:PDF").await, ["report.pdf"]);
+        assert_eq!(
+            names("whatever").await,
+            ["photos", "cat.PNG", "notes.txt", "report.pdf"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn list_entries_sorts_by_requested_key() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("49");
+        fs::create_dir_all(hub_root.join("zeta")).unwrap();
+        fs::create_dir_all(hub_root.join("alpha")).unwrap();
+        for (name, size, modified) in [
+            ("big.bin", 300, 3),
+            ("mid.bin", 200, 1),
+            ("small.bin", 100, 2),
+        ] {
+            let file = fs::File::create(hub_root.join(name)).unwrap();
+            file.set_len(size).unwrap();
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(modified * 1_000_000))
+                .unwrap();
+        }
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 49,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let names = async |sort| {
+            service
+                .list_entries(&user, None, &EntryFilter::All, sort, false)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(ListingSort::parse(Some("size"), Some("desc"))).await[2..],
+            ["big.bin", "mid.bin", "small.bin"]
+        );
+        assert_eq!(
+            names(ListingSort::parse(Some("size"), None)).await[2..],
+            ["small.bin", "mid.bin", "big.bin"]
+        );
+        assert_eq!(
+            names(ListingSort::parse(Some("modified"), Some("asc"))).await[2..],
+            ["mid.bin", "small.bin", "big.bin"]
+        );
+        assert_eq!(
+            names(ListingSort::parse(Some("name"), Some("desc"))).await,
+            ["zeta", "alpha", "small.bin", "mid.bin", "big.bin"]
+        );
+    }
+
+    #[test]
+    fn recent_lists_newest_files_outside_internal_folders() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("47");
+        fs::create_dir_all(hub_root.join("docs/old")).unwrap();
+        fs::create_dir_all(hub_root.join(TRASH_DIR)).unwrap();
+        let touch = |relative: &str, secs: u64| {
+            let file = fs::File::create(hub_root.join(relative)).unwrap();
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap();
+        };
+        touch("docs/old/first.txt", 1_000);
+        touch("docs/second.txt", 2_000);
+        touch("third.png", 3_000);
+        touch(".trash/deleted.txt", 4_000);
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 47,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let recent = service
+            .recent(&user, 2)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.path.unwrap(), entry.name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            recent,
+            [
+                (String::new(), "third.png".to_string()),
+                ("docs".to_string(), "second.txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn search_matches_names_across_the_hub_only() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("47");
+        fs::create_dir_all(hub_root.join("clients/acme/Reports")).unwrap();
+        fs::create_dir_all(dir.path().join("48")).unwrap();
+        fs::write(hub_root.join("report-2023.pdf"), b"a").unwrap();
+        fs::write(hub_root.join("clients/acme/Reports/q1-REPORT.xlsx"), b"b").unwrap();
+        fs::write(hub_root.join("clients/acme/invoice.pdf"), b"c").unwrap();
+        fs::write(dir.path().join("48/report-secret.pdf"), b"d").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path().join("48"), hub_root.join("clients/other")).unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 47,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let found = service
+            .search(&user, "Report")
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.path.unwrap(), entry.name, entry.is_directory))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (String::new(), "report-2023.pdf".to_string(), false),
+                ("clients/acme".to_string(), "Reports".to_string(), true),
+                (
+                    "clients/acme/Reports".to_string(),
+                    "q1-REPORT.xlsx".to_string(),
+                    false
+                ),
+            ]
+        );
+        assert!(matches!(
+            service.search(&user, "  "),
+            Err(ServiceError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn search_skips_reserved_folders() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("47");
+        fs::create_dir_all(hub_root.join(".trash/old")).unwrap();
+        fs::create_dir_all(hub_root.join(".versions")).unwrap();
+        fs::create_dir_all(hub_root.join("docs")).unwrap();
+        fs::write(hub_root.join(".trash/old/report.pdf"), b"a").unwrap();
+        fs::write(hub_root.join(".versions/report.pdf.1"), b"b").unwrap();
+        fs::write(hub_root.join("docs/report.pdf"), b"c").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 47,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let found = service
+            .search(&user, "report")
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.path.unwrap(), entry.name))
+            .collect::<Vec<_>>();
+        assert_eq!(found, [("docs".to_string(), "report.pdf".to_string())]);
+    }
+
+    #[test]
+    fn swap_entries_exchanges_contents_without_gaps() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("25");
+        fs::create_dir_all(hub_root.join("site")).unwrap();
+        let current = hub_root.join("site/current.json");
+        let next = hub_root.join("site/next.json");
+        fs::write(&current, b"old").unwrap();
+        fs::write(&next, b"new").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 25,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        service
+            .swap_entries(&user, Some("site"), "current.json", "next.json")
+            .unwrap();
+        assert_eq!(fs::read_to_string(&current).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&next).unwrap(), "old");
+
+        // Keep swapping while a reader checks both names never disappear.
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let reader = {
+            let stop = stop.clone();
+            let (current, next) = (current.clone(), next.clone());
+            std::thread::spawn(move || {
+                let mut missing = false;
+                while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                    missing |= !current.exists() || !next.exists();
+                }
+                missing
+            })
+        };
+        for _ in 0..50 {
+            service
+                .swap_entries(&user, Some("site"), "current.json", "next.json")
+                .unwrap();
+        }
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        assert!(!reader.join().unwrap());
+        assert_eq!(fs::read_dir(hub_root.join("site")).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn nfd_names_on_disk_resolve_from_nfc_requests() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("26");
+        fs::create_dir_all(&hub_root).unwrap();
+        let stored = hub_root.join("cafe\u{301}.txt");
+        fs::write(&stored, b"menu").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 26,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let listed = service.visible_name(stored.file_name().unwrap()).unwrap();
+        assert_eq!(listed.as_str(), "cafe\u{301}.txt");
+
+        let path = service.download_file(&user, None, "caf\u{e9}.txt").unwrap();
+        assert_eq!(path, stored);
+        service.delete_file(&user, None, "caf\u{e9}.txt").unwrap();
+        assert!(!stored.exists());
+    }
+
+    #[test]
+    fn rename_by_pattern_replaces_prefix_and_reports_invalid() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("26");
+        fs::create_dir_all(hub_root.join("batch")).unwrap();
+        for name in ["wrong_a.txt", "wrong_b.txt", "keep.txt", "bad.txt"] {
+            fs::write(hub_root.join("batch").join(name), name).unwrap();
+        }
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 26,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let results = service
+            .rename_by_pattern(&user, Some("batch"), "^wrong_", "right_")
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.renamed));
+        assert!(hub_root.join("batch/right_a.txt").exists());
+        assert!(hub_root.join("batch/right_b.txt").exists());
+        assert!(!hub_root.join("batch/wrong_a.txt").exists());
+        assert!(hub_root.join("batch/keep.txt").exists());
+
+        let results = service
+            .rename_by_pattern(&user, Some("batch"), "^bad", "../bad")
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].renamed);
+        assert!(results[0].error.is_some());
+        assert!(hub_root.join("batch/bad.txt").exists());
+    }
+
+    #[test]
+    fn rename_by_pattern_keeps_colliding_files() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("26");
+        fs::create_dir_all(hub_root.join("batch/2.txt")).unwrap();
+        for name in ["a_1.txt", "b_1.txt", "c_2.txt"] {
+            fs::write(hub_root.join("batch").join(name), name).unwrap();
+        }
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 26,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let results = service
+            .rename_by_pattern(&user, Some("batch"), "^[a-z]_", "")
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].to, "1.txt");
+        assert_eq!(results[1].to, "1 (1).txt");
+        assert!(results[..2].iter().all(|r| r.renamed));
+        assert_eq!(fs::read(hub_root.join("batch/1.txt")).unwrap(), b"a_1.txt");
+        assert_eq!(
+            fs::read(hub_root.join("batch/1 (1).txt")).unwrap(),
+            b"b_1.txt"
+        );
+
+        // A folder of the target name is never replaced.
+        assert!(!results[2].renamed);
+        assert!(results[2].error.is_some());
+        assert!(hub_root.join("batch/c_2.txt").exists());
+    }
+
+    #[test]
+    fn rename_by_pattern_keeps_a_distinct_file_differing_only_in_case() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("26");
+        fs::create_dir_all(hub_root.join("batch")).unwrap();
+        fs::write(hub_root.join("batch/A.txt"), b"upper").unwrap();
+        fs::write(hub_root.join("batch/a.txt"), b"lower").unwrap();
+        if fs::read(hub_root.join("batch/A.txt")).unwrap() != b"upper" {
+            // Case-insensitive file system: both names are one file.
+            return;
+        }
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 26,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let results = service
+            .rename_by_pattern(&user, Some("batch"), "^A", "a")
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].renamed);
+        assert_eq!(results[0].to, "a (1).txt");
+        assert_eq!(
+            fs::read(hub_root.join("batch/a (1).txt")).unwrap(),
+            b"upper"
+        );
+        assert_eq!(fs::read(hub_root.join("batch/a.txt")).unwrap(), b"lower");
+    }
+
+    #[test]
+    fn move_matching_archives_by_glob_and_age() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("27");
+        fs::create_dir_all(hub_root.join("logs")).unwrap();
+        for name in ["a.log", "b.log", "notes.txt", "old.csv", "new.csv"] {
+            fs::write(hub_root.join("logs").join(name), name).unwrap();
+        }
+        let old = fs::File::options()
+            .write(true)
+            .open(hub_root.join("logs/old.csv"))
+            .unwrap();
+        old.set_modified(UNIX_EPOCH + Duration::from_secs(1_000_000))
+            .unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 27,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let form = |glob: Option<&str>, older_than: Option<u64>| MoveMatchingForm {
+            from_path: Some("logs".into()),
+            to_path: Some("archive/2024".into()),
+            glob: glob.map(str::to_string),
+            older_than,
+        };
+
+        let results = service
+            .move_matching(&user, &form(Some("*.log"), None))
+            .unwrap();
+        let moved: Vec<_> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(moved, ["a.log", "b.log"]);
+        assert!(results.iter().all(|r| r.moved));
+        assert!(hub_root.join("archive/2024/a.log").exists());
+        assert!(!hub_root.join("logs/a.log").exists());
+        assert!(hub_root.join("logs/notes.txt").exists());
+
+        let results = service
+            .move_matching(&user, &form(None, Some(2_000_000)))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "old.csv");
+        assert!(hub_root.join("archive/2024/old.csv").exists());
+        assert!(hub_root.join("logs/new.csv").exists());
+
+        assert!(matches!(
+            service.move_matching(&user, &form(None, None)),
+            Err(ServiceError::Validation(_))
+        ));
+        let mut escape = form(Some("*"), None);
+        escape.to_path = Some("../26".into());
+        assert!(service.move_matching(&user, &escape).is_err());
+    }
+
+    #[test]
+    fn move_matching_keeps_existing_target_files() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("27");
+        fs::create_dir_all(hub_root.join("logs")).unwrap();
+        fs::create_dir_all(hub_root.join("archive")).unwrap();
+        fs::write(hub_root.join("logs/a.log"), b"new").unwrap();
+        fs::write(hub_root.join("archive/a.log"), b"old").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 27,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let form = MoveMatchingForm {
+            from_path: Some("logs".into()),
+            to_path: Some("archive".into()),
+            glob: Some("*.log".into()),
+            older_than: None,
+        };
+
+        let results = service.move_matching(&user, &form).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].moved);
+        assert_eq!(results[0].to, "a (1).log");
+        assert_eq!(fs::read(hub_root.join("archive/a.log")).unwrap(), b"old");
+        assert_eq!(
+            fs::read(hub_root.join("archive/a (1).log")).unwrap(),
+            b"new"
+        );
+    }
+
+    #[test]
+    fn emptied_folder_chain_is_pruned_but_hub_root_kept() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("32");
+        fs::create_dir_all(hub_root.join("a/b/c")).unwrap();
+        fs::write(hub_root.join("a/b/c/last.log"), b"log").unwrap();
+        fs::write(hub_root.join("top.log"), b"log").unwrap();
+
+        let service = build_service(dir.path().to_path_buf()).with_prune_empty_on_delete(true);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 32,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let move_logs = |from: Option<&str>, to: &str| MoveMatchingForm {
+            from_path: from.map(str::to_string),
+            to_path: Some(to.into()),
+            glob: Some("*.log".into()),
+            older_than: None,
+        };
+
+        service
+            .move_matching(&user, &move_logs(Some("a/b/c"), "archive"))
+            .unwrap();
+        assert!(!hub_root.join("a").exists());
+        assert!(hub_root.join("archive/last.log").exists());
+
+        service
+            .move_matching(&user, &move_logs(Some("archive"), "kept"))
+            .unwrap();
+        service
+            .move_matching(&user, &move_logs(None, "kept"))
+            .unwrap();
+        assert!(hub_root.is_dir());
+        assert!(hub_root.join("kept/top.log").exists());
+    }
+
+    #[test]
+    fn prune_empty_sweeps_nested_folders() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("33");
+        fs::create_dir_all(hub_root.join("empty/deeper")).unwrap();
+        fs::create_dir_all(hub_root.join("full")).unwrap();
+        fs::create_dir_all(hub_root.join(".trash")).unwrap();
+        fs::write(hub_root.join("full/keep.txt"), b"data").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let admin = AuthenticatedUser {
+            sub: "admin".into(),
+            email: "admin@example.com".into(),
+            hub_id: 1,
+            name: "Admin".into(),
+            roles: vec![SERVICE_ADMIN_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let mut removed = service.prune_empty(&admin, HubId::new(33)).unwrap();
+        removed.sort();
+        assert_eq!(removed, ["empty", "empty/deeper"]);
+        assert!(hub_root.join("full/keep.txt").exists());
+        assert!(hub_root.join(".trash").is_dir());
+        assert!(hub_root.is_dir());
+
+        let mut member = admin;
+        member.roles = vec![SERVICE_ACCESS_ROLE.to_string()];
+        assert!(matches!(
+            service.prune_empty(&member, HubId::new(33)),
+            Err(ServiceError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn rename_handles_files_and_folders_without_overwriting() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("42");
+        fs::create_dir_all(hub_root.join("docs/Reprots")).unwrap();
+        fs::write(hub_root.join("docs/Reprots/q1.pdf"), b"q1").unwrap();
+        fs::write(hub_root.join("docs/invoce.pdf"), b"inv").unwrap();
+        fs::write(hub_root.join("docs/taken.pdf"), b"taken").unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 42,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        service
+            .rename(&user, Some("docs"), "invoce.pdf", "invoice.pdf")
+            .unwrap();
+        assert!(hub_root.join("docs/invoice.pdf").is_file());
+        service
+            .rename(&user, Some("docs"), "Reprots", "Reports")
+            .unwrap();
+        assert!(hub_root.join("docs/Reports/q1.pdf").is_file());
+
+        assert!(matches!(
+            service.rename(&user, Some("docs"), "invoice.pdf", "taken.pdf"),
+            Err(ServiceError::FileExists)
+        ));
+        assert_eq!(
+            fs::read_to_string(hub_root.join("docs/taken.pdf")).unwrap(),
+            "taken"
+        );
+        assert!(matches!(
+            service.rename(&user, Some("docs"), "missing.pdf", "other.pdf"),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(matches!(
+            service.rename(&user, Some("docs"), "invoice.pdf", "../escape.pdf"),
+            Err(ServiceError::InvalidFileName)
+        ));
+    }
+
+    #[test]
+    fn rename_keeps_a_distinct_file_differing_only_in_case() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("42");
+        fs::create_dir_all(&hub_root).unwrap();
+        fs::write(hub_root.join("Report.pdf"), b"upper").unwrap();
+        fs::write(hub_root.join("report.pdf"), b"lower").unwrap();
+        if fs::read(hub_root.join("Report.pdf")).unwrap() != b"upper" {
+            // Case-insensitive file system: both names are one file.
+            return;
+        }
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 42,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        assert!(matches!(
+            service.rename(&user, None, "Report.pdf", "report.pdf"),
+            Err(ServiceError::FileExists)
+        ));
+        assert_eq!(fs::read(hub_root.join("Report.pdf")).unwrap(), b"upper");
+        assert_eq!(fs::read(hub_root.join("report.pdf")).unwrap(), b"lower");
+    }
+
+    #[test]
+    fn move_entry_relocates_within_the_hub() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("43");
+        fs::create_dir_all(hub_root.join("inbox/project")).unwrap();
+        fs::create_dir_all(hub_root.join("archive")).unwrap();
+        fs::write(hub_root.join("inbox/report.pdf"), b"pdf").unwrap();
+        fs::write(hub_root.join("inbox/project/plan.txt"), b"plan").unwrap();
+        fs::create_dir_all(dir.path().join("44")).unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 43,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
 
-        let filepath = storage.resolve_file(&relative, &file_name);
-        temp_file
-            .file
-            .persist(filepath)
-            .map_err(|err| ServiceError::SaveFile(err.error))?;
+        service
+            .move_entry(&user, Some("inbox"), Some("archive"), "report.pdf")
+            .unwrap();
+        service
+            .move_entry(&user, Some("inbox"), Some("archive"), "project")
+            .unwrap();
+        assert!(hub_root.join("archive/report.pdf").is_file());
+        assert!(hub_root.join("archive/project/plan.txt").is_file());
 
-        Ok(())
+        assert!(matches!(
+            service.move_entry(&user, Some("archive"), Some("missing"), "report.pdf"),
+            Err(ServiceError::InvalidPath)
+        ));
+        assert!(matches!(
+            service.move_entry(&user, Some("archive"), Some("archive/project"), "project"),
+            Err(ServiceError::Validation(_))
+        ));
+        assert!(
+            service
+                .move_entry(&user, Some("archive"), Some("../44"), "report.pdf")
+                .is_err()
+        );
+        assert!(hub_root.join("archive/report.pdf").is_file());
+
+        fs::write(hub_root.join("report.pdf"), b"other").unwrap();
+        assert!(matches!(
+            service.move_entry(&user, None, Some("archive"), "report.pdf"),
+            Err(ServiceError::FileExists)
+        ));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::fs;
-    use std::io::Write;
-    use std::path::PathBuf;
+    #[test]
+    fn sanitize_names_reports_in_dry_run_and_renames() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("31");
+        fs::create_dir_all(hub_root.join("docs")).unwrap();
+        fs::write(hub_root.join("docs/bad\tname?.txt"), b"data").unwrap();
+        fs::write(hub_root.join("docs/fine.txt"), b"data").unwrap();
 
-    use super::*;
-    use pushkind_common::domain::auth::AuthenticatedUser;
-    use tempfile::{NamedTempFile, tempdir};
+        let service = build_service(dir.path().to_path_buf());
+        let admin = AuthenticatedUser {
+            sub: "admin".into(),
+            email: "admin@example.com".into(),
+            hub_id: 1,
+            name: "Admin".into(),
+            roles: vec![SERVICE_ADMIN_ROLE.to_string()],
+            exp: 0,
+        };
 
-    fn build_service(root: PathBuf) -> FileService {
-        FileService::new(UploadRoot::from(root))
+        let planned = service
+            .sanitize_names(&admin, HubId::new(31), true)
+            .unwrap();
+        assert_eq!(planned.len(), 1);
+        assert_eq!(planned[0].to, "docs/bad_name_.txt");
+        assert!(!planned[0].renamed);
+        assert!(hub_root.join("docs/bad\tname?.txt").exists());
+
+        let applied = service
+            .sanitize_names(&admin, HubId::new(31), false)
+            .unwrap();
+        assert!(applied[0].renamed);
+        assert!(hub_root.join("docs/bad_name_.txt").exists());
+        assert!(hub_root.join("docs/fine.txt").exists());
+
+        let user = AuthenticatedUser {
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            ..admin
+        };
+        assert!(matches!(
+            service.sanitize_names(&user, HubId::new(31), true),
+            Err(ServiceError::Unauthorized)
+        ));
+    }
+
+    fn zip_upload(build: impl FnOnce(&mut zip::ZipWriter<&fs::File>)) -> TempFile {
+        let temp = NamedTempFile::new().unwrap();
+        let mut writer = zip::ZipWriter::new(temp.as_file());
+        build(&mut writer);
+        writer.finish().unwrap();
+        TempFile {
+            file: temp,
+            content_type: None,
+            file_name: Some("upload.zip".to_string()),
+            size: 0,
+        }
     }
 
     #[test]
-    fn list_entries_sorted_and_typed() {
+    fn zip_folder_keeps_relative_paths_and_handles_empty_folders() {
         let dir = tempdir().unwrap();
-        let hub_root = dir.path().join("42");
-        fs::create_dir_all(&hub_root).unwrap();
-        fs::create_dir(hub_root.join("b_folder")).unwrap();
-        fs::create_dir(hub_root.join("a_folder")).unwrap();
-        fs::write(hub_root.join("a_file.txt"), b"hello").unwrap();
-        std::thread::sleep(std::time::Duration::from_secs(1));
-        fs::write(hub_root.join("c_image.png"), b"fakepng").unwrap();
+        let hub_root = dir.path().join("8");
+        fs::create_dir_all(hub_root.join("backup/docs/empty")).unwrap();
+        fs::create_dir_all(hub_root.join("nothing")).unwrap();
+        fs::write(hub_root.join("backup/a.txt"), b"hello").unwrap();
+        fs::write(hub_root.join("backup/docs/b.txt"), b"world").unwrap();
+        fs::write(hub_root.join("backup/.quota"), b"100").unwrap();
 
         let service = build_service(dir.path().to_path_buf());
         let user = AuthenticatedUser {
             sub: "user".into(),
             email: "user@example.com".into(),
-            hub_id: 42,
+            hub_id: 8,
             name: "User".into(),
             roles: vec![SERVICE_ACCESS_ROLE.to_string()],
             exp: 0,
         };
-        let entries = service.list_entries(&user, None).unwrap();
-
-        let names: Vec<String> = entries.iter().map(|e| e.name.clone()).collect();
-        assert!(entries[0].is_directory);
-        assert!(entries[1].is_directory);
-        assert!(!entries[2].is_directory);
-        assert!(entries.iter().any(|entry| entry.is_image));
 
-        let created_a = fs::metadata(hub_root.join("a_file.txt"))
-            .ok()
-            .and_then(|m| m.created().ok());
-        let created_c = fs::metadata(hub_root.join("c_image.png"))
-            .ok()
-            .and_then(|m| m.created().ok());
-        // When creation timestamps are unavailable or identical, we fall back to name ordering.
-        let expected_files = match (created_a, created_c) {
-            (Some(a_time), Some(c_time)) if c_time > a_time => {
-                vec!["c_image.png".to_string(), "a_file.txt".to_string()]
-            }
-            (Some(a_time), Some(c_time)) if a_time > c_time => {
-                vec!["a_file.txt".to_string(), "c_image.png".to_string()]
-            }
-            _ => vec!["a_file.txt".to_string(), "c_image.png".to_string()],
-        };
+        let bytes = service.zip_folder(&user, Some("backup")).unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut names = archive.file_names().map(str::to_string).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["a.txt", "docs/", "docs/b.txt", "docs/empty/"]);
+        let mut content = String::new();
+        archive
+            .by_name("docs/b.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "world");
 
+        let empty = service.zip_folder(&user, Some("nothing")).unwrap();
         assert_eq!(
-            names,
-            [
-                &["a_folder".to_string(), "b_folder".to_string()][..],
-                &expected_files
-            ]
-            .concat()
+            zip::ZipArchive::new(std::io::Cursor::new(empty))
+                .unwrap()
+                .len(),
+            0
         );
+        assert!(matches!(
+            service.zip_folder(&user, Some("missing")),
+            Err(ServiceError::NotFound)
+        ));
     }
 
     #[test]
-    fn list_entries_missing_dir_returns_empty() {
+    fn zip_folder_leaves_out_hidden_and_reserved_entries() {
         let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("8");
+        for reserved in [TRASH_DIR, ".thumbnails", CHUNKED_UPLOAD_DIR] {
+            fs::create_dir_all(hub_root.join(reserved)).unwrap();
+            fs::write(hub_root.join(reserved).join("x.txt"), b"internal").unwrap();
+        }
+        fs::create_dir_all(hub_root.join("docs/.git")).unwrap();
+        fs::write(hub_root.join("docs/.git/HEAD"), b"ref").unwrap();
+        fs::write(hub_root.join("docs/.env"), b"SECRET=1").unwrap();
+        fs::write(hub_root.join("docs/a.txt"), b"hello").unwrap();
+        fs::write(hub_root.join(QUOTA_SIDECAR), b"100").unwrap();
+
         let service = build_service(dir.path().to_path_buf());
         let user = AuthenticatedUser {
             sub: "user".into(),
             email: "user@example.com".into(),
-            hub_id: 99,
+            hub_id: 8,
             name: "User".into(),
             roles: vec![SERVICE_ACCESS_ROLE.to_string()],
             exp: 0,
         };
 
-        let entries = service.list_entries(&user, Some("nope")).unwrap();
-        assert!(entries.is_empty());
+        let bytes = service.zip_folder(&user, None).unwrap();
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut names = archive.file_names().map(str::to_string).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["docs/", "docs/a.txt"]);
     }
 
     #[test]
-    fn list_entries_rejects_parent_paths() {
+    fn quota_sidecar_cannot_be_read_or_inspected() {
         let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("8");
+        fs::create_dir_all(&hub_root).unwrap();
+        fs::write(hub_root.join(QUOTA_SIDECAR), b"100").unwrap();
+
         let service = build_service(dir.path().to_path_buf());
         let user = AuthenticatedUser {
             sub: "user".into(),
             email: "user@example.com".into(),
-            hub_id: 1,
+            hub_id: 8,
             name: "User".into(),
             roles: vec![SERVICE_ACCESS_ROLE.to_string()],
             exp: 0,
         };
 
-        let err = service.list_entries(&user, Some("../etc")).unwrap_err();
-        assert!(matches!(err, ServiceError::InvalidPath));
+        assert!(matches!(
+            service.read_head(&user, None, QUOTA_SIDECAR, None),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(matches!(
+            service.chunk_manifest(&user, None, QUOTA_SIDECAR, None),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(matches!(
+            service.stat(&user, None, QUOTA_SIDECAR),
+            Err(ServiceError::NotFound)
+        ));
+        assert!(matches!(
+            service.file_info(&user, None, QUOTA_SIDECAR),
+            Err(ServiceError::NotFound)
+        ));
     }
 
     #[test]
-    fn create_folder_builds_nested_structure() {
+    fn inspect_archive_reports_benign_and_flags_malicious() {
+        use zip::write::SimpleFileOptions;
+
         let dir = tempdir().unwrap();
         let service = build_service(dir.path().to_path_buf());
         let user = AuthenticatedUser {
             sub: "user".into(),
             email: "user@example.com".into(),
-            hub_id: 5,
+            hub_id: 3,
             name: "User".into(),
             roles: vec![SERVICE_ACCESS_ROLE.to_string()],
             exp: 0,
         };
-        let form = CreateFolderForm {
-            name: "beta".to_string(),
-        };
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
 
-        service.create_folder(&user, Some("alpha"), &form).unwrap();
+        let benign = zip_upload(|zip| {
+            zip.start_file("a.txt", options).unwrap();
+            zip.write_all(b"hello").unwrap();
+            zip.start_file("docs/b.txt", options).unwrap();
+            zip.write_all(b"world").unwrap();
+        });
+        let report = service.inspect_archive(&user, benign).unwrap();
+        assert_eq!(report.entries.len(), 2);
+        assert_eq!(report.total_size, 10);
+        assert!(report.entries.iter().all(|e| e.problem.is_none()));
+        assert!(!report.possible_zip_bomb);
 
-        let storage = service.storage_for_hub(HubId::from(5));
-        assert!(
-            storage
-                .resolve_dir(
-                    &RelativePath::try_new(PathBuf::from("alpha"))
-                        .unwrap()
-                        .join(&RelativePath::try_new(PathBuf::from("beta")).unwrap())
-                )
-                .exists()
+        let malicious = zip_upload(|zip| {
+            zip.start_file("../evil.sh", options).unwrap();
+            zip.write_all(b"#!/bin/sh").unwrap();
+            zip.start_file("zeros.bin", options).unwrap();
+            zip.write_all(&vec![0u8; 10 * 1024 * 1024]).unwrap();
+        });
+        let report = service.inspect_archive(&user, malicious).unwrap();
+        assert!(report.entries[0].problem.is_some());
+        assert!(report.possible_zip_bomb);
+        assert!(!dir.path().join("evil.sh").exists());
+    }
+
+    #[actix_web::test]
+    async fn extract_upload_unpacks_folders_and_rejects_zip_slip() {
+        use zip::write::SimpleFileOptions;
+
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("53");
+        fs::create_dir_all(hub_root.join("target")).unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 53,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        let options = SimpleFileOptions::default();
+
+        let tree = zip_upload(|zip| {
+            zip.add_directory("docs/empty/", options).unwrap();
+            zip.start_file("a.txt", options).unwrap();
+            zip.write_all(b"hello").unwrap();
+            zip.start_file("docs/b.txt", options).unwrap();
+            zip.write_all(b"world").unwrap();
+        });
+        let result = service
+            .extract_upload(&user, Some("target"), tree, false)
+            .await
+            .unwrap();
+        assert_eq!(result.files, ["a.txt", "docs/b.txt"]);
+        assert_eq!(fs::read(hub_root.join("target/a.txt")).unwrap(), b"hello");
+        assert_eq!(
+            fs::read(hub_root.join("target/docs/b.txt")).unwrap(),
+            b"world"
         );
+        assert!(hub_root.join("target/docs/empty").is_dir());
+
+        let zip_slips: [&[&str]; 3] = [
+            &["ok.txt", "../../evil.sh"],
+            &["/tmp/evil.sh"],
+            &["..\\evil.sh"],
+        ];
+        for names in zip_slips {
+            let malicious = zip_upload(|zip| {
+                for name in names {
+                    zip.start_file(*name, options).unwrap();
+                    zip.write_all(b"#!/bin/sh").unwrap();
+                }
+            });
+            assert!(matches!(
+                service
+                    .extract_upload(&user, Some("target"), malicious, false)
+                    .await,
+                Err(ServiceError::InvalidPath)
+            ));
+        }
+        assert!(!hub_root.join("target/ok.txt").exists());
+        assert!(!hub_root.join("evil.sh").exists());
+        assert!(!dir.path().join("evil.sh").exists());
     }
 
     #[test]
-    fn persist_upload_writes_file() {
+    fn is_zip_name_checks_the_extension() {
+        assert!(FileService::is_zip_name("tree.ZIP"));
+        assert!(!FileService::is_zip_name("tree.zip.txt"));
+        assert!(!FileService::is_zip_name("zip"));
+    }
+
+    #[test]
+    fn swap_entries_refuses_directories() {
         let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("25");
+        fs::create_dir_all(hub_root.join("folder")).unwrap();
+        fs::write(hub_root.join("file.txt"), b"data").unwrap();
+
         let service = build_service(dir.path().to_path_buf());
         let user = AuthenticatedUser {
             sub: "user".into(),
             email: "user@example.com".into(),
-            hub_id: 9,
+            hub_id: 25,
             name: "User".into(),
             roles: vec![SERVICE_ACCESS_ROLE.to_string()],
             exp: 0,
         };
-        let rel = RelativePath::try_new(PathBuf::from("uploads")).unwrap();
-        let file_name = FileName::try_new("note.txt".to_string()).unwrap();
 
-        let mut temp = NamedTempFile::new().unwrap();
-        writeln!(temp, "content").unwrap();
-        let temp_file = TempFile {
-            file: temp,
-            content_type: None,
-            file_name: Some("note.txt".to_string()),
-            size: 0,
-        };
+        let err = service
+            .swap_entries(&user, None, "file.txt", "folder")
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::Validation(_)));
+        let err = service
+            .swap_entries(&user, None, "file.txt", "missing.txt")
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::NotFound));
+    }
 
-        service
-            .persist_upload(
-                &user,
-                Some("uploads"),
-                Some("note.txt".to_string()),
-                temp_file,
-            )
-            .unwrap();
+    #[test]
+    fn list_shared_visible_to_any_hub() {
+        let dir = tempdir().unwrap();
+        let shared_root = dir.path().join("shared");
+        fs::create_dir_all(shared_root.join("templates")).unwrap();
+        fs::write(shared_root.join("templates/offer.docx"), b"doc").unwrap();
 
-        let storage = service.storage_for_hub(HubId::from(9));
-        let saved = storage.resolve_file(&rel, &file_name);
-        assert!(saved.exists());
-        let data = fs::read_to_string(saved).unwrap();
-        assert!(data.contains("content"));
+        let service = build_service(dir.path().join("upload")).with_shared_root(shared_root);
+        for hub_id in [1, 2] {
+            let user = AuthenticatedUser {
+                sub: "user".into(),
+                email: "user@example.com".into(),
+                hub_id,
+                name: "User".into(),
+                roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+                exp: 0,
+            };
+
+            let root = service.list_shared(&user, None).unwrap();
+            assert_eq!(root.len(), 1);
+            assert!(root[0].is_directory);
+            let files = service.list_shared(&user, Some("templates")).unwrap();
+            assert_eq!(files[0].name, "offer.docx");
+            assert!(
+                service
+                    .shared_file(&user, Some("templates"), "offer.docx")
+                    .unwrap()
+                    .ends_with("templates/offer.docx")
+            );
+        }
     }
 
+    #[cfg(unix)]
     #[test]
-    fn unauthorized_without_role() {
+    fn shared_file_hides_paths_escaping_the_library() {
+        let dir = tempdir().unwrap();
+        let shared_root = dir.path().join("shared");
+        fs::create_dir_all(&shared_root).unwrap();
+        fs::write(dir.path().join("secret.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), shared_root.join("link.txt"))
+            .unwrap();
+
+        let service = build_service(dir.path().join("upload")).with_shared_root(shared_root);
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 1,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        for name in ["link.txt", "missing.txt"] {
+            assert!(matches!(
+                service.shared_file(&user, None, name),
+                Err(ServiceError::NotFound)
+            ));
+        }
+    }
+
+    #[actix_web::test]
+    async fn unauthorized_without_role() {
         let dir = tempdir().unwrap();
         let service = build_service(dir.path().to_path_buf());
         let user = AuthenticatedUser {
@@ -366,18 +7511,45 @@ mod tests {
             exp: 0,
         };
 
-        let err = service.list_entries(&user, None).unwrap_err();
+        let err = service
+            .list_entries(&user, None, &EntryFilter::All, ListingSort::Default, false)
+            .await
+            .unwrap_err();
         assert!(matches!(err, ServiceError::Unauthorized));
 
         let form = CreateFolderForm {
             name: "".to_string(),
+            exist_ok: true,
         };
-        let err = service.create_folder(&user, None, &form).unwrap_err();
+        let err = service.create_folder(&user, None, &form).await.unwrap_err();
         assert!(matches!(err, ServiceError::Validation(_)));
     }
 
-    #[test]
-    fn create_folder_rejects_invalid_current_path() {
+    #[actix_web::test]
+    async fn unauthorized_with_non_positive_hub_id() {
+        let dir = tempdir().unwrap();
+        let service = build_service(dir.path().to_path_buf());
+        for hub_id in [0, -1] {
+            let user = AuthenticatedUser {
+                sub: "user".into(),
+                email: "user@example.com".into(),
+                hub_id,
+                name: "User".into(),
+                roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+                exp: 0,
+            };
+            let err = service
+                .list_entries(&user, None, &EntryFilter::All, ListingSort::Default, false)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ServiceError::Unauthorized));
+        }
+        assert!(!dir.path().join("0").exists());
+        assert!(!dir.path().join("-1").exists());
+    }
+
+    #[actix_web::test]
+    async fn create_folder_rejects_invalid_current_path() {
         let dir = tempdir().unwrap();
         let service = build_service(dir.path().to_path_buf());
         let user = AuthenticatedUser {
@@ -390,11 +7562,203 @@ mod tests {
         };
         let form = CreateFolderForm {
             name: "safe".to_string(),
+            exist_ok: true,
         };
 
         let err = service
             .create_folder(&user, Some("../outside"), &form)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::InvalidPath));
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn list_entries_rejects_symlink_escape() {
+        let dir = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        let hub_root = dir.path().join("3");
+        fs::create_dir_all(&hub_root).unwrap();
+        std::os::unix::fs::symlink(outside.path(), hub_root.join("escape")).unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 3,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let err = service
+            .list_entries(
+                &user,
+                Some("escape"),
+                &EntryFilter::All,
+                ListingSort::Default,
+                false,
+            )
+            .await
             .unwrap_err();
         assert!(matches!(err, ServiceError::InvalidPath));
     }
+
+    #[test]
+    fn repair_thumbnails_drops_orphans_and_refreshes_stale_entries() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("34");
+        fs::create_dir_all(hub_root.join(".thumbnails/gone")).unwrap();
+        fs::create_dir_all(hub_root.join("photos")).unwrap();
+        fs::create_dir_all(hub_root.join(".thumbnails/photos")).unwrap();
+        image::RgbImage::new(600, 300)
+            .save_with_format(hub_root.join("photos/stale.png"), image::ImageFormat::Png)
+            .unwrap();
+        fs::write(hub_root.join("photos/current.png"), b"source").unwrap();
+        fs::write(hub_root.join(".thumbnails/photos/stale.png"), b"old").unwrap();
+        fs::write(hub_root.join(".thumbnails/photos/current.png"), b"thumb").unwrap();
+        fs::write(hub_root.join(".thumbnails/gone/orphan.png"), b"thumb").unwrap();
+
+        let earlier = SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(hub_root.join(".thumbnails/photos/stale.png"))
+            .unwrap()
+            .set_modified(earlier)
+            .unwrap();
+        fs::File::options()
+            .write(true)
+            .open(hub_root.join("photos/current.png"))
+            .unwrap()
+            .set_modified(earlier)
+            .unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let admin = AuthenticatedUser {
+            sub: "admin".into(),
+            email: "admin@example.com".into(),
+            hub_id: 1,
+            name: "Admin".into(),
+            roles: vec![SERVICE_ADMIN_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let report = service.repair_thumbnails(&admin, HubId::new(34)).unwrap();
+        assert_eq!(
+            report,
+            ThumbnailRepairDto {
+                deleted: 1,
+                regenerated: 1,
+            }
+        );
+        assert!(!hub_root.join(".thumbnails/gone").exists());
+        let refreshed =
+            image::image_dimensions(hub_root.join(".thumbnails/photos/stale.png")).unwrap();
+        assert_eq!(refreshed, (256, 128));
+        assert_eq!(
+            fs::read(hub_root.join(".thumbnails/photos/current.png")).unwrap(),
+            b"thumb"
+        );
+
+        let mut member = admin;
+        member.roles = vec![SERVICE_ACCESS_ROLE.to_string()];
+        assert!(matches!(
+            service.repair_thumbnails(&member, HubId::new(34)),
+            Err(ServiceError::Unauthorized)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn symlinks_to_system_folders_are_refused() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("3");
+        fs::create_dir_all(&hub_root).unwrap();
+        std::os::unix::fs::symlink("/etc", hub_root.join("etc")).unwrap();
+        std::os::unix::fs::symlink("/etc/passwd", hub_root.join("passwd")).unwrap();
+
+        let service = build_service(dir.path().to_path_buf());
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 3,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+
+        let listing = service
+            .list_entries(
+                &user,
+                Some("etc"),
+                &EntryFilter::All,
+                ListingSort::Default,
+                false,
+            )
+            .await;
+        assert!(matches!(listing, Err(ServiceError::InvalidPath)));
+        assert!(matches!(
+            service.download_file(&user, Some("etc"), "passwd"),
+            Err(ServiceError::InvalidPath)
+        ));
+        assert!(matches!(
+            service.download_file(&user, None, "passwd"),
+            Err(ServiceError::InvalidPath)
+        ));
+    }
+
+    #[test]
+    fn moved_entries_keep_thumbnails_and_download_counts() {
+        let dir = tempdir().unwrap();
+        let hub_root = dir.path().join("43");
+        fs::create_dir_all(hub_root.join("inbox/trip")).unwrap();
+        fs::create_dir_all(hub_root.join("archive")).unwrap();
+        fs::create_dir_all(hub_root.join(".thumbnails/inbox/trip")).unwrap();
+        fs::write(hub_root.join("inbox/cover.png"), b"png").unwrap();
+        fs::write(hub_root.join("inbox/trip/beach.png"), b"png").unwrap();
+        fs::write(hub_root.join(".thumbnails/inbox/cover.png"), b"thumb").unwrap();
+        fs::write(hub_root.join(".thumbnails/inbox/trip/beach.png"), b"thumb").unwrap();
+
+        let service = build_service(dir.path().to_path_buf()).with_download_counts(
+            DownloadCounts::load(dir.path().join(".download_counts.json")),
+        );
+        let user = AuthenticatedUser {
+            sub: "user".into(),
+            email: "user@example.com".into(),
+            hub_id: 43,
+            name: "User".into(),
+            roles: vec![SERVICE_ACCESS_ROLE.to_string()],
+            exp: 0,
+        };
+        service.record_download(&user, Some("inbox"), "cover.png");
+        service.record_download(&user, Some("inbox/trip"), "beach.png");
+
+        service
+            .rename(&user, Some("inbox"), "cover.png", "front.png")
+            .unwrap();
+        service
+            .move_entry(&user, Some("inbox"), Some("archive"), "trip")
+            .unwrap();
+
+        assert!(hub_root.join(".thumbnails/inbox/front.png").is_file());
+        assert!(!hub_root.join(".thumbnails/inbox/cover.png").exists());
+        assert!(
+            hub_root
+                .join(".thumbnails/archive/trip/beach.png")
+                .is_file()
+        );
+        assert!(!hub_root.join(".thumbnails/inbox/trip").exists());
+
+        let counts = service.download_counts.as_ref().unwrap();
+        let storage = service.storage_for_hub(HubId::new(43));
+        let key = |folder: &str, name: &str| {
+            storage.file_key(
+                &RelativePath::try_from_str(folder).unwrap(),
+                &FileName::try_from_str(name).unwrap(),
+            )
+        };
+        assert_eq!(counts.get(&key("inbox", "front.png")), 1);
+        assert_eq!(counts.get(&key("inbox", "cover.png")), 0);
+        assert_eq!(counts.get(&key("archive/trip", "beach.png")), 1);
+    }
 }
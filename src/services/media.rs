@@ -0,0 +1,49 @@
+//! Container metadata for video files. Probing needs the `media-info`
+//! feature; without it every file is reported as unsupported.
+use std::path::Path;
+
+use crate::dto::MediaInfoDto;
+use crate::services::{ServiceError, ServiceResult};
+
+/// Read container, duration and track metadata from an ISO media file
+/// (MP4, MOV, M4V). Other containers are rejected as unsupported.
+#[cfg(feature = "media-info")]
+pub fn probe(path: &Path) -> ServiceResult<MediaInfoDto> {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use crate::dto::MediaTrackDto;
+
+    let file = File::open(path).map_err(ServiceError::ReadFile)?;
+    let size = file.metadata().map_err(ServiceError::ReadFile)?.len();
+    let reader = mp4::Mp4Reader::read_header(BufReader::new(file), size)
+        .map_err(|_| ServiceError::UnsupportedMedia)?;
+
+    let mut tracks: Vec<(u32, MediaTrackDto)> = reader
+        .tracks()
+        .iter()
+        .map(|(id, track)| {
+            let track_type = track.track_type().ok();
+            let is_video = matches!(track_type, Some(mp4::TrackType::Video));
+            let dto = MediaTrackDto {
+                track_type: track_type.map(|t| t.to_string()),
+                codec: track.media_type().ok().map(|m| m.to_string()),
+                width: is_video.then(|| track.width()),
+                height: is_video.then(|| track.height()),
+            };
+            (*id, dto)
+        })
+        .collect();
+    tracks.sort_by_key(|(id, _)| *id);
+
+    Ok(MediaInfoDto {
+        container: reader.major_brand().to_string(),
+        duration_secs: reader.duration().as_secs_f64(),
+        tracks: tracks.into_iter().map(|(_, track)| track).collect(),
+    })
+}
+
+#[cfg(not(feature = "media-info"))]
+pub fn probe(_path: &Path) -> ServiceResult<MediaInfoDto> {
+    Err(ServiceError::UnsupportedMedia)
+}
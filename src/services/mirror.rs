@@ -0,0 +1,172 @@
+//! Best-effort replication of storage mutations to a secondary root.
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+
+use crate::domain::{FileName, HubId, HubStorage, RelativePath, UploadRoot};
+use crate::services::files::copy_tree;
+
+/// Storage mutation replayed against the mirror root.
+#[derive(Clone, Debug)]
+pub enum MirrorOp {
+    /// Copy a file that was just written to primary storage.
+    WriteFile {
+        hub_id: HubId,
+        relative: RelativePath,
+        name: FileName,
+    },
+    /// Create a directory (and its parents).
+    CreateDir {
+        hub_id: HubId,
+        relative: RelativePath,
+    },
+    /// Rename a file or directory; both paths are relative to the hub root.
+    Rename {
+        hub_id: HubId,
+        from: RelativePath,
+        to: RelativePath,
+    },
+    /// Copy a file or directory that was just copied within primary storage;
+    /// the path is relative to the hub root.
+    Copy {
+        hub_id: HubId,
+        relative: RelativePath,
+    },
+    /// Remove a directory and everything below it.
+    DeleteDir {
+        hub_id: HubId,
+        relative: RelativePath,
+    },
+    /// Remove a file deleted from primary storage.
+    DeleteFile {
+        hub_id: HubId,
+        relative: RelativePath,
+        name: FileName,
+    },
+    /// Make the `staging` folder live as `target`, first moving an existing
+    /// `target` to `backup`. One operation, so the renames keep their order.
+    Publish {
+        hub_id: HubId,
+        staging: RelativePath,
+        target: RelativePath,
+        backup: Option<RelativePath>,
+    },
+}
+
+/// Replays successful writes from the primary upload root onto a mirror root.
+/// Reads never touch the mirror and failures are only logged.
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    primary: UploadRoot,
+    primary_hub_roots: HashMap<i32, PathBuf>,
+    mirror: UploadRoot,
+}
+
+impl Mirror {
+    pub fn new(primary: UploadRoot, mirror: UploadRoot) -> Self {
+        Self {
+            primary,
+            primary_hub_roots: HashMap::new(),
+            mirror,
+        }
+    }
+
+    /// Read the listed hubs from their own primary directories. The mirror
+    /// keeps every hub below its root.
+    pub fn with_primary_hub_roots(mut self, hub_roots: HashMap<i32, PathBuf>) -> Self {
+        self.primary_hub_roots = hub_roots;
+        self
+    }
+
+    fn source(&self, hub_id: &HubId) -> HubStorage {
+        match self.primary_hub_roots.get(&hub_id.value()) {
+            Some(hub_root) => HubStorage::with_hub_root(hub_root.clone(), hub_id.clone()),
+            None => HubStorage::new(self.primary.clone(), hub_id.clone()),
+        }
+    }
+
+    /// Replay `op` on a background thread so the caller's request is not
+    /// delayed or failed by the mirror.
+    pub fn replay(&self, op: MirrorOp) -> JoinHandle<()> {
+        let mirror = self.clone();
+        thread::spawn(move || {
+            if let Err(err) = mirror.apply(&op) {
+                log::warn!("Failed to mirror {op:?}: {err}");
+            }
+        })
+    }
+
+    fn apply(&self, op: &MirrorOp) -> std::io::Result<()> {
+        match op {
+            MirrorOp::WriteFile {
+                hub_id,
+                relative,
+                name,
+            } => {
+                let source = self.source(hub_id);
+                let target = HubStorage::new(self.mirror.clone(), hub_id.clone());
+                fs::create_dir_all(target.resolve_dir(relative))?;
+                fs::copy(
+                    source.resolve_file(relative, name),
+                    target.resolve_file(relative, name),
+                )
+                .map(|_| ())
+            }
+            MirrorOp::CreateDir { hub_id, relative } => {
+                let target = HubStorage::new(self.mirror.clone(), hub_id.clone());
+                fs::create_dir_all(target.resolve_dir(relative))
+            }
+            MirrorOp::Rename { hub_id, from, to } => {
+                let target = HubStorage::new(self.mirror.clone(), hub_id.clone());
+                let destination = target.resolve_dir(to);
+                if let Some(parent) = destination.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::rename(target.resolve_dir(from), destination)
+            }
+            MirrorOp::Copy { hub_id, relative } => {
+                let source = self.source(hub_id);
+                let target = HubStorage::new(self.mirror.clone(), hub_id.clone());
+                copy_tree(
+                    &source.resolve_dir(relative),
+                    &target.resolve_dir(relative),
+                    usize::MAX,
+                )
+            }
+            MirrorOp::DeleteDir { hub_id, relative } => {
+                let target = HubStorage::new(self.mirror.clone(), hub_id.clone());
+                match fs::remove_dir_all(target.resolve_dir(relative)) {
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                    result => result,
+                }
+            }
+            MirrorOp::DeleteFile {
+                hub_id,
+                relative,
+                name,
+            } => {
+                let target = HubStorage::new(self.mirror.clone(), hub_id.clone());
+                match fs::remove_file(target.resolve_file(relative, name)) {
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                    result => result,
+                }
+            }
+            MirrorOp::Publish {
+                hub_id,
+                staging,
+                target: live,
+                backup,
+            } => {
+                let target = HubStorage::new(self.mirror.clone(), hub_id.clone());
+                if let Some(backup) = backup {
+                    match fs::rename(target.resolve_dir(live), target.resolve_dir(backup)) {
+                        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                        result => result?,
+                    }
+                }
+                fs::rename(target.resolve_dir(staging), target.resolve_dir(live))
+            }
+        }
+    }
+}
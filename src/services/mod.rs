@@ -1,5 +1,14 @@
 //! Application services orchestrating domain logic and side effects.
+pub mod artifacts;
+pub mod audit;
+pub mod download_counts;
 pub mod files;
+pub mod media;
+pub mod mirror;
+pub mod quota;
+pub mod rate_limit;
+pub mod storage;
+pub mod watch;
 
 /// Convenience alias for service results.
 pub type ServiceResult<T> = Result<T, ServiceError>;
@@ -15,12 +24,56 @@ pub enum ServiceError {
     InvalidPath,
     #[error("invalid file name")]
     InvalidFileName,
+    #[error("file already exists")]
+    FileExists,
+    #[error("entry not found")]
+    NotFound,
+    #[error("folder is not empty")]
+    FolderNotEmpty,
+    #[error("file exceeds the upload limit of {limit} bytes")]
+    FileTooLarge { limit: u64 },
+    #[error("folder {folder} exceeds its quota of {limit} bytes")]
+    QuotaExceeded { folder: String, limit: u64 },
+    #[error("file extension is not allowed")]
+    DisallowedExtension,
+    #[error("file extension is not in the allowlist")]
+    ForbiddenExtension,
+    #[error("file is larger than the {limit} bytes shown as text")]
+    TooLargeToView { limit: u64 },
+    #[error("file is not text")]
+    NotText,
+    #[error("unsupported media file")]
+    UnsupportedMedia,
+    #[error("image aspect ratio exceeds the allowed maximum")]
+    AspectRatioExceeded,
+    #[error("file content does not match its extension")]
+    ContentTypeMismatch,
+    #[error("download quota exceeded for this period")]
+    DownloadQuotaExceeded,
+    #[error("too many concurrent watchers for this hub")]
+    TooManyWatchers,
+    #[error("too many uploads in a short time")]
+    TooManyUploads,
+    #[error("failed to watch folder")]
+    Watch(#[source] notify::Error),
     #[error("failed to prepare storage")]
     StorageSetup(#[source] std::io::Error),
     #[error("failed to list entries")]
     ListEntries(#[source] std::io::Error),
     #[error("failed to create folder")]
     CreateFolder(#[source] std::io::Error),
+    #[error("failed to read file")]
+    ReadFile(#[source] std::io::Error),
     #[error("failed to save file")]
     SaveFile(#[source] std::io::Error),
+    #[error("failed to delete entry")]
+    DeleteEntry(#[source] std::io::Error),
+    #[error("failed to move entry")]
+    MoveEntry(#[source] std::io::Error),
+    #[error("copy would overwrite {}", .0.join(", "))]
+    CopyConflict(Vec<String>),
+    #[error("failed to copy entry")]
+    CopyEntry(#[source] std::io::Error),
+    #[error("failed to build archive")]
+    BuildArchive(#[source] zip::result::ZipError),
 }
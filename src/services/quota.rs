@@ -0,0 +1,234 @@
+//! Per-hub download bandwidth accounting.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::domain::HubId;
+use crate::services::{ServiceError, ServiceResult};
+
+/// Default length of a download quota period (30 days).
+pub const DEFAULT_DOWNLOAD_QUOTA_PERIOD_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Bytes downloaded by a hub since `period_start`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+struct PeriodUsage {
+    /// Start of the current period in seconds since the Unix epoch.
+    period_start: u64,
+    bytes: u64,
+}
+
+/// Tracks downloaded bytes per hub and refuses downloads once a hub used up
+/// its quota for the current period. Counters are persisted to a JSON
+/// sidecar so restarts do not reset them; hubs whose period elapsed are
+/// dropped, so only hubs downloading in the current period are kept.
+#[derive(Clone, Debug)]
+pub struct DownloadQuota {
+    limit: u64,
+    period: Duration,
+    sidecar: PathBuf,
+    shared: bool,
+    usage: Arc<Mutex<HashMap<HubId, PeriodUsage>>>,
+}
+
+impl DownloadQuota {
+    /// Create the tracker, restoring counters from `sidecar` when present.
+    pub fn load(limit: u64, period: Duration, sidecar: PathBuf) -> Self {
+        let usage = read_sidecar(&sidecar).unwrap_or_default();
+
+        Self {
+            limit,
+            period,
+            sidecar,
+            shared: false,
+            usage: Arc::new(Mutex::new(usage)),
+        }
+    }
+
+    /// Re-read the sidecar before every check and update, for instances
+    /// sharing an upload root. Updates are read-modify-write, so instances
+    /// still need external coordination not to lose usage.
+    pub fn with_shared_storage(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Fail with [`ServiceError::DownloadQuotaExceeded`] when the hub has no
+    /// download budget left in the current period.
+    pub fn check(&self, hub_id: &HubId) -> ServiceResult<()> {
+        self.check_at(hub_id, SystemTime::now())
+    }
+
+    /// Add `bytes` to the hub's usage for the current period.
+    pub fn record(&self, hub_id: &HubId, bytes: u64) {
+        self.record_at(hub_id, bytes, SystemTime::now());
+    }
+
+    /// Check and record in one step: fail like [`DownloadQuota::check`] when
+    /// the budget is used up, otherwise add `bytes`. Concurrent downloads
+    /// cannot all pass the check before any of them is recorded.
+    pub fn try_record(&self, hub_id: &HubId, bytes: u64) -> ServiceResult<()> {
+        self.try_record_at(hub_id, bytes, SystemTime::now())
+    }
+
+    fn check_at(&self, hub_id: &HubId, now: SystemTime) -> ServiceResult<()> {
+        let mut usage = self.lock();
+        let current = self.current(&mut usage, hub_id, now);
+        if current.bytes >= self.limit {
+            return Err(ServiceError::DownloadQuotaExceeded);
+        }
+        Ok(())
+    }
+
+    fn record_at(&self, hub_id: &HubId, bytes: u64, now: SystemTime) {
+        let mut usage = self.lock();
+        let current = self.current(&mut usage, hub_id, now);
+        current.bytes = current.bytes.saturating_add(bytes);
+        self.persist(&usage);
+    }
+
+    fn try_record_at(&self, hub_id: &HubId, bytes: u64, now: SystemTime) -> ServiceResult<()> {
+        let mut usage = self.lock();
+        let current = self.current(&mut usage, hub_id, now);
+        if current.bytes >= self.limit {
+            return Err(ServiceError::DownloadQuotaExceeded);
+        }
+        current.bytes = current.bytes.saturating_add(bytes);
+        self.persist(&usage);
+        Ok(())
+    }
+
+    /// Lock the usage, refreshed from the sidecar in shared mode.
+    fn lock(&self) -> MutexGuard<'_, HashMap<HubId, PeriodUsage>> {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        if self.shared {
+            if let Some(stored) = read_sidecar(&self.sidecar) {
+                *usage = stored;
+            }
+        }
+        usage
+    }
+
+    /// Usage entry for the period containing `now`. Hubs whose period has
+    /// elapsed are dropped first, so an entry missing from the map is a
+    /// fresh period.
+    fn current<'a>(
+        &self,
+        usage: &'a mut HashMap<HubId, PeriodUsage>,
+        hub_id: &HubId,
+        now: SystemTime,
+    ) -> &'a mut PeriodUsage {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let period = self.period.as_secs();
+        usage.retain(|_, usage| now < usage.period_start.saturating_add(period));
+        usage.entry(hub_id.clone()).or_insert(PeriodUsage {
+            period_start: now,
+            bytes: 0,
+        })
+    }
+
+    fn persist(&self, usage: &HashMap<HubId, PeriodUsage>) {
+        let snapshot: HashMap<i32, PeriodUsage> = usage
+            .iter()
+            .map(|(hub_id, usage)| (hub_id.value(), *usage))
+            .collect();
+        let result = serde_json::to_vec(&snapshot)
+            .map_err(std::io::Error::other)
+            .and_then(|data| fs::write(&self.sidecar, data));
+        if let Err(err) = result {
+            log::warn!(
+                "Failed to persist download usage to {:?}: {err}",
+                self.sidecar
+            );
+        }
+    }
+}
+
+/// Usage stored in `sidecar`, if it exists and is readable.
+fn read_sidecar(sidecar: &Path) -> Option<HashMap<HubId, PeriodUsage>> {
+    let data = fs::read(sidecar).ok()?;
+    match serde_json::from_slice::<HashMap<i32, PeriodUsage>>(&data) {
+        Ok(usage) => Some(
+            usage
+                .into_iter()
+                .filter_map(|(hub_id, usage)| Some((HubId::try_new(hub_id).ok()?, usage)))
+                .collect(),
+        ),
+        Err(err) => {
+            log::warn!("Ignoring unreadable download usage {sidecar:?}: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downloads_blocked_after_quota_and_resume_after_reset() {
+        let dir = tempfile::tempdir().unwrap();
+        let sidecar = dir.path().join(".download_usage.json");
+        let quota = DownloadQuota::load(100, Duration::from_secs(60), sidecar.clone());
+        let hub = HubId::new(4);
+        let start = UNIX_EPOCH + Duration::from_secs(1_000);
+
+        quota.check_at(&hub, start).unwrap();
+        quota.record_at(&hub, 100, start);
+        assert!(matches!(
+            quota.check_at(&hub, start + Duration::from_secs(10)),
+            Err(ServiceError::DownloadQuotaExceeded)
+        ));
+        quota.check_at(&HubId::new(5), start).unwrap();
+
+        let restored = DownloadQuota::load(100, Duration::from_secs(60), sidecar);
+        assert!(
+            restored
+                .check_at(&hub, start + Duration::from_secs(10))
+                .is_err()
+        );
+        restored
+            .check_at(&hub, start + Duration::from_secs(61))
+            .unwrap();
+    }
+
+    #[test]
+    fn try_record_refuses_once_the_budget_is_used_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let sidecar = dir.path().join(".download_usage.json");
+        let quota = DownloadQuota::load(100, Duration::from_secs(60), sidecar);
+        let hub = HubId::new(4);
+        let start = UNIX_EPOCH + Duration::from_secs(1_000);
+
+        quota.try_record_at(&hub, 60, start).unwrap();
+        quota.try_record_at(&hub, 60, start).unwrap();
+        assert!(matches!(
+            quota.try_record_at(&hub, 1, start),
+            Err(ServiceError::DownloadQuotaExceeded)
+        ));
+        quota
+            .try_record_at(&hub, 1, start + Duration::from_secs(61))
+            .unwrap();
+    }
+
+    #[test]
+    fn hubs_with_an_elapsed_period_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let sidecar = dir.path().join(".download_usage.json");
+        let quota = DownloadQuota::load(100, Duration::from_secs(60), sidecar.clone());
+        let start = UNIX_EPOCH + Duration::from_secs(1_000);
+
+        quota.record_at(&HubId::new(4), 10, start);
+        quota.record_at(&HubId::new(5), 10, start + Duration::from_secs(61));
+
+        let stored: HashMap<i32, PeriodUsage> =
+            serde_json::from_slice(&fs::read(sidecar).unwrap()).unwrap();
+        assert_eq!(stored.keys().collect::<Vec<_>>(), [&5]);
+    }
+}
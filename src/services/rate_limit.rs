@@ -0,0 +1,81 @@
+//! In-memory per-user rate limiting of uploads.
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::services::{ServiceError, ServiceResult};
+
+/// Length of the sliding window uploads are counted in.
+pub const UPLOAD_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Caps how many uploads each user may start within [`UPLOAD_RATE_WINDOW`].
+/// Counters live in memory only, start over on restart and are kept per
+/// instance even in `shared_storage_mode`. Users without uploads in the
+/// window are swept on every check, so only active users are tracked.
+#[derive(Clone, Debug)]
+pub struct UploadRateLimiter {
+    max_per_window: usize,
+    window: Duration,
+    recent: Arc<Mutex<HashMap<String, VecDeque<Instant>>>>,
+}
+
+impl UploadRateLimiter {
+    pub fn new(max_per_minute: u32) -> Self {
+        Self {
+            max_per_window: max_per_minute as usize,
+            window: UPLOAD_RATE_WINDOW,
+            recent: Arc::default(),
+        }
+    }
+
+    /// Count an upload by `user` (their `sub`), failing with
+    /// [`ServiceError::TooManyUploads`] once the window is full. Refused
+    /// attempts are not counted.
+    pub fn check(&self, user: &str) -> ServiceResult<()> {
+        self.check_at(user, Instant::now())
+    }
+
+    fn check_at(&self, user: &str, now: Instant) -> ServiceResult<()> {
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        // Forget users whose uploads all left the window so the map stays small.
+        recent.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|time| now.saturating_duration_since(*time) >= self.window)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = recent.entry(user.to_string()).or_default();
+        if times.len() >= self.max_per_window {
+            return Err(ServiceError::TooManyUploads);
+        }
+        times.push_back(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uploads_beyond_the_limit_are_refused_until_the_window_moves() {
+        let limiter = UploadRateLimiter::new(3);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            limiter.check_at("alice", start).unwrap();
+        }
+        assert!(matches!(
+            limiter.check_at("alice", start + Duration::from_secs(1)),
+            Err(ServiceError::TooManyUploads)
+        ));
+        limiter.check_at("bob", start).unwrap();
+        limiter
+            .check_at("alice", start + UPLOAD_RATE_WINDOW)
+            .unwrap();
+    }
+}
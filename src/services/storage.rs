@@ -0,0 +1,275 @@
+//! Pluggable storage backends addressed by [`StorageKey`]s.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use tempfile::NamedTempFile;
+
+use crate::domain::{StorageKey, UploadRoot};
+
+/// Entry of a folder as reported by [`StorageBackend::list`].
+#[derive(Clone, Debug)]
+pub struct BackendEntry {
+    pub name: String,
+    pub is_directory: bool,
+    pub size: u64,
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+}
+
+/// Storage operations [`crate::services::files::FileService`] delegates to.
+/// Keys are validated and confined to the caller's hub before they reach the
+/// backend.
+pub trait StorageBackend: fmt::Debug + Send + Sync {
+    /// Entries directly inside the folder at `key`.
+    fn list(&self, key: &StorageKey) -> io::Result<Vec<BackendEntry>>;
+
+    /// Create the folder at `key` and any missing parents. Returns `false`
+    /// when the folder already existed and fails with
+    /// [`io::ErrorKind::AlreadyExists`] when a file occupies the key.
+    fn create_dir(&self, key: &StorageKey) -> io::Result<bool>;
+
+    /// Store `file` at `key`. Without `overwrite` an existing entry fails the
+    /// call with [`io::ErrorKind::AlreadyExists`].
+    fn persist(&self, file: NamedTempFile, key: &StorageKey, overwrite: bool) -> io::Result<()>;
+
+    /// Open the file at `key` for reading.
+    fn read(&self, key: &StorageKey) -> io::Result<Box<dyn Read + Send>>;
+
+    /// Remove the file at `key`.
+    fn delete(&self, key: &StorageKey) -> io::Result<()>;
+}
+
+/// Backend keeping hub files on the local disk below the upload root.
+#[derive(Clone, Debug)]
+pub struct LocalBackend {
+    root: UploadRoot,
+    hub_roots: HashMap<i32, PathBuf>,
+}
+
+impl LocalBackend {
+    pub fn new(root: UploadRoot) -> Self {
+        Self {
+            root,
+            hub_roots: HashMap::new(),
+        }
+    }
+
+    /// Keep the files of the listed hubs in the given directories instead of
+    /// below the upload root.
+    pub fn with_hub_roots(mut self, hub_roots: HashMap<i32, PathBuf>) -> Self {
+        self.hub_roots = hub_roots;
+        self
+    }
+
+    /// Disk path a key maps to.
+    fn path(&self, key: &StorageKey) -> PathBuf {
+        let mut segments = key.segments();
+        let hub = segments.next().unwrap_or_default();
+        let mut path = hub
+            .parse::<i32>()
+            .ok()
+            .and_then(|hub_id| self.hub_roots.get(&hub_id))
+            .cloned()
+            .unwrap_or_else(|| self.root.as_path().join(hub));
+        path.extend(segments);
+        path
+    }
+}
+
+impl StorageBackend for LocalBackend {
+    fn list(&self, key: &StorageKey) -> io::Result<Vec<BackendEntry>> {
+        let entries = fs::read_dir(self.path(key))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let metadata = entry.metadata().ok();
+                BackendEntry {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    is_directory: entry.file_type().is_ok_and(|ft| ft.is_dir()),
+                    size: metadata.as_ref().map_or(0, |m| m.len()),
+                    created: metadata.as_ref().and_then(|m| m.created().ok()),
+                    modified: metadata.as_ref().and_then(|m| m.modified().ok()),
+                }
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn create_dir(&self, key: &StorageKey) -> io::Result<bool> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::create_dir(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                if fs::metadata(&path).is_ok_and(|m| m.is_dir()) {
+                    Ok(false)
+                } else {
+                    Err(err)
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn persist(&self, file: NamedTempFile, key: &StorageKey, overwrite: bool) -> io::Result<()> {
+        persist_file(file, &self.path(key), overwrite)
+    }
+
+    fn read(&self, key: &StorageKey) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(self.path(key))?))
+    }
+
+    fn delete(&self, key: &StorageKey) -> io::Result<()> {
+        fs::remove_file(self.path(key))
+    }
+}
+
+/// Move `file` to `path` by renaming it. When the temp dir sits on another
+/// mount than `path` (e.g. a tmpfs `/tmp`) the rename fails with
+/// [`io::ErrorKind::CrossesDevices`] and the file is copied instead.
+pub(crate) fn persist_file(file: NamedTempFile, path: &Path, overwrite: bool) -> io::Result<()> {
+    let stored = if overwrite {
+        file.persist(path)
+    } else {
+        file.persist_noclobber(path)
+    };
+    match stored {
+        Ok(_) => Ok(()),
+        Err(err) if err.error.kind() == io::ErrorKind::CrossesDevices => {
+            copy_into_place(err.file, path, overwrite)
+        }
+        Err(err) => Err(err.error),
+    }
+}
+
+/// Copy `file` into a temp file next to `path` and rename that into place, so
+/// readers never see a partly written file, then remove `file`.
+fn copy_into_place(file: NamedTempFile, path: &Path, overwrite: bool) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut staged = NamedTempFile::new_in(dir)?;
+    io::copy(&mut fs::File::open(file.path())?, staged.as_file_mut())?;
+    staged.as_file().sync_all()?;
+    let stored = if overwrite {
+        staged.persist(path)
+    } else {
+        staged.persist_noclobber(path)
+    };
+    stored.map_err(|err| err.error)?;
+
+    if let Err(err) = file.close() {
+        log::warn!("Failed to remove temp file after copying it to {path:?}: {err}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::domain::{FileName, HubId, HubStorage, RelativePath};
+
+    fn temp_file(content: &[u8]) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(content).unwrap();
+        file
+    }
+
+    #[test]
+    fn local_backend_round_trips_files_by_key() {
+        let dir = tempdir().unwrap();
+        let root = UploadRoot::from(dir.path().to_path_buf());
+        let backend = LocalBackend::new(root.clone());
+        let storage = HubStorage::new(root, HubId::new(7));
+        let docs = RelativePath::try_from_str("docs").unwrap();
+        let name = FileName::try_from_str("a.txt").unwrap();
+        let key = storage.file_key(&docs, &name);
+
+        assert!(backend.create_dir(&storage.dir_key(&docs)).unwrap());
+        assert!(!backend.create_dir(&storage.dir_key(&docs)).unwrap());
+        backend.persist(temp_file(b"one"), &key, false).unwrap();
+        assert!(dir.path().join("7/docs/a.txt").is_file());
+
+        let err = backend.persist(temp_file(b"two"), &key, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        backend.persist(temp_file(b"two"), &key, true).unwrap();
+
+        let mut content = String::new();
+        backend
+            .read(&key)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "two");
+
+        let listed = backend.list(&storage.dir_key(&docs)).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "a.txt");
+        assert!(!listed[0].is_directory);
+        assert_eq!(listed[0].size, 3);
+
+        let err = backend.create_dir(&key).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        backend.delete(&key).unwrap();
+        assert!(backend.list(&storage.dir_key(&docs)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn local_backend_maps_overridden_hubs_to_their_root() {
+        let dir = tempdir().unwrap();
+        let mount = dir.path().join("mount");
+        let root = UploadRoot::from(dir.path().join("upload"));
+        let backend =
+            LocalBackend::new(root.clone()).with_hub_roots(HashMap::from([(7, mount.clone())]));
+        let name = FileName::try_from_str("a.txt").unwrap();
+
+        let moved = HubStorage::with_hub_root(mount.clone(), HubId::new(7));
+        assert!(
+            backend
+                .create_dir(&moved.dir_key(&RelativePath::root()))
+                .unwrap()
+        );
+        backend
+            .persist(
+                temp_file(b"one"),
+                &moved.file_key(&RelativePath::root(), &name),
+                false,
+            )
+            .unwrap();
+        assert!(mount.join("a.txt").is_file());
+
+        let regular = HubStorage::new(root, HubId::new(8));
+        assert!(
+            backend
+                .create_dir(&regular.dir_key(&RelativePath::root()))
+                .unwrap()
+        );
+        assert!(dir.path().join("upload/8").is_dir());
+    }
+
+    #[test]
+    fn copy_into_place_stores_and_removes_the_source() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("a.txt");
+        let source = temp_file(b"one");
+        let source_path = source.path().to_path_buf();
+
+        copy_into_place(source, &target, false).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"one");
+        assert!(!source_path.exists());
+
+        let err = copy_into_place(temp_file(b"two"), &target, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        copy_into_place(temp_file(b"two"), &target, true).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"two");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,124 @@
+//! Bookkeeping for long-poll directory watchers.
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::domain::HubId;
+use crate::services::{ServiceError, ServiceResult};
+
+/// Default number of concurrent watchers allowed per hub.
+pub const DEFAULT_MAX_WATCHERS_PER_HUB: usize = 8;
+
+/// Caps how many file system watchers each hub may hold open at once.
+#[derive(Clone, Debug)]
+pub struct WatchLimiter {
+    max_per_hub: usize,
+    active: Arc<Mutex<HashMap<HubId, usize>>>,
+}
+
+impl WatchLimiter {
+    pub fn new(max_per_hub: usize) -> Self {
+        Self {
+            max_per_hub,
+            active: Arc::default(),
+        }
+    }
+
+    /// Reserve a watcher slot for `hub_id`. The slot is released when the
+    /// returned guard is dropped.
+    pub fn acquire(&self, hub_id: &HubId) -> ServiceResult<WatchSlot> {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        let count = active.entry(hub_id.clone()).or_default();
+        if *count >= self.max_per_hub {
+            return Err(ServiceError::TooManyWatchers);
+        }
+        *count += 1;
+        Ok(WatchSlot {
+            hub_id: hub_id.clone(),
+            active: Arc::clone(&self.active),
+        })
+    }
+}
+
+impl Default for WatchLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_WATCHERS_PER_HUB)
+    }
+}
+
+/// A held watcher slot; see [`WatchLimiter::acquire`].
+#[derive(Debug)]
+pub struct WatchSlot {
+    hub_id: HubId,
+    active: Arc<Mutex<HashMap<HubId, usize>>>,
+}
+
+impl Drop for WatchSlot {
+    fn drop(&mut self) {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = active.get_mut(&self.hub_id) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                active.remove(&self.hub_id);
+            }
+        }
+    }
+}
+
+/// An authorized folder watch holding its hub's slot until it is dropped.
+#[derive(Debug)]
+pub struct PendingWatch {
+    dir: PathBuf,
+    _slot: WatchSlot,
+}
+
+impl PendingWatch {
+    pub fn new(dir: PathBuf, slot: WatchSlot) -> Self {
+        Self { dir, _slot: slot }
+    }
+
+    /// Block until the folder changes or `timeout` elapses. Returns whether a
+    /// change was observed; pure access events are ignored.
+    pub fn wait(self, timeout: Duration) -> ServiceResult<bool> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(ServiceError::Watch)?;
+        watcher
+            .watch(&self.dir, RecursiveMode::NonRecursive)
+            .map_err(ServiceError::Watch)?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(remaining) {
+                Ok(Ok(event)) if !matches!(event.kind, EventKind::Access(_)) => return Ok(true),
+                Ok(Ok(_)) => continue,
+                Ok(Err(err)) => return Err(ServiceError::Watch(err)),
+                Err(_) => return Ok(false),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limiter_bounds_and_releases_slots() {
+        let limiter = WatchLimiter::new(1);
+        let hub = HubId::new(3);
+
+        let slot = limiter.acquire(&hub).unwrap();
+        assert!(matches!(
+            limiter.acquire(&hub),
+            Err(ServiceError::TooManyWatchers)
+        ));
+        limiter.acquire(&HubId::new(4)).unwrap();
+
+        drop(slot);
+        limiter.acquire(&hub).unwrap();
+    }
+}